@@ -0,0 +1,149 @@
+use crate::util::time_util::DateTime;
+use http::Request;
+use http::StatusCode;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Common,
+    Combined,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "common" => Ok(Format::Common),
+            "combined" => Ok(Format::Combined),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "Unknown access log format '{}', expected common, combined or json",
+                s
+            )),
+        }
+    }
+}
+
+pub struct Entry<'a> {
+    pub req: &'a Request<()>,
+    pub peer_addr: Option<SocketAddr>,
+    pub status: StatusCode,
+    pub bytes: u64,
+    pub duration: Duration,
+}
+
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    time: String,
+    remote_addr: Option<String>,
+    method: &'a str,
+    path: &'a str,
+    query: Option<&'a str>,
+    protocol: String,
+    status: u16,
+    bytes: u64,
+    duration_ms: f64,
+    referer: Option<&'a str>,
+    user_agent: Option<&'a str>,
+}
+
+// Written to its own target so traffic can be collected separately from build output
+pub struct AccessLog {
+    format: Format,
+    is_stdout: bool,
+    target: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    pub fn new(format: Format, path: Option<&Path>) -> Result<Self, io::Error> {
+        let target: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(io::stdout()),
+        };
+
+        Ok(Self {
+            format,
+            is_stdout: path.is_none(),
+            target: Mutex::new(target),
+        })
+    }
+
+    // The plain request log is redundant when the access log goes to stdout
+    pub fn is_stdout(&self) -> bool {
+        self.is_stdout
+    }
+
+    pub fn log(&self, entry: &Entry) {
+        let line = match self.format {
+            Format::Common => common_line(entry),
+            Format::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                common_line(entry),
+                header(entry.req, "Referer").unwrap_or("-"),
+                header(entry.req, "User-Agent").unwrap_or("-")
+            ),
+            Format::Json => json_line(entry),
+        };
+
+        if let Ok(mut target) = self.target.lock() {
+            if let Err(err) = writeln!(target, "{}", line).and_then(|_| target.flush()) {
+                eprintln!("Warning: Failed to write access log: {}", err);
+            }
+        }
+    }
+}
+
+fn common_line(entry: &Entry) -> String {
+    let req = entry.req;
+
+    format!(
+        "{} - - [{}] \"{} {} {:?}\" {} {}",
+        entry
+            .peer_addr
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|| "-".to_string()),
+        DateTime::from_system_time(SystemTime::now()).to_clf_date(),
+        req.method(),
+        req.uri(),
+        req.version(),
+        entry.status.as_u16(),
+        entry.bytes
+    )
+}
+
+fn json_line(entry: &Entry) -> String {
+    let req = entry.req;
+
+    let json_entry = JsonEntry {
+        time: DateTime::from_system_time(SystemTime::now()).to_iso_string(),
+        remote_addr: entry.peer_addr.map(|addr| addr.ip().to_string()),
+        method: req.method().as_str(),
+        path: req.uri().path(),
+        query: req.uri().query(),
+        protocol: format!("{:?}", req.version()),
+        status: entry.status.as_u16(),
+        bytes: entry.bytes,
+        duration_ms: entry.duration.as_secs_f64() * 1000.0,
+        referer: header(req, "Referer"),
+        user_agent: header(req, "User-Agent"),
+    };
+
+    serde_json::to_string(&json_entry).unwrap_or_default()
+}
+
+fn header<'a>(req: &'a Request<()>, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
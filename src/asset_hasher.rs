@@ -1,81 +1,668 @@
-use crate::util::file_util;
+use crate::poly_config::HashAlgorithm;
+use crate::poly_config::PolyConfig;
+use crate::poly_config::PreloadConfig;
+use crate::poly_config::SourceMaps;
+use crate::poly_config::Versioning;
+use crate::util::compress;
 use crate::ProjectInfo;
+use globset::Glob;
+use globset::GlobSet;
+use globset::GlobSetBuilder;
 use regex::Regex;
+use serde::Serialize;
 use sha2::Digest;
 use sha2::Sha256;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::ops::Deref;
 use std::path;
+use std::path::Path;
 use std::path::PathBuf;
 use walkdir::WalkDir;
+use xxhash_rust::xxh64::xxh64;
+
+// Rewriting stops after this many passes when documents reference each other in a cycle
+const MAX_REWRITE_PASSES: usize = 5;
+
+// Query param that keeps a reference in dist documents unhashed, e.g. `/img/logo.png?nohash`
+const NO_HASH_PARAM: &str = "nohash";
+
+// Where the hash goes in [assets] filename_pattern
+const HASH_PLACEHOLDER: &str = "[hash]";
+
+// Comments that keep references on a line of a dist document unhashed, e.g. `// poly:nohash-next-line`
+const NO_HASH_LINE_MARKER: &str = "poly:nohash-line";
+const NO_HASH_NEXT_LINE_MARKER: &str = "poly:nohash-next-line";
+
+// Formats that are already compressed, compressing them again only costs build time
+pub const COMPRESSED_EXTENSIONS: [&str; 16] = [
+    "avif", "br", "gif", "gz", "ico", "jpeg", "jpg", "mp3", "mp4", "ogg", "png", "webm", "webp",
+    "woff", "woff2", "zip",
+];
+
+// References in dist documents, `url(...)` in css, url attributes of html tags that load assets
+// and string literals in js
+const CSS_URL_PATTERN: &str = r#"url\(\s*(['"]?)([^'")]+)(['"]?)\s*\)"#;
+const HTML_TAG_PATTERN: &str = r"(?i)<(img|link|script|source|video|audio|image|use)\b[^>]*>";
+const HTML_URL_ATTRIBUTE_PATTERN: &str =
+    r#"(?i)(\s(?:src|href|poster|srcset)\s*=\s*)("[^"]*"|'[^']*')"#;
+const JS_STRING_PATTERN: &str = r#""([^"\s\\]+)"|'([^'\s\\]+)'|`([^`\s\\$]+)`"#;
+
+// `//# sourceMappingURL=app.js.map` in js and `/*# sourceMappingURL=app.css.map */` in css
+const SOURCE_MAP_PATTERN: &str =
+    r"(//[#@]\s*sourceMappingURL=|/\*[#@]\s*sourceMappingURL=)([^\s*]+)";
+
+// Preload Link headers are appended to this file in dist
+const HEADERS_FILE_NAME: &str = "_headers";
+
+// Written to the dist dir after hashing, maps each asset uri to its hashed url and integrity digest
+const MANIFEST_FILE_NAME: &str = "asset-manifest.json";
 
 pub struct Config {
-    pub core_project_path_src: PathBuf,
-    pub web_project_path_src: PathBuf,
-    pub web_project_path_css: PathBuf,
     pub dist_path: PathBuf,
+    pub project_name: String,
+    pub hash_length: usize,
+    pub hash_algorithm: HashAlgorithm,
+    pub versioning: Versioning,
+    pub filename_pattern: String,
+    pub version_param: String,
+    pub integrity: bool,
+    pub precompress: bool,
+    pub exclude: Vec<String>,
+    pub preload: PreloadConfig,
+    pub sourcemaps: SourceMaps,
+    // Renamed assets are copied instead, for consumers that need the stable names
+    pub keep_original: bool,
+    // References to missing dist assets fail hashing instead of printing warnings
+    pub strict: bool,
 }
 
 impl Config {
-    pub fn from_project_info(project_info: &ProjectInfo) -> Self {
+    pub fn from_project_info(project_info: &ProjectInfo, poly_config: &PolyConfig) -> Self {
         Self {
-            core_project_path_src: project_info.core_project_path_src(),
-            web_project_path_src: project_info.web_project_path_src(),
-            web_project_path_css: project_info.web_project_path_css(),
             dist_path: project_info.dist_path.clone(),
+            project_name: project_info.project_name.clone(),
+            hash_length: poly_config.assets.hash_length,
+            hash_algorithm: poly_config.assets.hash_algorithm,
+            versioning: poly_config.assets.versioning,
+            filename_pattern: poly_config.assets.filename_pattern.clone(),
+            version_param: poly_config.assets.version_param.clone(),
+            integrity: poly_config.assets.integrity,
+            precompress: poly_config.assets.precompress,
+            exclude: poly_config.assets.exclude.clone(),
+            preload: poly_config.assets.preload.clone(),
+            sourcemaps: poly_config.assets.sourcemaps,
+            keep_original: false,
+            strict: false,
         }
     }
 }
 
 pub struct AssetHasher {
     config: Config,
+    // Binary references that can't be replaced are only reported once, not on every rewrite pass
+    reported_references: RefCell<HashSet<String>>,
 }
 
 #[derive(Debug)]
 pub enum Error {
     ReadFile(io::Error),
     OpenAssetFile(io::Error),
-    HashAssetFile(io::Error),
     WriteSourceFile(io::Error),
+    WriteManifest(io::Error),
+    WritePreloadHints(io::Error),
+    RemoveSourceMap(io::Error),
+    RenameAsset(io::Error),
+    Compress(io::Error),
     StripPathPrefix(path::StripPrefixError),
     Regex(regex::Error),
+    ExcludePattern(String, globset::Error),
+    FilenamePattern(String),
+    DanglingReferences(Vec<DanglingReference>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadFile(err) => write!(f, "Failed to read dist file: {}", err),
+            Error::OpenAssetFile(err) => write!(f, "Failed to open asset: {}", err),
+            Error::WriteSourceFile(err) => write!(f, "Failed to write dist file: {}", err),
+            Error::WriteManifest(err) => write!(f, "Failed to write asset manifest: {}", err),
+            Error::WritePreloadHints(err) => write!(f, "Failed to write preload hints: {}", err),
+            Error::RemoveSourceMap(err) => write!(f, "Failed to remove source map: {}", err),
+            Error::RenameAsset(err) => write!(f, "Failed to rename asset: {}", err),
+            Error::Compress(err) => write!(f, "Failed to precompress asset: {}", err),
+            Error::StripPathPrefix(err) => write!(f, "Failed to get asset uri: {}", err),
+            Error::Regex(err) => write!(f, "Invalid asset pattern: {}", err),
+
+            Error::ExcludePattern(pattern, err) => {
+                write!(
+                    f,
+                    "Invalid exclude pattern '{}' in [assets]: {}",
+                    pattern, err
+                )
+            }
+
+            Error::FilenamePattern(pattern) => write!(
+                f,
+                "filename_pattern '{}' in [assets] must contain [hash] once",
+                pattern
+            ),
+
+            Error::DanglingReferences(references) => {
+                writeln!(f, "{} references to missing dist assets:", references.len())?;
+
+                for reference in references {
+                    writeln!(f, "  {}", reference)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
 }
 
 impl AssetHasher {
     pub fn new(config: Config) -> AssetHasher {
-        AssetHasher { config }
+        AssetHasher {
+            config,
+            reported_references: RefCell::new(HashSet::new()),
+        }
     }
 
-    pub fn collect_hashed_dist_assets(&self) -> Result<Vec<HashedAsset>, Error> {
+    // Urls in the dist css, html, js and wasm are rewritten before those files are hashed, so
+    // their hashes cover the final content. Documents can reference each other, like js
+    // chunks, so rewriting is repeated until the hashes settle
+    fn collect_hashed_dist_assets(&self) -> Result<Vec<HashedAsset>, Error> {
         let dist_assets = self.collect_dist_assets()?;
 
-        dist_assets
+        // Source maps aren't hashed on their own, they follow the file they belong to
+        let (document_assets, other_assets): (Vec<Asset>, Vec<Asset>) = dist_assets
+            .iter()
+            .filter(|asset| !asset.is_source_map_of_any(&dist_assets))
+            .cloned()
+            .partition(Asset::is_document);
+
+        // Excluded documents still get their references rewritten, they're just not referenced by hash
+        let exclude = self.exclude_matcher()?;
+        let is_included = |asset: &HashedAsset| !exclude.is_match(&asset.uri);
+
+        let hashed_assets = other_assets
             .into_iter()
+            .filter(|asset| !exclude.is_match(&asset.uri))
             .map(|asset| self.hash_asset(asset))
-            .collect::<Result<Vec<HashedAsset>, Error>>()
+            .collect::<Result<Vec<HashedAsset>, Error>>()?;
+
+        let hash_documents = || {
+            document_assets
+                .iter()
+                .map(|asset| self.hash_asset(asset.clone()))
+                .collect::<Result<Vec<HashedAsset>, Error>>()
+                .map(|assets| assets.into_iter().filter(is_included).collect::<Vec<_>>())
+        };
+
+        let mut hashed_documents = hash_documents()?;
+
+        for pass in 1..=MAX_REWRITE_PASSES {
+            let all_assets = [hashed_assets.clone(), hashed_documents.clone()].concat();
+            let mut changed = false;
+
+            for asset in &document_assets {
+                changed |= self.replace_checksum_in_document(asset, &all_assets)?;
+            }
+
+            if !changed {
+                break;
+            }
+
+            hashed_documents = hash_documents()?;
+
+            if pass == MAX_REWRITE_PASSES {
+                eprintln!("Warning: Hashes of dist documents didn't settle, they may reference each other in a cycle");
+            }
+        }
+
+        Ok([hashed_assets, hashed_documents].concat())
+    }
+
+    fn exclude_matcher(&self) -> Result<GlobSet, Error> {
+        let mut builder = GlobSetBuilder::new();
+
+        for pattern in &self.config.exclude {
+            let glob =
+                Glob::new(pattern).map_err(|err| Error::ExcludePattern(pattern.clone(), err))?;
+            builder.add(glob);
+        }
+
+        builder
+            .build()
+            .map_err(|err| Error::ExcludePattern(self.config.exclude.join(", "), err))
+    }
+
+    // Hashes are resolved in dist only, so source files are left untouched and nothing has to be rebuilt
+    pub fn hash_dist_assets(&self) -> Result<(), Error> {
+        if self
+            .config
+            .filename_pattern
+            .matches(HASH_PLACEHOLDER)
+            .count()
+            != 1
+        {
+            return Err(Error::FilenamePattern(self.config.filename_pattern.clone()));
+        }
+
+        if self.config.sourcemaps == SourceMaps::Exclude {
+            self.remove_source_maps()?;
+        }
+
+        // Links are added with plain urls, hashing rewrites them like any other reference
+        let preload_uris = self.preload_uris();
+
+        if self.config.preload.links {
+            self.inject_preload_links(&preload_uris)?;
+        }
+
+        let assets = self.collect_hashed_dist_assets()?;
+        let renamed_uris = self.rename_hashed_assets(&assets)?;
+        let manifest = self.manifest(&assets, &renamed_uris);
+        self.write_manifest(&manifest)?;
+
+        if self.config.preload.headers {
+            self.write_preload_headers(&preload_uris, &manifest)?;
+        }
+
+        let dangling_references = self.find_dangling_references()?;
+
+        if self.config.strict && !dangling_references.is_empty() {
+            return Err(Error::DanglingReferences(dangling_references));
+        }
+
+        for reference in &dangling_references {
+            eprintln!("Warning: Reference to a missing dist asset, {}", reference);
+        }
+
+        if self.config.precompress {
+            self.precompress_dist_files()?;
+        }
+
+        Ok(())
+    }
+
+    // Runs after renaming, so the siblings have the hashed names
+    fn precompress_dist_files(&self) -> Result<(), Error> {
+        let paths = self
+            .collect_files(&self.config.dist_path)
+            .into_iter()
+            .filter(|path| {
+                let ext = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+                !COMPRESSED_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+            });
+
+        for path in paths {
+            let content = fs::read(&path).map_err(Error::ReadFile)?;
+            let gzip = compress::gzip(&content, 9).map_err(Error::Compress)?;
+            let brotli = compress::brotli(&content, 11).map_err(Error::Compress)?;
+
+            for (ext, compressed) in [("gz", gzip), ("br", brotli)] {
+                // Tiny files can grow, the host falls back to the original then
+                if compressed.len() >= content.len() {
+                    continue;
+                }
+
+                let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+                file_name.push(format!(".{}", ext));
+
+                fs::write(path.with_file_name(file_name), compressed).map_err(Error::Compress)?;
+            }
+        }
+
+        Ok(())
     }
 
-    pub fn replace_checksum_in_source_files(&self, assets: &Vec<HashedAsset>) -> Result<(), Error> {
-        let rust_files = self.collect_files_by_ext(&self.config.core_project_path_src, "rs");
-        let typescript_files = self.collect_files_by_ext(&self.config.web_project_path_src, "ts");
-        let css_files = self.collect_files_by_ext(&self.config.web_project_path_css, "css");
+    // Only assets referenced by their hashed name are renamed, entry points like index.html
+    // and files like robots.txt keep their names
+    fn rename_hashed_assets(&self, assets: &[HashedAsset]) -> Result<HashSet<String>, Error> {
+        let mut renamed_uris = HashSet::new();
+
+        if self.config.versioning != Versioning::Filename {
+            if self.config.keep_original {
+                eprintln!("Warning: Assets are only renamed with versioning = \"filename\" in [assets], there's no original to keep");
+            }
+
+            return Ok(renamed_uris);
+        }
+
+        let documents = assets
+            .iter()
+            .filter(|asset| asset.is_document())
+            .map(|asset| fs::read(&asset.path).map_err(Error::ReadFile))
+            .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+        for asset in assets {
+            // References can be relative, the part from the hash on is in all of them
+            let (_, suffix) = asset.hash_affixes(&self.config.filename_pattern);
+            let needle = format!("{}{}", asset.short_hash(), suffix);
+            let re = regex::bytes::Regex::new(&regex::escape(&needle)).map_err(Error::Regex)?;
+
+            if !documents.iter().any(|content| re.is_match(content)) {
+                continue;
+            }
+
+            let hashed_path = asset.path_with_hash(&self.config.dist_path);
+            let hashed_name = asset.uri_with_hash();
+
+            if let Some(dir) = hashed_path.parent() {
+                fs::create_dir_all(dir).map_err(Error::RenameAsset)?;
+            }
+
+            println!(
+                "Hash asset: {} '{}' -> '{}'",
+                if self.config.keep_original {
+                    "Copying"
+                } else {
+                    "Renaming"
+                },
+                asset.uri,
+                hashed_name
+            );
 
-        let files = [rust_files, typescript_files, css_files].concat();
+            self.move_asset(&asset.path, &hashed_path)?;
+            self.move_source_map(asset, &hashed_path)?;
 
-        for path in files {
-            self.replace_checksum_in_file(&path, &assets)?;
+            renamed_uris.insert(asset.uri.clone());
+        }
+
+        Ok(renamed_uris)
+    }
+
+    fn move_asset(&self, path: &Path, hashed_path: &Path) -> Result<(), Error> {
+        if self.config.keep_original {
+            fs::copy(path, hashed_path).map_err(Error::RenameAsset)?;
+        } else {
+            fs::rename(path, hashed_path).map_err(Error::RenameAsset)?;
         }
 
         Ok(())
     }
 
+    // The map gets the hashed name of its file with `.map` appended, and its `file` field is updated
+    fn move_source_map(&self, asset: &HashedAsset, hashed_path: &Path) -> Result<(), Error> {
+        let map_path = source_map_path(&asset.path);
+
+        if !map_path.is_file() {
+            return Ok(());
+        }
+
+        let hashed_map_path = source_map_path(hashed_path);
+        self.move_asset(&map_path, &hashed_map_path)?;
+
+        let content = fs::read_to_string(&hashed_map_path).map_err(Error::ReadFile)?;
+
+        if let Ok(serde_json::Value::Object(mut map)) = serde_json::from_str(&content) {
+            if map.contains_key("file") {
+                let file_name = hashed_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                map.insert("file".into(), file_name.into());
+
+                let json = serde_json::to_string(&map).unwrap_or(content);
+                fs::write(&hashed_map_path, json).map_err(Error::RenameAsset)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Maps are removed along with the links to them, so browsers don't request missing files
+    fn remove_source_maps(&self) -> Result<(), Error> {
+        let re = Regex::new(&format!(
+            r"(?m)^[ \t]*{}[ \t]*(\*/)?[ \t]*\r?\n?",
+            SOURCE_MAP_PATTERN
+        ))
+        .map_err(Error::Regex)?;
+
+        for path in self.collect_files(&self.config.dist_path) {
+            match path.extension().and_then(OsStr::to_str) {
+                Some("map") => fs::remove_file(&path).map_err(Error::RemoveSourceMap)?,
+
+                Some("js" | "mjs" | "css") => {
+                    let content = fs::read_to_string(&path).map_err(Error::ReadFile)?;
+                    let new_content = re.replace_all(&content, "");
+
+                    if new_content != content {
+                        fs::write(&path, new_content.as_bytes()).map_err(Error::RemoveSourceMap)?;
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn manifest(
+        &self,
+        assets: &[HashedAsset],
+        renamed_uris: &HashSet<String>,
+    ) -> BTreeMap<String, ManifestEntry> {
+        assets
+            .iter()
+            .map(|asset| {
+                let url = match asset.versioning {
+                    Versioning::Query => format!("{}?{}", asset.uri, asset.version_query()),
+                    Versioning::Filename if renamed_uris.contains(&asset.uri) => {
+                        asset.uri_with_hash()
+                    }
+                    Versioning::Filename => asset.uri.clone(),
+                };
+
+                let entry = ManifestEntry {
+                    url,
+                    hash: asset.short_hash(),
+                    integrity: asset.integrity.clone(),
+                };
+
+                (asset.uri.clone(), entry)
+            })
+            .collect()
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.config.dist_path.join(MANIFEST_FILE_NAME)
+    }
+
+    fn write_manifest(&self, manifest: &BTreeMap<String, ManifestEntry>) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(manifest).unwrap_or_default();
+        fs::write(self.manifest_path(), json).map_err(Error::WriteManifest)
+    }
+
+    // The wasm is only discovered when the glue runs, preloading fetches it in parallel
+    fn preload_uris(&self) -> Vec<String> {
+        let wasm_uris = [
+            format!("/wasm/{}_bg.wasm", self.config.project_name),
+            format!("/wasm/{}.js", self.config.project_name),
+        ];
+
+        wasm_uris
+            .into_iter()
+            .chain(self.config.preload.assets.iter().cloned())
+            .filter(|uri| {
+                self.config
+                    .dist_path
+                    .join(uri.trim_start_matches('/'))
+                    .is_file()
+            })
+            .collect()
+    }
+
+    fn inject_preload_links(&self, uris: &[String]) -> Result<(), Error> {
+        let html_paths = self
+            .collect_files(&self.config.dist_path)
+            .into_iter()
+            .filter(|path| path.extension() == Some(OsStr::new("html")));
+
+        for path in html_paths {
+            let content = fs::read_to_string(&path).map_err(Error::ReadFile)?;
+
+            let head_end = match content.to_lowercase().find("</head>") {
+                Some(index) => index,
+                None => continue,
+            };
+
+            let links = uris
+                .iter()
+                .filter(|uri| !content.contains(&format!("href=\"{}\"", uri)))
+                .map(|uri| format!("<link href=\"{}\" {}>\n", uri, preload_link_attributes(uri)))
+                .collect::<String>();
+
+            if links.is_empty() {
+                continue;
+            }
+
+            let new_content = format!("{}{}{}", &content[..head_end], links, &content[head_end..]);
+            fs::write(&path, new_content).map_err(Error::WritePreloadHints)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_preload_headers(
+        &self,
+        uris: &[String],
+        manifest: &BTreeMap<String, ManifestEntry>,
+    ) -> Result<(), Error> {
+        let path = self.config.dist_path.join(HEADERS_FILE_NAME);
+        let mut content = fs::read_to_string(&path).unwrap_or_default();
+
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+
+        content.push_str("/*\n");
+
+        for uri in uris {
+            let url = manifest
+                .get(uri)
+                .map(|entry| entry.url.as_str())
+                .unwrap_or(uri);
+
+            content.push_str(&format!(
+                "  Link: <{}>; {}\n",
+                url,
+                preload_header_params(uri)
+            ));
+        }
+
+        fs::write(&path, content).map_err(Error::WritePreloadHints)
+    }
+
+    // Runs after renaming, so references are checked against the final dist files. Only urls with a
+    // file extension are checked, others are likely app routes
+    fn find_dangling_references(&self) -> Result<Vec<DanglingReference>, Error> {
+        let css_url_re = Regex::new(CSS_URL_PATTERN).map_err(Error::Regex)?;
+        let html_tag_re = Regex::new(HTML_TAG_PATTERN).map_err(Error::Regex)?;
+        let html_attr_re = Regex::new(HTML_URL_ATTRIBUTE_PATTERN).map_err(Error::Regex)?;
+        let js_string_re = Regex::new(JS_STRING_PATTERN).map_err(Error::Regex)?;
+
+        let mut dangling_references = Vec::new();
+
+        for asset in self.collect_dist_assets()? {
+            let urls: Vec<String> = match asset.extension() {
+                "css" => {
+                    let content = fs::read_to_string(&asset.path).map_err(Error::ReadFile)?;
+                    css_url_re
+                        .captures_iter(&content)
+                        .map(|caps| caps[2].trim().to_string())
+                        .collect()
+                }
+
+                "html" => {
+                    let content = fs::read_to_string(&asset.path).map_err(Error::ReadFile)?;
+                    html_tag_re
+                        .find_iter(&content)
+                        .flat_map(|tag| {
+                            html_attr_re
+                                .captures_iter(tag.as_str())
+                                .flat_map(|attr| {
+                                    let quoted = &attr[2];
+                                    let value = &quoted[1..quoted.len() - 1];
+
+                                    if attr[1].trim().to_lowercase().starts_with("srcset") {
+                                        srcset_urls(value)
+                                    } else {
+                                        vec![value.trim().to_string()]
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .collect()
+                }
+
+                // Only literals that look like paths, other strings with a dot are too common in js
+                "js" | "mjs" => {
+                    let content = fs::read_to_string(&asset.path).map_err(Error::ReadFile)?;
+                    js_string_re
+                        .find_iter(&content)
+                        .map(|literal| {
+                            let literal = literal.as_str();
+                            literal[1..literal.len() - 1].to_string()
+                        })
+                        .filter(|value| {
+                            value.starts_with('/')
+                                || value.starts_with("./")
+                                || value.starts_with("../")
+                        })
+                        .collect()
+                }
+
+                _ => Vec::new(),
+            };
+
+            let dir_uri = asset.uri.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+
+            for url in urls {
+                let path = url.split(['?', '#']).next().unwrap_or_default();
+                let has_extension = path
+                    .rsplit('/')
+                    .next()
+                    .map(|name| name.contains('.'))
+                    .unwrap_or(false);
+
+                let uri = match resolve_uri(dir_uri, path) {
+                    Some(uri) if has_extension => uri,
+                    _ => continue,
+                };
+
+                if !self
+                    .config
+                    .dist_path
+                    .join(uri.trim_start_matches('/'))
+                    .is_file()
+                {
+                    dangling_references.push(DanglingReference {
+                        url,
+                        file_uri: asset.uri.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(dangling_references)
+    }
+
     fn collect_dist_assets(&self) -> Result<Vec<Asset>, Error> {
         let dist_files = self.collect_files(&self.config.dist_path);
 
         dist_files
             .into_iter()
+            .filter(|path| path != &self.config.dist_path.join(MANIFEST_FILE_NAME))
             .map(|path| {
                 let uri = self.get_dist_uri(&self.config.dist_path, &path)?;
                 Ok(Asset { path, uri })
@@ -91,26 +678,6 @@ impl AssetHasher {
         Ok(format!("/{}", rel_path.to_string_lossy().to_string()))
     }
 
-    fn collect_files_by_ext(&self, path: &PathBuf, extension: &str) -> Vec<PathBuf> {
-        WalkDir::new(path)
-            .into_iter()
-            .filter_map(|entry| {
-                match entry {
-                    Ok(entry) => {
-                        //fmt
-                        Some(entry.path().to_path_buf())
-                    }
-
-                    Err(err) => {
-                        eprintln!("Warning: Can't access file: {}", err);
-                        None
-                    }
-                }
-            })
-            .filter(|path| path.extension() == Some(OsStr::new(extension)))
-            .collect()
-    }
-
     fn collect_files(&self, path: &PathBuf) -> Vec<PathBuf> {
         WalkDir::new(path)
             .into_iter()
@@ -132,70 +699,243 @@ impl AssetHasher {
     }
 
     fn hash_asset(&self, asset: Asset) -> Result<HashedAsset, Error> {
-        let mut hasher = Sha256::new();
-        let mut file = fs::File::open(&asset.path).map_err(Error::OpenAssetFile)?;
-        io::copy(&mut file, &mut hasher).map_err(Error::HashAssetFile)?;
-        let digest = hasher.finalize();
-        let pattern = format!(r"{}\?hash=(?<hash>[a-zA-Z0-9]+)", asset.uri);
-        let re = Regex::new(&pattern).map_err(Error::Regex)?;
+        let content = fs::read(&asset.path).map_err(Error::OpenAssetFile)?;
+
+        // The source map url contains the hash of the file itself, so it's left out of the hash
+        let digest = if ["js", "mjs", "css"].contains(&asset.extension()) {
+            let re = regex::bytes::Regex::new(SOURCE_MAP_PATTERN).map_err(Error::Regex)?;
+            self.digest(&re.replace_all(&content, &b"$1"[..]))
+        } else {
+            self.digest(&content)
+        };
+
+        let pattern = match self.config.versioning {
+            Versioning::Query => format!(
+                r"{}\?{}=(?<hash>[a-zA-Z0-9]+)",
+                regex::escape(&asset.uri),
+                regex::escape(&self.config.version_param)
+            ),
+
+            Versioning::Filename => {
+                let (prefix, suffix) = asset.hash_affixes(&self.config.filename_pattern);
+                format!(
+                    r"{}(?<hash>[0-9a-f]+){}",
+                    regex::escape(&prefix),
+                    regex::escape(&suffix)
+                )
+            }
+        };
+        let re = regex::bytes::Regex::new(&pattern).map_err(Error::Regex)?;
 
         let hashed_asset = HashedAsset {
             asset,
             hash: data_encoding::HEXLOWER.encode(&digest),
+            hash_length: self.config.hash_length,
+            versioning: self.config.versioning,
+            filename_pattern: self.config.filename_pattern.clone(),
+            version_param: self.config.version_param.clone(),
+            integrity: format!("sha256-{}", base64::encode(Sha256::digest(&content))),
             re,
         };
 
         Ok(hashed_asset)
     }
 
-    fn replace_checksum_in_file(
+    fn digest(&self, content: &[u8]) -> Vec<u8> {
+        match self.config.hash_algorithm {
+            HashAlgorithm::Sha256 => Sha256::digest(content).to_vec(),
+            HashAlgorithm::Blake3 => blake3::hash(content).as_bytes().to_vec(),
+            HashAlgorithm::Xxhash => xxh64(content, 0).to_be_bytes().to_vec(),
+        }
+    }
+
+    fn replace_checksum_in_document(
         &self,
-        file_path: &PathBuf,
-        assets: &Vec<HashedAsset>,
-    ) -> Result<(), Error> {
-        let old_file = file_util::read(&file_path).map_err(Error::ReadFile)?;
-        let mut file_was_changed = false;
-
-        let new_content = old_file
-            .content
-            .lines()
-            .map(|line| {
-                assets
-                    .iter()
-                    .fold(line.to_string(), |modified_line, asset| {
-                        let new_hash = asset.short_hash();
-
-                        match asset.extract_hash(&modified_line) {
-                            Some(old_hash) if new_hash != old_hash => {
-                                println!(
-                                    "Hash asset [{}]: Replacing hash '{}' -> '{}' in file '{}'",
-                                    asset.uri,
-                                    old_hash,
-                                    asset.short_hash(),
-                                    file_path.file_name().unwrap().to_string_lossy(),
-                                );
-
-                                file_was_changed = true;
-                                modified_line.replace(&old_hash, &asset.short_hash())
-                            }
-
-                            _ => modified_line,
-                        }
-                    })
+        asset: &Asset,
+        assets: &[HashedAsset],
+    ) -> Result<bool, Error> {
+        match asset.extension() {
+            "css" => self.replace_checksum_in_css_urls(asset, assets),
+            "html" => self.replace_checksum_in_html_attributes(asset, assets),
+            "wasm" => self.replace_checksum_in_binary(asset, assets),
+            _ => self.replace_checksum_in_js_strings(asset, assets),
+        }
+    }
+
+    // Sets the version param on `url(...)` references to other dist assets
+    fn replace_checksum_in_css_urls(
+        &self,
+        css_asset: &Asset,
+        assets: &[HashedAsset],
+    ) -> Result<bool, Error> {
+        let re = Regex::new(CSS_URL_PATTERN).map_err(Error::Regex)?;
+        let source_map_re = Regex::new(SOURCE_MAP_PATTERN).map_err(Error::Regex)?;
+
+        self.rewrite_file(css_asset, |content, dir_uri| {
+            let content = re.replace_all(content, |caps: &regex::Captures| {
+                match hashed_url(dir_uri, caps[2].trim(), assets) {
+                    Some(url) => format!("url({}{}{})", &caps[1], url, &caps[3]),
+                    None => caps[0].to_string(),
+                }
+            });
+
+            rewrite_source_map_urls(&content, dir_uri, assets, &source_map_re)
+        })
+    }
+
+    // Only url attributes of tags that load assets are touched, text and other attributes are kept
+    fn replace_checksum_in_html_attributes(
+        &self,
+        html_asset: &Asset,
+        assets: &[HashedAsset],
+    ) -> Result<bool, Error> {
+        let tag_re = Regex::new(HTML_TAG_PATTERN).map_err(Error::Regex)?;
+        let attr_re = Regex::new(HTML_URL_ATTRIBUTE_PATTERN).map_err(Error::Regex)?;
+        let integrity_re =
+            Regex::new(r#"(?i)\s(integrity|crossorigin)(?:\s*=\s*(?:"[^"]*"|'[^']*'|[^\s>]+))?"#)
+                .map_err(Error::Regex)?;
+
+        self.rewrite_file(html_asset, |content, dir_uri| {
+            tag_re
+                .replace_all(content, |tag: &regex::Captures| {
+                    let new_tag = attr_re
+                        .replace_all(&tag[0], |attr: &regex::Captures| {
+                            let quoted = &attr[2];
+                            let quote = &quoted[..1];
+                            let value = &quoted[1..quoted.len() - 1];
+
+                            let new_value = if attr[1].trim().to_lowercase().starts_with("srcset") {
+                                rewrite_srcset(dir_uri, value, assets)
+                            } else {
+                                hashed_url(dir_uri, value.trim(), assets)
+                                    .unwrap_or_else(|| value.to_string())
+                            };
+
+                            format!("{}{}{}{}", &attr[1], quote, new_value, quote)
+                        })
+                        .into_owned();
+
+                    let loads_subresource =
+                        ["script", "link"].contains(&tag[1].to_lowercase().as_str());
+
+                    if self.config.integrity && loads_subresource {
+                        with_integrity(&new_tag, dir_uri, assets, &attr_re, &integrity_re)
+                    } else {
+                        new_tag
+                    }
+                })
+                .into_owned()
+        })
+    }
+
+    // String literals that resolve to a dist asset, like dynamic imports or `new URL("x.wasm", import.meta.url)`
+    fn replace_checksum_in_js_strings(
+        &self,
+        js_asset: &Asset,
+        assets: &[HashedAsset],
+    ) -> Result<bool, Error> {
+        let re = Regex::new(JS_STRING_PATTERN).map_err(Error::Regex)?;
+
+        let source_map_re = Regex::new(SOURCE_MAP_PATTERN).map_err(Error::Regex)?;
+
+        self.rewrite_file(js_asset, |content, dir_uri| {
+            let content = re.replace_all(content, |caps: &regex::Captures| {
+                let literal = &caps[0];
+                let quote = &literal[..1];
+                let value = &literal[1..literal.len() - 1];
+
+                match hashed_url(dir_uri, value, assets) {
+                    Some(url) => format!("{}{}{}", quote, url, quote),
+                    None => literal.to_string(),
+                }
+            });
+
+            rewrite_source_map_urls(&content, dir_uri, assets, &source_map_re)
+        })
+    }
+
+    // Relative urls are resolved from the dir of the rewritten file
+    fn rewrite_file<F>(&self, asset: &Asset, rewrite: F) -> Result<bool, Error>
+    where
+        F: Fn(&str, &str) -> String,
+    {
+        let content = fs::read_to_string(&asset.path).map_err(Error::ReadFile)?;
+        let dir_uri = asset.uri.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+
+        let new_content = split_marked_lines(&content)
+            .into_iter()
+            .map(|(marked, lines)| {
+                if marked {
+                    lines.to_string()
+                } else {
+                    rewrite(lines, dir_uri)
+                }
             })
-            .collect::<Vec<_>>()
-            .join("\n");
+            .collect::<String>();
 
-        if file_was_changed {
-            let new_file = file_util::FileData {
-                content: new_content,
-                permissions: old_file.permissions,
-            };
+        if new_content != content {
+            println!(
+                "Hash asset: Rewriting urls in file '{}'",
+                asset.path.file_name().unwrap().to_string_lossy()
+            );
 
-            file_util::write(&file_path, new_file).map_err(Error::WriteSourceFile)?;
+            fs::write(&asset.path, new_content.as_bytes()).map_err(Error::WriteSourceFile)?;
         }
 
-        Ok(())
+        Ok(new_content != content)
+    }
+
+    // Rust strings like `/app.css?hash=abc1234` end up in the wasm data section. Hashes have a fixed
+    // length, so they can be replaced in place without breaking the binary
+    fn replace_checksum_in_binary(
+        &self,
+        binary_asset: &Asset,
+        assets: &[HashedAsset],
+    ) -> Result<bool, Error> {
+        let content = fs::read(&binary_asset.path).map_err(Error::ReadFile)?;
+        let file_name = binary_asset.path.file_name().unwrap().to_string_lossy();
+
+        let new_content = assets.iter().fold(content.clone(), |content, asset| {
+            let new_hash = asset.short_hash();
+
+            asset
+                .re
+                .replace_all(&content, |caps: &regex::bytes::Captures| {
+                    let old_hash = String::from_utf8_lossy(&caps["hash"]).to_string();
+
+                    if old_hash == new_hash {
+                        return caps[0].to_vec();
+                    }
+
+                    let reference = String::from_utf8_lossy(&caps[0]).to_string();
+
+                    if old_hash.len() != new_hash.len() {
+                        if self.reported_references.borrow_mut().insert(reference) {
+                            eprintln!(
+                            "Warning: Can't replace hash '{}' of {} in '{}', it doesn't have the configured length of {}",
+                            old_hash, asset.uri, file_name, new_hash.len()
+                        );
+                        }
+
+                        return caps[0].to_vec();
+                    }
+
+                    println!(
+                        "Hash asset [{}]: Replacing hash '{}' -> '{}' in file '{}'",
+                        asset.uri, old_hash, new_hash, file_name,
+                    );
+
+                    let hash_start = caps.name("hash").unwrap().start() - caps.get(0).unwrap().start();
+                    [&caps[0][..hash_start], new_hash.as_bytes()].concat()
+                })
+                .into_owned()
+        });
+
+        if new_content != content {
+            fs::write(&binary_asset.path, &new_content).map_err(Error::WriteSourceFile)?;
+        }
+
+        Ok(new_content != content)
     }
 }
 
@@ -209,17 +949,398 @@ pub struct Asset {
 pub struct HashedAsset {
     asset: Asset,
     hash: String,
-    re: Regex,
+    hash_length: usize,
+    versioning: Versioning,
+    filename_pattern: String,
+    version_param: String,
+    // Subresource integrity value, always sha256 of the file content regardless of the hash algorithm
+    integrity: String,
+    re: regex::bytes::Regex,
+}
+
+#[derive(Debug)]
+pub struct DanglingReference {
+    url: String,
+    file_uri: String,
+}
+
+impl fmt::Display for DanglingReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "'{}' in {}", self.url, self.file_uri)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    url: String,
+    hash: String,
+    integrity: String,
+}
+
+impl Asset {
+    // Files that reference other assets
+    fn is_document(&self) -> bool {
+        ["css", "html", "js", "mjs", "wasm"].contains(&self.extension())
+    }
+
+    // `app.js.map` when `app.js` is in dist as well
+    fn is_source_map_of_any(&self, assets: &[Asset]) -> bool {
+        self.uri
+            .strip_suffix(".map")
+            .map(|uri| assets.iter().any(|asset| asset.uri == uri))
+            .unwrap_or(false)
+    }
+
+    fn extension(&self) -> &str {
+        self.path
+            .extension()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default()
+    }
+
+    // The uri around the hash in its hashed form, `/js/app.` and `.js` for `/js/app.<hash>.js`
+    // with the pattern `[dir]/[name].[hash].[ext]`
+    fn hash_affixes(&self, filename_pattern: &str) -> (String, String) {
+        let (dir, file_name) = self.uri.rsplit_once('/').unwrap_or(("", &self.uri));
+        let (name, ext) = match file_name.rsplit_once('.') {
+            Some((name, ext)) if !name.is_empty() => (name, ext),
+            _ => (file_name, ""),
+        };
+
+        // Separators next to empty parts are dropped, like `[dir]/` in the dist root
+        let expanded = if ext.is_empty() {
+            filename_pattern.replace(".[ext]", "").replace("[ext]", "")
+        } else {
+            filename_pattern.replace("[ext]", ext)
+        };
+
+        let expanded = expanded
+            .replace("[dir]", dir.trim_start_matches('/'))
+            .replace("[name]", name)
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let (prefix, suffix) = expanded
+            .split_once(HASH_PLACEHOLDER)
+            .unwrap_or((&expanded, ""));
+
+        (format!("/{}", prefix), suffix.to_string())
+    }
 }
 
 impl HashedAsset {
+    // Longer lengths than the digest are capped, xxhash only has 16 hex chars
     fn short_hash(&self) -> String {
-        self.hash[..7].to_string()
+        self.hash[..self.hash_length.min(self.hash.len())].to_string()
+    }
+
+    fn version_query(&self) -> String {
+        format!("{}={}", self.version_param, self.short_hash())
+    }
+
+    fn uri_with_hash(&self) -> String {
+        let (prefix, suffix) = self.hash_affixes(&self.filename_pattern);
+        format!("{}{}{}", prefix, self.short_hash(), suffix)
+    }
+
+    pub fn path_with_hash(&self, dist_path: &Path) -> PathBuf {
+        dist_path.join(self.uri_with_hash().trim_start_matches('/'))
+    }
+
+    // References are already renamed after the first rewrite pass, they're matched by any hash of the right length
+    fn matches_hashed_uri(&self, uri: &str) -> bool {
+        let (prefix, suffix) = self.hash_affixes(&self.filename_pattern);
+
+        self.versioning == Versioning::Filename
+            && uri.len() == prefix.len() + self.short_hash().len() + suffix.len()
+            && uri.starts_with(&prefix)
+            && uri.ends_with(&suffix)
+            && uri[prefix.len()..uri.len() - suffix.len()]
+                .chars()
+                .all(|c| c.is_ascii_hexdigit())
+    }
+}
+
+// Splits content into runs of lines, marked runs are opted out of hashing with a marker comment
+fn split_marked_lines(content: &str) -> Vec<(bool, &str)> {
+    let mut runs: Vec<(bool, &str)> = Vec::new();
+    let mut run_start = 0;
+    let mut run_marked = false;
+    let mut next_line_marked = false;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let marked = next_line_marked || line.contains(NO_HASH_LINE_MARKER);
+        next_line_marked = line.contains(NO_HASH_NEXT_LINE_MARKER);
+
+        if marked != run_marked && offset > run_start {
+            runs.push((run_marked, &content[run_start..offset]));
+            run_start = offset;
+        }
+
+        run_marked = marked;
+        offset += line.len();
+    }
+
+    if offset > run_start {
+        runs.push((run_marked, &content[run_start..offset]));
+    }
+
+    runs
+}
+
+// Scripts are preloaded as modules, everything else with the destination the browser would fetch it for
+fn preload_kind(uri: &str) -> (&'static str, Option<&'static str>, bool) {
+    let ext = uri.rsplit('.').next().unwrap_or_default();
+
+    match ext {
+        "js" | "mjs" => ("modulepreload", None, false),
+        "css" => ("preload", Some("style"), false),
+        "wasm" => ("preload", Some("fetch"), true),
+        "woff" | "woff2" | "ttf" | "otf" => ("preload", Some("font"), true),
+        _ => ("preload", Some("image"), false),
+    }
+}
+
+fn preload_link_attributes(uri: &str) -> String {
+    let (rel, destination, crossorigin) = preload_kind(uri);
+    let mut attributes = format!("rel=\"{}\"", rel);
+
+    if let Some(destination) = destination {
+        attributes.push_str(&format!(" as=\"{}\"", destination));
+    }
+
+    if crossorigin {
+        attributes.push_str(" crossorigin");
+    }
+
+    attributes
+}
+
+fn preload_header_params(uri: &str) -> String {
+    let (rel, destination, crossorigin) = preload_kind(uri);
+    let mut params = format!("rel={}", rel);
+
+    if let Some(destination) = destination {
+        params.push_str(&format!("; as={}", destination));
     }
 
-    fn extract_hash(&self, s: &str) -> Option<String> {
-        self.re.captures(s).map(|groups| groups["hash"].to_string())
+    if crossorigin {
+        params.push_str("; crossorigin");
     }
+
+    params
+}
+
+// Source map urls point to the versioned file with `.map` appended
+fn rewrite_source_map_urls(
+    content: &str,
+    base_dir_uri: &str,
+    assets: &[HashedAsset],
+    source_map_re: &Regex,
+) -> String {
+    source_map_re
+        .replace_all(content, |caps: &regex::Captures| {
+            let url = &caps[2];
+            let path = url.split('?').next().unwrap_or_default();
+
+            let new_url = path.strip_suffix(".map").and_then(|file_path| {
+                let uri = resolve_uri(base_dir_uri, file_path)?;
+                let asset = find_asset(assets, &uri)?;
+
+                match asset.versioning {
+                    Versioning::Query => Some(format!("{}?{}", path, asset.version_query())),
+                    Versioning::Filename => {
+                        let file_url = hashed_url(base_dir_uri, file_path, assets)?;
+                        Some(format!("{}.map", file_url))
+                    }
+                }
+            });
+
+            match new_url {
+                Some(new_url) => format!("{}{}", &caps[1], new_url),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn source_map_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".map");
+    path.with_file_name(file_name)
+}
+
+// Exact matches win, `/app.min.css` is its own asset and not a hashed `/app.css`
+fn find_asset<'a>(assets: &'a [HashedAsset], uri: &str) -> Option<&'a HashedAsset> {
+    assets
+        .iter()
+        .find(|asset| asset.uri == uri)
+        .or_else(|| assets.iter().find(|asset| asset.matches_hashed_uri(uri)))
+}
+
+// Returns the hashed url when it points to a dist asset, other query params are kept.
+// External and data urls, and urls opting out with `?nohash`, are left alone
+fn hashed_url(base_dir_uri: &str, url: &str, assets: &[HashedAsset]) -> Option<String> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+
+    if query.split('&').any(|param| param == NO_HASH_PARAM) {
+        return None;
+    }
+
+    let uri = resolve_uri(base_dir_uri, path)?;
+    let asset = find_asset(assets, &uri)?;
+
+    if asset.versioning == Versioning::Filename {
+        let hashed_uri = asset.uri_with_hash();
+        let (hashed_dir, hashed_name) = hashed_uri.rsplit_once('/').unwrap_or(("", &hashed_uri));
+        let uri_dir = uri.rsplit_once('/').map(|(dir, _)| dir).unwrap_or_default();
+
+        // The url keeps its form when the file stays in its dir. Otherwise it's absolute, the
+        // referencing document may be moved as well
+        let new_path = if hashed_dir == uri_dir {
+            match path.rsplit_once('/') {
+                Some((dir, _)) => format!("{}/{}", dir, hashed_name),
+                None => hashed_name.to_string(),
+            }
+        } else {
+            hashed_uri.clone()
+        };
+
+        let query = if query.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", query)
+        };
+
+        return Some(format!("{}{}", new_path, query));
+    }
+
+    let query = query
+        .split('&')
+        .filter(|param| {
+            !param.is_empty() && param.split('=').next() != Some(asset.version_param.as_str())
+        })
+        .chain([asset.version_query().as_str()])
+        .collect::<Vec<_>>()
+        .join("&");
+
+    Some(format!("{}?{}", path, query))
+}
+
+// Sets the integrity attribute of a tag that loads a dist asset. crossorigin is added when missing,
+// browsers only check integrity of cross-origin assets, like ones served from a cdn, in cors mode
+fn with_integrity(
+    tag: &str,
+    base_dir_uri: &str,
+    assets: &[HashedAsset],
+    attr_re: &Regex,
+    integrity_re: &Regex,
+) -> String {
+    let asset = attr_re.captures_iter(tag).find_map(|attr| {
+        let quoted = &attr[2];
+        let value = &quoted[1..quoted.len() - 1];
+        let path = value.trim().split('?').next().unwrap_or_default();
+        let uri = resolve_uri(base_dir_uri, path)?;
+
+        find_asset(assets, &uri)
+    });
+
+    let asset = match asset {
+        Some(asset) => asset,
+        None => return tag.to_string(),
+    };
+
+    let has_crossorigin = integrity_re
+        .captures_iter(tag)
+        .any(|attr| attr[1].eq_ignore_ascii_case("crossorigin"));
+
+    let tag = integrity_re.replace_all(tag, |attr: &regex::Captures| {
+        if attr[1].eq_ignore_ascii_case("integrity") {
+            String::new()
+        } else {
+            attr[0].to_string()
+        }
+    });
+
+    let (start, end) = match tag.strip_suffix("/>") {
+        Some(start) => (start.trim_end(), " />"),
+        None => (tag.trim_end_matches('>').trim_end(), ">"),
+    };
+
+    let crossorigin = if has_crossorigin {
+        ""
+    } else {
+        r#" crossorigin="anonymous""#
+    };
+
+    format!(
+        r#"{} integrity="{}"{}{}"#,
+        start, asset.integrity, crossorigin, end
+    )
+}
+
+// Candidates are separated by commas, each is a url optionally followed by a descriptor like `2x`
+fn rewrite_srcset(base_dir_uri: &str, srcset: &str, assets: &[HashedAsset]) -> String {
+    srcset
+        .split(',')
+        .map(|candidate| {
+            let candidate = candidate.trim();
+            let (url, descriptor) = candidate
+                .split_once(char::is_whitespace)
+                .unwrap_or((candidate, ""));
+
+            let url = hashed_url(base_dir_uri, url, assets).unwrap_or_else(|| url.to_string());
+
+            if descriptor.is_empty() {
+                url
+            } else {
+                format!("{} {}", url, descriptor.trim())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn srcset_urls(srcset: &str) -> Vec<String> {
+    srcset
+        .split(',')
+        .filter_map(|candidate| candidate.split_whitespace().next())
+        .map(|url| url.to_string())
+        .collect()
+}
+
+// Resolves a url relative to the dir of the referencing file to a dist uri like `/fonts/a.woff2`
+fn resolve_uri(base_dir_uri: &str, url: &str) -> Option<String> {
+    if url.starts_with("data:")
+        || url.starts_with('#')
+        || url.starts_with("//")
+        || url.contains("://")
+    {
+        return None;
+    }
+
+    let joined = if url.starts_with('/') {
+        url.to_string()
+    } else {
+        format!("{}/{}", base_dir_uri, url)
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+
+    for segment in joined.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    Some(format!("/{}", segments.join("/")))
 }
 
 impl Deref for HashedAsset {
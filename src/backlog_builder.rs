@@ -1,34 +1,57 @@
 use crate::build::Runner;
+use crate::change_analyzer::ChangeAnalyzer;
+use crate::live_reload::LiveReload;
+use crate::plugin;
+use crate::plugin::Phase;
+use crate::plugin::PluginStep;
 use crate::rust_builder;
 use crate::rust_builder::RustBuilder;
 use crate::script_runner;
-use crate::script_runner::ScriptRunner;
+use crate::script_runner::Context;
+use crate::script_runner::Event;
+use crate::script_runner::Hooks;
 use crate::web_builder;
 use crate::web_builder::WebBuilder;
+use crate::webhook::BuildEvent;
+use crate::webhook::Notifier;
+use std::collections::BTreeSet;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+// More rebuilds than this within the window is treated as a rebuild loop
+const LOOP_MAX_REBUILDS: usize = 5;
+const LOOP_WINDOW: Duration = Duration::from_secs(10);
+const LOOP_PAUSE: Duration = Duration::from_secs(30);
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum ChangeType {
     Rust,
     TypeScript,
     Css,
+    Plugin(String),
 }
 
 #[derive(Debug)]
 pub enum Error {
     BacklogLock(String),
+    LoopGuardLock(String),
 }
 
 #[derive(Debug)]
 pub enum BuildError {
     RustBuild(rust_builder::Error),
     WebBuild(web_builder::Error),
-    PostBuildRunner(script_runner::Error),
+    Hook(Event, script_runner::Error),
+    Plugin(String),
 }
 
 impl Display for BuildError {
@@ -36,7 +59,8 @@ impl Display for BuildError {
         match self {
             BuildError::RustBuild(err) => write!(f, "---Rust build failed: {}", err),
             BuildError::WebBuild(err) => write!(f, "Web build failed: {}", err),
-            BuildError::PostBuildRunner(err) => write!(f, "Post build runner failed: {}", err),
+            BuildError::Hook(event, err) => write!(f, "The {} hook failed: {}", event, err),
+            BuildError::Plugin(err) => write!(f, "{}", err),
         }
     }
 }
@@ -51,7 +75,11 @@ pub struct BacklogBuilder {
 pub struct Config {
     pub rust_builder: RustBuilder,
     pub web_builder: WebBuilder,
-    pub post_build_runner: Option<ScriptRunner>,
+    pub hooks: Hooks,
+    pub plugins: Vec<PluginStep>,
+    pub notifier: Notifier,
+    pub live_reload: LiveReload,
+    pub change_analyzer: ChangeAnalyzer,
 }
 
 impl BacklogBuilder {
@@ -62,13 +90,23 @@ impl BacklogBuilder {
         }
     }
 
-    pub fn run(&mut self, change: ChangeType) -> Result<(), Error> {
+    pub fn run(&mut self, change: ChangeType, path: PathBuf) -> Result<(), Error> {
+        if self.state.is_paused()? {
+            return Ok(());
+        }
+
         self.state
             .backlog
             .lock()
             .map_err(|err| Error::BacklogLock(err.to_string()))?
             .insert(change);
 
+        self.state
+            .changed_files
+            .lock()
+            .map_err(|err| Error::BacklogLock(err.to_string()))?
+            .insert(path);
+
         if self.is_running() {
             Ok(())
         } else {
@@ -76,6 +114,14 @@ impl BacklogBuilder {
         }
     }
 
+    pub fn plugins(&self) -> &[PluginStep] {
+        &self.config.plugins
+    }
+
+    pub fn change_analyzer(&self) -> &ChangeAnalyzer {
+        &self.config.change_analyzer
+    }
+
     fn is_running(&self) -> bool {
         self.state
             .is_running
@@ -102,11 +148,66 @@ fn build(config: Config, state: Arc<State>) -> Result<(), Error> {
             .drain()
             .collect();
 
-        let build_type = BuildType::from_changes(changes);
+        // Passed to the hooks of the rebuild
+        let changed_files: Vec<PathBuf> = std::mem::take(
+            &mut *state
+                .changed_files
+                .lock()
+                .map_err(|err| Error::BacklogLock(err.to_string()))?,
+        )
+        .into_iter()
+        .collect();
+
+        if state.detect_loop()? {
+            eprintln!(
+                "Warning: More than {} rebuilds within {:?}, last triggered by {:?}",
+                LOOP_MAX_REBUILDS, LOOP_WINDOW, changes
+            );
+            eprintln!(
+                "This looks like a rebuild loop caused by a generated file, pausing the watcher for {:?}",
+                LOOP_PAUSE
+            );
+
+            state
+                .is_running
+                .store(false, std::sync::atomic::Ordering::Relaxed);
+
+            return Ok(());
+        }
+
+        let changed_plugins: Vec<PluginStep> = config
+            .plugins
+            .iter()
+            .filter(|plugin| changes.contains(&ChangeType::Plugin(plugin.name().to_string())))
+            .cloned()
+            .collect();
+
+        let build_type = BuildType::from_changes(changes, &config.change_analyzer);
 
         std::thread::spawn(move || {
-            if let Err(err) = run_script(build_type, &config) {
-                println!("{}", err);
+            let started_at = Instant::now();
+            config.notifier.notify(&BuildEvent::Started);
+
+            let context = Context {
+                changed_files,
+                ..Context::default()
+            };
+
+            match run_script(build_type, &changed_plugins, &context, &config) {
+                Ok(()) => {
+                    config.notifier.notify(&BuildEvent::Succeeded {
+                        duration: started_at.elapsed(),
+                    });
+                    config.live_reload.notify();
+                }
+
+                Err(err) => {
+                    println!("{}", err);
+                    config.notifier.notify(&BuildEvent::Failed {
+                        duration: started_at.elapsed(),
+                        error: err.to_string(),
+                    });
+                }
             };
 
             state
@@ -129,6 +230,10 @@ pub fn handle_error(err: Error) {
         Error::BacklogLock(err) => {
             println!("Failed to get a lock on backlog: {}", err);
         }
+
+        Error::LoopGuardLock(err) => {
+            println!("Failed to get a lock on rebuild history: {}", err);
+        }
     }
 }
 
@@ -136,6 +241,9 @@ pub fn handle_error(err: Error) {
 pub struct State {
     is_running: AtomicBool,
     backlog: Mutex<HashSet<ChangeType>>,
+    changed_files: Mutex<BTreeSet<PathBuf>>,
+    recent_builds: Mutex<VecDeque<Instant>>,
+    paused_until: Mutex<Option<Instant>>,
 }
 
 impl State {
@@ -143,52 +251,128 @@ impl State {
         Self {
             is_running: AtomicBool::new(false),
             backlog: Mutex::new(HashSet::new()),
+            changed_files: Mutex::new(BTreeSet::new()),
+            recent_builds: Mutex::new(VecDeque::new()),
+            paused_until: Mutex::new(None),
         }
     }
+
+    fn is_paused(&self) -> Result<bool, Error> {
+        let paused_until = self
+            .paused_until
+            .lock()
+            .map_err(|err| Error::LoopGuardLock(err.to_string()))?;
+
+        Ok(paused_until
+            .map(|paused_until| Instant::now() < paused_until)
+            .unwrap_or(false))
+    }
+
+    // Records a build start and pauses the watcher if too many builds were started recently
+    fn detect_loop(&self) -> Result<bool, Error> {
+        let now = Instant::now();
+        let mut recent_builds = self
+            .recent_builds
+            .lock()
+            .map_err(|err| Error::LoopGuardLock(err.to_string()))?;
+
+        recent_builds.retain(|started_at| now.duration_since(*started_at) < LOOP_WINDOW);
+        recent_builds.push_back(now);
+
+        if recent_builds.len() <= LOOP_MAX_REBUILDS {
+            return Ok(false);
+        }
+
+        recent_builds.clear();
+
+        let mut paused_until = self
+            .paused_until
+            .lock()
+            .map_err(|err| Error::LoopGuardLock(err.to_string()))?;
+        *paused_until = Some(now + LOOP_PAUSE);
+
+        Ok(true)
+    }
 }
 
-fn run_script(build_type: BuildType, config: &Config) -> Result<(), BuildError> {
+fn run_script(
+    build_type: BuildType,
+    changed_plugins: &[PluginStep],
+    context: &Context,
+    config: &Config,
+) -> Result<(), BuildError> {
     println!("\nStarting build of {:?}", build_type);
 
+    run_hooks(config, Event::PreBuild, context)?;
+    plugin::run_phase(changed_plugins, Phase::PreBuild).map_err(BuildError::Plugin)?;
+
     match build_type {
-        BuildType::All => {
+        BuildType::Rust { web } => {
+            let glue_path = config.rust_builder.frontend_glue_path();
+            let old_glue = fs::read(&glue_path).ok();
+
             config.rust_builder.run().map_err(BuildError::RustBuild)?;
-            config.web_builder.run().map_err(BuildError::WebBuild)?;
+            run_hooks(config, Event::PostRustBuild, context)?;
+
+            // The wasm is copied to dist by the rust builder, the bundle only changes with the bindings
+            if web || fs::read(&glue_path).ok() != old_glue {
+                config.web_builder.run().map_err(BuildError::WebBuild)?;
+                run_hooks(config, Event::PostWebBuild, context)?;
+            } else {
+                println!("Skipping web build, the wasm bindings are unchanged");
+            }
         }
 
         BuildType::OnlyWeb => {
             config.web_builder.run().map_err(BuildError::WebBuild)?;
+            run_hooks(config, Event::PostWebBuild, context)?;
         }
-    }
 
-    if let Some(post_build_runner) = &config.post_build_runner {
-        post_build_runner
-            .run(script_runner::Event::BeforeAssetHash)
-            .map_err(BuildError::PostBuildRunner)?;
+        BuildType::OnlyPlugins => {}
     }
 
+    plugin::run_phase(changed_plugins, Phase::PostBuild).map_err(BuildError::Plugin)?;
+
+    run_hooks(config, Event::PostBuild, context)?;
+    run_hooks(config, Event::OnWatchRebuild, context)?;
+
     println!("Completed build of {:?}", build_type);
 
     Ok(())
 }
 
+fn run_hooks(config: &Config, event: Event, context: &Context) -> Result<(), BuildError> {
+    config
+        .hooks
+        .run_with(event, context)
+        .map_err(|err| BuildError::Hook(event, err))
+}
+
 #[derive(Debug)]
 enum BuildType {
-    All,
+    Rust { web: bool },
     OnlyWeb,
+    OnlyPlugins,
 }
 
 impl BuildType {
-    fn from_changes(changes: HashSet<ChangeType>) -> BuildType {
-        let only_typescript = HashSet::from([ChangeType::TypeScript]);
-        let only_css = HashSet::from([ChangeType::Css]);
+    fn from_changes(changes: HashSet<ChangeType>, change_analyzer: &ChangeAnalyzer) -> BuildType {
+        let changes: HashSet<ChangeType> = changes
+            .into_iter()
+            .filter(|change| !matches!(change, ChangeType::Plugin(_)))
+            .collect();
 
-        if changes == only_typescript {
-            BuildType::OnlyWeb
-        } else if changes == only_css {
+        let web_changed =
+            changes.contains(&ChangeType::TypeScript) || changes.contains(&ChangeType::Css);
+
+        if changes.contains(&ChangeType::Rust) {
+            BuildType::Rust {
+                web: web_changed || change_analyzer.rust_affects_css(),
+            }
+        } else if web_changed {
             BuildType::OnlyWeb
         } else {
-            BuildType::All
+            BuildType::OnlyPlugins
         }
     }
 }
@@ -1,10 +1,139 @@
+use crate::build_log;
+use crate::exec;
+use crate::ProjectInfo;
 use std::fmt;
 use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
 
 pub trait Runner<E> {
     fn run(&self) -> Result<(), E>;
 }
 
+pub trait BuildStep {
+    fn name(&self) -> String;
+    fn inputs(&self) -> Vec<PathBuf>;
+    fn outputs(&self) -> Vec<PathBuf>;
+    // The commands run by the step in order, listed by dry runs
+    fn commands(&self) -> Vec<exec::Config>;
+    fn run(&self) -> Result<(), String>;
+}
+
+pub fn run_step<S: BuildStep + ?Sized>(step: &S) -> Result<(), String> {
+    let started_at = Instant::now();
+    let result = step.run();
+    let elapsed = started_at.elapsed();
+
+    let line = match &result {
+        Ok(()) => format!("[{}] Completed in {:.2?}", step.name(), elapsed),
+        Err(_) => format!("[{}] Failed after {:.2?}", step.name(), elapsed),
+    };
+
+    println!("{}", line);
+    build_log::append(&format!("{}\n", line));
+
+    result
+}
+
+// One job per cpu unless --jobs says otherwise
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|jobs| jobs.get())
+        .unwrap_or(1)
+}
+
+// Runs independent jobs on at most `limit` threads. `report` is called on the calling
+// thread as each job finishes, the results are returned in the order of the jobs
+pub fn run_parallel<T, R>(
+    jobs: &[T],
+    limit: usize,
+    run: impl Fn(&T) -> R + Sync,
+    mut report: impl FnMut(&T, &R),
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut results: Vec<Option<R>> = jobs.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..limit.clamp(1, jobs.len().max(1)) {
+            let sender = sender.clone();
+            let next = &next;
+            let run = &run;
+
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+
+                match jobs.get(index) {
+                    Some(job) => {
+                        let _ = sender.send((index, run(job)));
+                    }
+
+                    None => break,
+                }
+            });
+        }
+
+        // The receiver ends when the last worker is done with its sender
+        drop(sender);
+
+        for (index, result) in receiver {
+            report(&jobs[index], &result);
+            results[index] = Some(result);
+        }
+    });
+
+    results.into_iter().flatten().collect()
+}
+
+// For --dry-run, lists what the step would run instead of running it
+pub fn print_plan<S: BuildStep + ?Sized>(step: &S) {
+    let commands = step.commands();
+
+    if commands.is_empty() {
+        let outputs: Vec<String> = step
+            .outputs()
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+
+        println!(
+            "[{}] Would only copy files to {}",
+            step.name(),
+            outputs.join(", ")
+        );
+    }
+
+    for command in commands {
+        println!("[{}] {}", step.name(), exec::describe(&command));
+    }
+}
+
+pub fn describe_step<S: BuildStep + ?Sized>(step: &S) -> String {
+    let format_paths = |paths: Vec<PathBuf>| {
+        paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    format!(
+        "{} (inputs: [{}], outputs: [{}])",
+        step.name(),
+        format_paths(step.inputs()),
+        format_paths(step.outputs())
+    )
+}
+
 #[derive(Debug, Clone)]
 pub enum Env {
     Dev,
@@ -19,3 +148,65 @@ impl Display for Env {
         }
     }
 }
+
+// Everything a builder, script or plugin may want to know about the current build
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub env: Env,
+    // None when serving a dir outside of a project
+    pub project_name: Option<String>,
+    pub git_sha: Option<String>,
+    pub dist_path: PathBuf,
+    pub hash_assets: bool,
+}
+
+impl Profile {
+    pub fn from_project_info(env: Env, project_info: &ProjectInfo, hash_assets: bool) -> Self {
+        Self {
+            env,
+            project_name: Some(project_info.project_name.clone()),
+            git_sha: git_sha(),
+            dist_path: project_info.dist_path.clone(),
+            hash_assets,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.env.to_string()
+    }
+
+    // Exposed to scripts and npm as environment variables. The dist dir is not passed as
+    // POLY_DIST_DIR, a poly run by a hook would read it as an override and build into it
+    pub fn script_env(&self) -> Vec<(String, String)> {
+        let mut vars = vec![
+            ("POLY_PROFILE".to_string(), self.name()),
+            (
+                "POLY_OUT_DIR".to_string(),
+                self.dist_path.display().to_string(),
+            ),
+            ("POLY_HASH_ASSETS".to_string(), self.hash_assets.to_string()),
+        ];
+
+        if let Some(project_name) = &self.project_name {
+            vars.push(("POLY_PROJECT_NAME".to_string(), project_name.clone()));
+        }
+
+        if let Some(git_sha) = &self.git_sha {
+            vars.push(("POLY_GIT_SHA".to_string(), git_sha.clone()));
+        }
+
+        vars
+    }
+}
+
+pub fn git_sha() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
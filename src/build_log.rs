@@ -0,0 +1,256 @@
+use crate::util::time_util::DateTime;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+pub const DEFAULT_KEEP: usize = 20;
+
+const LOG_FILE_NAME: &str = "build.log";
+const INDEX_FILE_NAME: &str = "index.json";
+
+// Log file of the current invocation, written to by exec and the build steps
+static ACTIVE_LOG: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+#[derive(Debug)]
+pub enum Error {
+    CreateLogDir(PathBuf, io::Error),
+    ReadIndex(PathBuf, io::Error),
+    ParseIndex(PathBuf, serde_json::Error),
+    WriteIndex(PathBuf, io::Error),
+    SerializeIndex(serde_json::Error),
+    ReadLog(PathBuf, io::Error),
+    NoBuilds,
+    UnknownBuild(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::CreateLogDir(path, err) => {
+                write!(f, "Failed to create {}: {}", path.display(), err)
+            }
+            Error::ReadIndex(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::ParseIndex(path, err) => {
+                write!(f, "Failed to parse {}: {}", path.display(), err)
+            }
+            Error::WriteIndex(path, err) => {
+                write!(f, "Failed to write {}: {}", path.display(), err)
+            }
+            Error::SerializeIndex(err) => write!(f, "Failed to serialize log index: {}", err),
+            Error::ReadLog(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::NoBuilds => write!(f, "No build logs found"),
+            Error::UnknownBuild(id) => write!(f, "No build log with id '{}'", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub logs_path: PathBuf,
+    pub keep: usize,
+}
+
+impl Config {
+    pub fn new(current_dir: &Path, keep: Option<usize>) -> Self {
+        Self {
+            logs_path: current_dir.join(".poly").join("logs"),
+            keep: keep.unwrap_or(DEFAULT_KEEP).max(1),
+        }
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.logs_path.join(INDEX_FILE_NAME)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Running => write!(f, "running"),
+            Status::Succeeded => write!(f, "succeeded"),
+            Status::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub id: String,
+    pub command: String,
+    pub started_at: String,
+    pub duration_ms: Option<u128>,
+    pub status: Status,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildLog {
+    config: Config,
+    id: String,
+    log_path: PathBuf,
+}
+
+impl BuildLog {
+    // Creates `.poly/logs/<id>/build.log`, makes it the active log and prunes old builds
+    pub fn start(config: Config, command: &str) -> Result<BuildLog, Error> {
+        let started_at = DateTime::from_system_time(SystemTime::now());
+        let mut entries = read_index(&config)?;
+        let id = unique_id(&started_at, &entries);
+        let log_dir = config.logs_path.join(&id);
+
+        fs::create_dir_all(&log_dir).map_err(|err| Error::CreateLogDir(log_dir.clone(), err))?;
+
+        entries.push(IndexEntry {
+            id: id.clone(),
+            command: command.to_string(),
+            started_at: started_at.to_iso_string(),
+            duration_ms: None,
+            status: Status::Running,
+        });
+
+        let keep_from = entries.len().saturating_sub(config.keep);
+        for entry in entries.drain(..keep_from) {
+            let _ = fs::remove_dir_all(config.logs_path.join(&entry.id));
+        }
+
+        write_index(&config, &entries)?;
+
+        let log = BuildLog {
+            log_path: log_dir.join(LOG_FILE_NAME),
+            config,
+            id,
+        };
+
+        if let Ok(mut active_log) = ACTIVE_LOG.lock() {
+            *active_log = Some(log.log_path.clone());
+        }
+
+        append(&format!("$ poly {}\n", command));
+
+        Ok(log)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.log_path
+    }
+
+    pub fn finish(&self, status: Status, duration: Duration) {
+        append(&format!("\n[{}] after {:.2?}\n", status, duration));
+
+        let result = read_index(&self.config).and_then(|mut entries| {
+            if let Some(entry) = entries.iter_mut().find(|entry| entry.id == self.id) {
+                entry.status = status;
+                entry.duration_ms = Some(duration.as_millis());
+            }
+
+            write_index(&self.config, &entries)
+        });
+
+        if let Err(err) = result {
+            eprintln!("Warning: {}", err);
+        }
+    }
+}
+
+// Logging is best effort, a failing write should never fail the build
+pub fn append(text: &str) {
+    let active_log = match ACTIVE_LOG.lock() {
+        Ok(active_log) => active_log.clone(),
+        Err(_) => None,
+    };
+
+    if let Some(path) = active_log {
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(text.as_bytes()));
+
+        if let Err(err) = result {
+            eprintln!("Warning: Failed to write {}: {}", path.display(), err);
+        }
+    }
+}
+
+pub fn list(config: &Config) -> Result<Vec<IndexEntry>, Error> {
+    read_index(config)
+}
+
+// Returns the log of the given build, or of the latest build when no id is given
+pub fn read(config: &Config, id: Option<&str>) -> Result<(IndexEntry, String), Error> {
+    let entries = read_index(config)?;
+
+    let entry = match id {
+        Some(id) => entries
+            .into_iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| Error::UnknownBuild(id.to_string()))?,
+
+        None => entries.into_iter().last().ok_or(Error::NoBuilds)?,
+    };
+
+    let log_path = config.logs_path.join(&entry.id).join(LOG_FILE_NAME);
+    let content = fs::read_to_string(&log_path).map_err(|err| Error::ReadLog(log_path, err))?;
+
+    Ok((entry, content))
+}
+
+fn unique_id(started_at: &DateTime, entries: &[IndexEntry]) -> String {
+    let base_id = format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        started_at.year,
+        started_at.month,
+        started_at.day,
+        started_at.hour,
+        started_at.minute,
+        started_at.second
+    );
+
+    let is_taken = |id: &str| entries.iter().any(|entry| entry.id == id);
+
+    (1..)
+        .map(|n| match n {
+            1 => base_id.clone(),
+            n => format!("{}-{}", base_id, n),
+        })
+        .find(|id| !is_taken(id))
+        .unwrap_or(base_id)
+}
+
+fn read_index(config: &Config) -> Result<Vec<IndexEntry>, Error> {
+    let path = config.index_path();
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path).map_err(|err| Error::ReadIndex(path.clone(), err))?;
+    serde_json::from_str(&content).map_err(|err| Error::ParseIndex(path, err))
+}
+
+fn write_index(config: &Config, entries: &[IndexEntry]) -> Result<(), Error> {
+    let path = config.index_path();
+    let content = serde_json::to_string_pretty(entries).map_err(Error::SerializeIndex)?;
+
+    fs::write(&path, content).map_err(|err| Error::WriteIndex(path, err))
+}
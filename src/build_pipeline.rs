@@ -0,0 +1,148 @@
+use crate::artifact_uploader::ArtifactUploader;
+use crate::asset_hasher::AssetHasher;
+use crate::build;
+use crate::cleaner::Cleaner;
+use crate::image_optimizer::ImageOptimizer;
+use crate::plugin;
+use crate::plugin::Phase;
+use crate::plugin::PluginStep;
+use crate::rust_builder::RustBuilder;
+use crate::script_runner::Context;
+use crate::script_runner::Event;
+use crate::script_runner::Hooks;
+use crate::target_matrix::TargetMatrix;
+use crate::web_builder::WebBuilder;
+use std::path::Path;
+
+// A full build with the hooks and plugin phases between its steps, used by `poly build` and
+// the initial build of `poly watch`. Watch rebuilds are run by the backlog builder. The asset
+// hash events are only emitted when the assets are hashed
+pub struct Pipeline<'a> {
+    pub dist_path: &'a Path,
+    pub cleaner: &'a Cleaner,
+    pub rust_builder: &'a RustBuilder,
+    pub web_builder: &'a WebBuilder,
+    pub target_matrix: Option<&'a TargetMatrix>,
+    pub plugins: &'a [PluginStep],
+    pub hooks: &'a Hooks,
+    pub image_optimizer: Option<ImageOptimizer>,
+    pub asset_hasher: Option<AssetHasher>,
+    pub uploader: Option<ArtifactUploader>,
+}
+
+impl Pipeline<'_> {
+    // Stops at the first failing step or hook
+    pub fn run(&mut self) -> Result<(), String> {
+        self.run_hooks(Event::PreClean)?;
+
+        self.cleaner
+            .run()
+            .map_err(|err| format!("Clean failed: {}", err))?;
+
+        self.run_hooks(Event::PreBuild)?;
+        plugin::run_phase(self.plugins, Phase::PreBuild)?;
+
+        build::run_step(self.rust_builder).map_err(|err| format!("Rust build failed: {}", err))?;
+        self.run_hooks(Event::PostRustBuild)?;
+
+        build::run_step(self.web_builder).map_err(|err| format!("Web build failed: {}", err))?;
+        self.run_hooks(Event::PostWebBuild)?;
+
+        if let Some(target_matrix) = self.target_matrix {
+            build::run_step(target_matrix)
+                .map_err(|err| format!("Target matrix build failed: {}", err))?;
+        }
+
+        plugin::run_phase(self.plugins, Phase::PostBuild)?;
+
+        if self.asset_hasher.is_some() {
+            self.run_hooks(Event::BeforeAssetHash)?;
+        }
+
+        if let Some(image_optimizer) = &mut self.image_optimizer {
+            image_optimizer.run().map_err(|err| err.to_string())?;
+        }
+
+        if let Some(asset_hasher) = &self.asset_hasher {
+            asset_hasher
+                .hash_dist_assets()
+                .map_err(|err| format!("Asset hashing failed: {}", err))?;
+
+            let context = Context {
+                asset_manifest: Some(asset_hasher.manifest_path()),
+                ..Context::default()
+            };
+
+            self.run_hooks_with(Event::AfterAssetHash, &context)?;
+        }
+
+        if let Some(uploader) = &self.uploader {
+            uploader
+                .run(None)
+                .map_err(|err| format!("Upload failed: {}", err))?;
+        }
+
+        self.run_hooks(Event::PostBuild)
+    }
+
+    // For --dry-run, in the order run goes through the steps
+    pub fn print_plan(&self) {
+        println!("Dry run, nothing is executed");
+
+        self.hooks.print_plan(Event::PreClean);
+
+        let dirs: Vec<String> = self
+            .cleaner
+            .recreated_dirs()
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect();
+        println!("[clean] Would empty {}", dirs.join(", "));
+
+        self.hooks.print_plan(Event::PreBuild);
+        plugin::print_phase_plan(self.plugins, Phase::PreBuild);
+
+        build::print_plan(self.rust_builder);
+        self.hooks.print_plan(Event::PostRustBuild);
+
+        build::print_plan(self.web_builder);
+        self.hooks.print_plan(Event::PostWebBuild);
+
+        if let Some(target_matrix) = self.target_matrix {
+            build::print_plan(target_matrix);
+        }
+
+        plugin::print_phase_plan(self.plugins, Phase::PostBuild);
+
+        let dist = self.dist_path.display();
+
+        if self.asset_hasher.is_some() {
+            self.hooks.print_plan(Event::BeforeAssetHash);
+        }
+
+        if self.image_optimizer.is_some() {
+            println!("[images] Would optimize the images in {}", dist);
+        }
+
+        if self.asset_hasher.is_some() {
+            println!("[hash] Would hash the assets in {}", dist);
+            self.hooks.print_plan(Event::AfterAssetHash);
+        }
+
+        if self.uploader.is_some() {
+            println!("[upload] Would upload the artifacts configured in [upload]");
+        }
+
+        self.hooks.print_plan(Event::PostBuild);
+    }
+
+    fn run_hooks(&self, event: Event) -> Result<(), String> {
+        self.run_hooks_with(event, &Context::default())
+    }
+
+    fn run_hooks_with(&self, event: Event, context: &Context) -> Result<(), String> {
+        self.hooks
+            .run_with(event, context)
+            .map_err(|err| format!("The {} hook failed: {}", event, err))
+    }
+}
@@ -0,0 +1,152 @@
+use crate::exec;
+use crate::ProjectInfo;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+const TAILWIND_CONFIG_FILES: [&str; 4] = [
+    "tailwind.config.js",
+    "tailwind.config.cjs",
+    "tailwind.config.mjs",
+    "tailwind.config.ts",
+];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub current_dir: PathBuf,
+    pub wasm_project_path: PathBuf,
+    pub web_project_path: PathBuf,
+}
+
+impl Config {
+    pub fn from_project_info(current_dir: &Path, project_info: &ProjectInfo) -> Self {
+        Self {
+            current_dir: current_dir.to_path_buf(),
+            wasm_project_path: project_info.wasm_project_path.clone(),
+            web_project_path: project_info.web_project_path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    manifest_path: PathBuf,
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Dependency {
+    path: Option<PathBuf>,
+}
+
+// Decides which builders a change needs. Built once when watch starts, when
+// cargo metadata fails every Rust file is treated as part of the frontend
+#[derive(Debug, Clone)]
+pub struct ChangeAnalyzer {
+    workspace_crates: Vec<PathBuf>,
+    frontend_crates: HashSet<PathBuf>,
+    rust_affects_css: bool,
+}
+
+impl ChangeAnalyzer {
+    pub fn new(config: &Config) -> Self {
+        let (workspace_crates, frontend_crates) = match read_metadata(config) {
+            Ok(metadata) => crate_graph(config, metadata),
+
+            Err(err) => {
+                eprintln!("Warning: Failed to read cargo metadata: {}", err);
+                (Vec::new(), HashSet::new())
+            }
+        };
+
+        Self {
+            workspace_crates,
+            frontend_crates,
+            rust_affects_css: css_depends_on_rust(config),
+        }
+    }
+
+    // Files in workspace crates the wasm crate doesn't depend on, like a backend, don't need a rebuild
+    pub fn is_frontend_rust_file(&self, path: &Path) -> bool {
+        let owning_crate = self
+            .workspace_crates
+            .iter()
+            .filter(|crate_path| path.starts_with(crate_path))
+            .max_by_key(|crate_path| crate_path.components().count());
+
+        match owning_crate {
+            Some(crate_path) => self.frontend_crates.contains(crate_path),
+            None => true,
+        }
+    }
+
+    // Tailwind scans the Rust sources for class names, so the css has to be rebuilt too
+    pub fn rust_affects_css(&self) -> bool {
+        self.rust_affects_css
+    }
+}
+
+fn read_metadata(config: &Config) -> Result<Metadata, String> {
+    let stdout = exec::run(&exec::Config {
+        work_dir: config.current_dir.clone(),
+        cmd: "cargo".into(),
+        args: exec::to_args(&["metadata", "--format-version", "1", "--no-deps"]),
+        env: Vec::new(),
+        env_mode: exec::EnvMode::Inherit,
+        timeout: None,
+    })
+    .map_err(|err| err.to_string())?;
+
+    serde_json::from_str(&stdout).map_err(|err| err.to_string())
+}
+
+// Returns the dirs of all workspace crates and of the ones the wasm crate depends on
+fn crate_graph(config: &Config, metadata: Metadata) -> (Vec<PathBuf>, HashSet<PathBuf>) {
+    let crate_dir = |package: &Package| {
+        let dir = package.manifest_path.parent().unwrap_or(Path::new(""));
+        fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+    };
+
+    let workspace_crates: Vec<PathBuf> = metadata.packages.iter().map(crate_dir).collect();
+
+    let mut frontend_crates = HashSet::new();
+    let mut pending = vec![fs::canonicalize(&config.wasm_project_path)
+        .unwrap_or_else(|_| config.wasm_project_path.clone())];
+
+    while let Some(dir) = pending.pop() {
+        if !frontend_crates.insert(dir.clone()) {
+            continue;
+        }
+
+        let package = metadata
+            .packages
+            .iter()
+            .find(|package| crate_dir(package) == dir);
+
+        if let Some(package) = package {
+            pending.extend(
+                package
+                    .dependencies
+                    .iter()
+                    .filter_map(|dependency| dependency.path.as_ref())
+                    .map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.clone())),
+            );
+        }
+    }
+
+    (workspace_crates, frontend_crates)
+}
+
+fn css_depends_on_rust(config: &Config) -> bool {
+    TAILWIND_CONFIG_FILES
+        .iter()
+        .filter_map(|name| fs::read_to_string(config.web_project_path.join(name)).ok())
+        .any(|content| content.contains(".rs") || content.contains("rs}"))
+}
@@ -1,4 +1,5 @@
 use crate::ProjectInfo;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
@@ -7,25 +8,66 @@ use std::path::PathBuf;
 pub struct Config {
     pub dist_path: PathBuf,
     pub web_project_path: PathBuf,
+    pub backend_dist_path: Option<PathBuf>,
+    pub target_path: PathBuf,
 }
 
 impl Config {
     pub fn from_project_info(project_info: &ProjectInfo) -> Self {
+        let project_dir = project_info
+            .dist_path
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_default();
+
         Self {
             dist_path: project_info.dist_path.clone(),
             web_project_path: project_info.web_project_path.clone(),
+            backend_dist_path: project_info.backend_dist_path.clone(),
+            target_path: project_dir.join("target"),
         }
     }
 
     fn web_project_wasm_path(&self) -> PathBuf {
         self.web_project_path.join("wasm")
     }
+
+    fn web_project_wasm_backend_path(&self) -> PathBuf {
+        self.web_project_path.join("wasm_backend")
+    }
+
+    fn node_modules_path(&self) -> PathBuf {
+        self.web_project_path.join("node_modules")
+    }
 }
 
 #[derive(Debug)]
 pub enum Error {
     CreateDistDir(io::Error),
     CreateWebWasmDir(io::Error),
+    Remove(PathBuf, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::CreateDistDir(err) => write!(f, "Failed to create the dist dir: {}", err),
+
+            Error::CreateWebWasmDir(err) => {
+                write!(f, "Failed to create the wasm dir in web project: {}", err)
+            }
+
+            Error::Remove(path, err) => write!(f, "Failed to remove {}: {}", path.display(), err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    // Build output, cheap to recreate
+    Dist,
+    // Also cargo and npm caches, the next build starts from scratch
+    All,
 }
 
 pub struct Cleaner {
@@ -37,6 +79,14 @@ impl Cleaner {
         Self { config }
     }
 
+    // The dirs emptied before every build
+    pub fn recreated_dirs(&self) -> Vec<PathBuf> {
+        vec![
+            self.config.dist_path.clone(),
+            self.config.web_project_wasm_path(),
+        ]
+    }
+
     pub fn run(&self) -> Result<(), Error> {
         let _ = fs::remove_dir_all(&self.config.dist_path);
         fs::create_dir_all(&self.config.dist_path).map_err(Error::CreateDistDir)?;
@@ -47,4 +97,29 @@ impl Cleaner {
 
         Ok(())
     }
+
+    // Returns the dirs that exist and are, or with dry_run would be, removed
+    pub fn clean(&self, scope: Scope, dry_run: bool) -> Result<Vec<PathBuf>, Error> {
+        let mut paths = vec![
+            self.config.dist_path.clone(),
+            self.config.web_project_wasm_path(),
+            self.config.web_project_wasm_backend_path(),
+        ];
+        paths.extend(self.config.backend_dist_path.clone());
+
+        if scope == Scope::All {
+            paths.push(self.config.target_path.clone());
+            paths.push(self.config.node_modules_path());
+        }
+
+        let existing: Vec<PathBuf> = paths.into_iter().filter(|path| path.exists()).collect();
+
+        if !dry_run {
+            for path in &existing {
+                fs::remove_dir_all(path).map_err(|err| Error::Remove(path.clone(), err))?;
+            }
+        }
+
+        Ok(existing)
+    }
 }
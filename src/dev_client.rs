@@ -0,0 +1,164 @@
+use serde::Deserialize;
+
+pub const CLIENT_PATH: &str = "/_poly/client.js";
+pub const CONSOLE_PATH: &str = "/_poly/console";
+
+// Forwards console.error calls, uncaught errors and rejected promises (which is
+// where wasm panics end up) to the terminal running `poly serve`, and reloads
+// the page when `poly watch` reports a successful build. Snippets sent by
+// `poly console` are evaluated here and the result is posted back
+const CLIENT_SCRIPT: &str = r#"(function () {
+  function send(level, message, stack) {
+    try {
+      fetch("/_poly/console", {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ level: level, message: message, stack: stack, url: location.href }),
+        keepalive: true,
+      });
+    } catch (e) {}
+  }
+
+  function format(args) {
+    return Array.prototype.map
+      .call(args, function (arg) {
+        if (arg instanceof Error) return arg.stack || String(arg);
+        if (arg === undefined) return "undefined";
+        if (typeof arg === "object") {
+          try {
+            return JSON.stringify(arg);
+          } catch (e) {}
+        }
+        return String(arg);
+      })
+      .join(" ");
+  }
+
+  var consoleError = console.error;
+  console.error = function () {
+    send("error", format(arguments));
+    return consoleError.apply(console, arguments);
+  };
+
+  window.addEventListener("error", function (event) {
+    send("error", event.message, event.error && event.error.stack);
+  });
+
+  window.addEventListener("unhandledrejection", function (event) {
+    var reason = event.reason;
+    send("error", "Unhandled rejection: " + ((reason && reason.message) || String(reason)), reason && reason.stack);
+  });
+
+  // Exports of the last instantiated wasm module, available as `wasm` in `poly console`
+  var wasm = {};
+
+  function captureExports(promise) {
+    return promise.then(function (result) {
+      var instance = result.instance || result;
+      if (instance && instance.exports) wasm = instance.exports;
+      return result;
+    });
+  }
+
+  var instantiate = WebAssembly.instantiate;
+  WebAssembly.instantiate = function () {
+    return captureExports(instantiate.apply(WebAssembly, arguments));
+  };
+
+  var instantiateStreaming = WebAssembly.instantiateStreaming;
+  if (instantiateStreaming) {
+    WebAssembly.instantiateStreaming = function () {
+      return captureExports(instantiateStreaming.apply(WebAssembly, arguments));
+    };
+  }
+
+  function evaluate(message) {
+    var answer = function (ok, value) {
+      fetch("/_poly/eval/result", {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ id: message.id, ok: ok, value: value }),
+      });
+    };
+
+    try {
+      var result = new Function("wasm", "return eval(arguments[1])")(wasm, message.code);
+      Promise.resolve(result).then(
+        function (value) {
+          answer(true, format([value]));
+        },
+        function (err) {
+          answer(false, format([err]));
+        }
+      );
+    } catch (err) {
+      answer(false, format([err]));
+    }
+  }
+
+  function connect() {
+    var protocol = location.protocol === "https:" ? "wss:" : "ws:";
+    var socket = new WebSocket(protocol + "//" + location.host + "/_poly/reload");
+
+    socket.onmessage = function (event) {
+      if (event.data === "reload") return location.reload();
+
+      var message = JSON.parse(event.data);
+      if (message.type === "eval") evaluate(message);
+    };
+
+    socket.onclose = function () {
+      setTimeout(connect, 1000);
+    };
+  }
+
+  connect();
+})();
+"#;
+
+#[derive(Debug, Deserialize)]
+struct ConsoleMessage {
+    level: String,
+    message: String,
+    stack: Option<String>,
+    url: Option<String>,
+}
+
+pub fn script() -> &'static str {
+    CLIENT_SCRIPT
+}
+
+// The script tag goes into <head> so errors during page load are captured too
+pub fn inject(html: &[u8]) -> Vec<u8> {
+    let html = String::from_utf8_lossy(html);
+    let script_tag = format!("<script src=\"{}\"></script>", CLIENT_PATH);
+
+    match html.find("</head>").or_else(|| html.find("</body>")) {
+        Some(index) => format!("{}{}{}", &html[..index], script_tag, &html[index..]),
+        None => format!("{}{}", html, script_tag),
+    }
+    .into_bytes()
+}
+
+pub fn print_console_message(body: &[u8]) {
+    match serde_json::from_slice::<ConsoleMessage>(body) {
+        Ok(msg) => {
+            eprintln!("[Browser {}] {}", msg.level, msg.message);
+
+            if let Some(stack) = msg
+                .stack
+                .filter(|stack| !msg.message.contains(stack.as_str()))
+            {
+                for line in stack.lines() {
+                    eprintln!("    {}", line.trim());
+                }
+            }
+
+            if let Some(url) = msg.url {
+                eprintln!("    at page {}", url);
+            }
+        }
+
+        Err(err) => eprintln!("Invalid console message from browser: {}", err),
+    }
+}
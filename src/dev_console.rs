@@ -0,0 +1,204 @@
+use crate::dev_session;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const EVAL_PATH: &str = "/_poly/eval";
+pub const EVAL_RESULT_PATH: &str = "/_poly/eval/result";
+pub const EVAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum Error {
+    ReadInput(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadInput(err) => write!(f, "Failed to read input: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalResult {
+    pub ok: bool,
+    pub value: String,
+}
+
+impl EvalResult {
+    pub fn failed(message: &str) -> Self {
+        Self {
+            ok: false,
+            value: message.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PageResult {
+    id: u64,
+    #[serde(flatten)]
+    result: EvalResult,
+}
+
+#[derive(Debug, Serialize)]
+struct EvalMessage<'a> {
+    #[serde(rename = "type")]
+    type_: &'a str,
+    id: u64,
+    code: &'a str,
+}
+
+pub fn eval_message(id: u64, code: &str) -> String {
+    serde_json::to_string(&EvalMessage {
+        type_: "eval",
+        id,
+        code,
+    })
+    .unwrap_or_default()
+}
+
+// Evaluations waiting for an answer from the page, used by `poly serve --dev`
+#[derive(Debug, Default)]
+pub struct Evaluations {
+    next_id: AtomicU64,
+    results: Mutex<HashMap<u64, Option<EvalResult>>>,
+    ready: Condvar,
+}
+
+impl Evaluations {
+    pub fn start(&self) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if let Ok(mut results) = self.results.lock() {
+            results.insert(id, None);
+        }
+
+        id
+    }
+
+    // Only the first answer counts when several tabs are open
+    pub fn complete(&self, body: &[u8]) {
+        let page_result = match serde_json::from_slice::<PageResult>(body) {
+            Ok(page_result) => page_result,
+            Err(err) => {
+                eprintln!("Invalid eval result from browser: {}", err);
+                return;
+            }
+        };
+
+        if let Ok(mut results) = self.results.lock() {
+            if let Some(result @ None) = results.get_mut(&page_result.id) {
+                *result = Some(page_result.result);
+                self.ready.notify_all();
+            }
+        }
+    }
+
+    pub fn wait(&self, id: u64, timeout: Duration) -> Option<EvalResult> {
+        let results = self.results.lock().ok()?;
+
+        let (mut results, _) = self
+            .ready
+            .wait_timeout_while(results, timeout, |results| {
+                matches!(results.get(&id), Some(None))
+            })
+            .ok()?;
+
+        results.remove(&id).flatten()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub url: String,
+    pub session_dir: PathBuf,
+    pub port: u32,
+}
+
+impl Config {
+    pub fn from_port(session_dir: &Path, port: u32) -> Self {
+        Self {
+            url: format!("http://127.0.0.1:{}{}", port, EVAL_PATH),
+            session_dir: session_dir.to_path_buf(),
+            port,
+        }
+    }
+}
+
+// Reads snippets from stdin until eof or .exit and evaluates them in the page
+pub fn run(config: &Config) -> Result<(), Error> {
+    println!("Evaluating in the page served by `poly serve --dev`");
+    println!("Exported wasm functions are available as `wasm`, type .exit to quit");
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+
+        let line = match lines.next() {
+            Some(line) => line.map_err(Error::ReadInput)?,
+            None => return Ok(()),
+        };
+
+        match line.trim() {
+            "" => continue,
+            ".exit" => return Ok(()),
+            code => print_result(&eval(config, code)),
+        }
+    }
+}
+
+fn eval(config: &Config, code: &str) -> EvalResult {
+    // Read per snippet so a restarted serve is picked up without restarting the console
+    let token = match dev_session::read_token(&config.session_dir, config.port) {
+        Some(token) => token,
+        None => {
+            return EvalResult::failed(&format!(
+                "No dev token in {}, is poly serve running with --dev on port {}?",
+                config.session_dir.display(),
+                config.port
+            ))
+        }
+    };
+
+    let response = ureq::post(&config.url)
+        .timeout(EVAL_TIMEOUT + Duration::from_secs(5))
+        .set(dev_session::TOKEN_HEADER, &token)
+        .send_string(code);
+
+    match response {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response
+            .into_string()
+            .map_err(|err| err.to_string())
+            .and_then(|body| serde_json::from_str(&body).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| EvalResult::failed(&format!("Invalid response: {}", err))),
+
+        Err(err) => EvalResult::failed(&format!(
+            "Could not reach poly serve at {}, is it running with --dev? ({})",
+            config.url, err
+        )),
+    }
+}
+
+fn print_result(result: &EvalResult) {
+    if result.ok {
+        println!("{}", result.value);
+    } else {
+        eprintln!("Error: {}", result.value);
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::fs;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// Sent by `poly watch` and `poly console` so that only local tools can use the
+// dev endpoints of `poly serve --dev` that act on the page
+pub const TOKEN_HEADER: &str = "X-Poly-Dev-Token";
+
+#[derive(Debug)]
+pub enum Error {
+    CreateDir(PathBuf, io::Error),
+    WriteToken(PathBuf, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::CreateDir(path, err) => {
+                write!(f, "Failed to create {}: {}", path.display(), err)
+            }
+
+            Error::WriteToken(path, err) => {
+                write!(f, "Failed to write dev token {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+pub fn session_dir(project_path: &Path) -> PathBuf {
+    project_path.join(".poly").join("dev")
+}
+
+// A new token is made every time serve starts, one per port so several servers can run
+pub fn create_token(session_dir: &Path, port: u32) -> Result<String, Error> {
+    fs::create_dir_all(session_dir)
+        .map_err(|err| Error::CreateDir(session_dir.to_path_buf(), err))?;
+
+    let token = random_token();
+    let path = token_path(session_dir, port);
+    write_private(&path, &token).map_err(|err| Error::WriteToken(path.clone(), err))?;
+
+    Ok(token)
+}
+
+pub fn read_token(session_dir: &Path, port: u32) -> Option<String> {
+    fs::read_to_string(token_path(session_dir, port))
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+// Compares every byte so the time taken doesn't tell how much of a guess was right
+pub fn is_valid_token(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn token_path(session_dir: &Path, port: u32) -> PathBuf {
+    session_dir.join(format!("{}.token", port))
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> Result<(), io::Error> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> Result<(), io::Error> {
+    fs::write(path, contents)
+}
+
+// RandomState is seeded from the OS random source, which avoids another dependency
+fn random_token() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+
+    let bytes = (0..4u64)
+        .flat_map(|i| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(i);
+            hasher.write_u128(nanos);
+            hasher.write_u32(std::process::id());
+            hasher.finish().to_le_bytes()
+        })
+        .collect::<Vec<u8>>();
+
+    data_encoding::HEXLOWER.encode(&bytes)
+}
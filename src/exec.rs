@@ -1,17 +1,49 @@
+use crate::build_log;
+use serde::Deserialize;
+use serde::Serialize;
+use std::env;
 use std::fmt;
 use std::fmt::Formatter;
 use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::IsTerminal;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process;
+use std::process::Child;
 use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
 use std::string;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const WAIT_INTERVAL: Duration = Duration::from_millis(50);
+
+// Tag colors, picked by the command name so a command keeps its color between builds
+const TAG_COLORS: [&str; 5] = ["36", "35", "33", "32", "34"];
+
+// Streamed lines are written one at a time under this lock, so lines of commands running
+// at the same time are interleaved instead of mixed up
+static OUTPUT: Mutex<()> = Mutex::new(());
 
 #[derive(Debug)]
 pub enum Error {
     FailedToExecute(io::Error),
+    FailedToStream(io::Error),
+    TimedOut {
+        command: String,
+        timeout: Duration,
+    },
     FailedToReadStdout(string::FromUtf8Error),
-    FailedToReadStderr(string::FromUtf8Error),
+    // Both streams are kept since tools like npm print their errors to stdout
     ExitFailure {
+        command: String,
         stdout: String,
         stderr: String,
         exit_status: Option<i32>,
@@ -22,39 +54,55 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
         match self {
             Error::FailedToExecute(err) => write!(f, "Failed to execute command: {}", err),
+            Error::FailedToStream(err) => write!(f, "Failed to read command output: {}", err),
+            Error::TimedOut { command, timeout } => write!(
+                f,
+                "'{}' didn't finish within {:?} and was killed",
+                command, timeout
+            ),
             Error::FailedToReadStdout(err) => write!(f, "Failed to read stdout: {}", err),
-            Error::FailedToReadStderr(err) => write!(f, "Failed to read stderr: {}", err),
             Error::ExitFailure {
+                command,
                 stdout,
                 stderr,
                 exit_status,
             } => {
-                let mut output = String::new();
-
-                if let Some(exit_status) = exit_status {
-                    output.push_str(&format!("Command failed with status: {}\n", exit_status));
-                } else {
-                    output.push_str(&format!("Command failed\n"));
+                match exit_status {
+                    Some(code) => write!(f, "'{}' failed with exit code {}", command, code)?,
+                    None => write!(f, "'{}' was terminated by a signal", command)?,
                 }
 
-                if !stdout.is_empty() {
-                    output.push_str(&format!("\n[stdout]\n{}\n", stdout));
-                }
+                for (name, content) in [("stdout", stdout), ("stderr", stderr)] {
+                    let content = content.trim_end();
 
-                if !stderr.is_empty() {
-                    output.push_str(&format!("\n[stderr]\n{}\n", stderr));
+                    if !content.is_empty() {
+                        write!(f, "\n\n[{}]\n{}", name, content)?;
+                    }
                 }
 
-                write!(f, "{}", output)
+                Ok(())
             }
         }
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Config {
     pub work_dir: PathBuf,
     pub cmd: String,
     pub args: Vec<String>,
+    // Set for the command only, poly's own environment is left alone
+    pub env: Vec<(String, String)>,
+    pub env_mode: EnvMode,
+    // The command is killed when it runs longer
+    pub timeout: Option<Duration>,
+}
+
+// What the command gets of poly's environment, the variables in config.env are added to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvMode {
+    Inherit,
+    Clear,
 }
 
 pub fn to_args(args: &[&str]) -> Vec<String> {
@@ -70,44 +118,370 @@ pub fn cmd_from_str(s: &str) -> Option<(String, Vec<String>)> {
     }
 }
 
+// A command as written in a config file, either a plain string split on whitespace or a
+// table like `{ run = "npm ci && npm run build", shell = true }`. Interactive commands
+// get the terminal, for prompts like the one of `wrangler login`, and `on_failure` decides
+// whether a failure stops what the command is part of
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CommandEntry {
+    Plain(String),
+    Table {
+        run: String,
+        #[serde(default)]
+        shell: bool,
+        #[serde(default)]
+        interactive: bool,
+        #[serde(default)]
+        on_failure: OnFailure,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnFailure {
+    #[default]
+    Abort,
+    Warn,
+    Ignore,
+}
+
+impl CommandEntry {
+    pub fn text(&self) -> &str {
+        match self {
+            CommandEntry::Plain(run) => run,
+            CommandEntry::Table { run, .. } => run,
+        }
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        matches!(
+            self,
+            CommandEntry::Table {
+                interactive: true,
+                ..
+            }
+        )
+    }
+
+    pub fn on_failure(&self) -> OnFailure {
+        match self {
+            CommandEntry::Plain(_) => OnFailure::Abort,
+            CommandEntry::Table { on_failure, .. } => *on_failure,
+        }
+    }
+
+    pub fn map_text(&self, f: impl Fn(&str) -> String) -> CommandEntry {
+        match self {
+            CommandEntry::Plain(run) => CommandEntry::Plain(f(run)),
+            CommandEntry::Table {
+                run,
+                shell,
+                interactive,
+                on_failure,
+            } => CommandEntry::Table {
+                run: f(run),
+                shell: *shell,
+                interactive: *interactive,
+                on_failure: *on_failure,
+            },
+        }
+    }
+
+    pub fn to_cmd(&self) -> Option<(String, Vec<String>)> {
+        match self {
+            CommandEntry::Table {
+                run, shell: true, ..
+            } => shell_cmd(run),
+            entry => cmd_from_str(entry.text()),
+        }
+    }
+}
+
+// Runs the command through the user's shell, so pipes, `&&` and `$VARS` work
+pub fn shell_cmd(command: &str) -> Option<(String, Vec<String>)> {
+    if command.trim().is_empty() {
+        return None;
+    }
+
+    if cfg!(windows) {
+        return Some(("cmd".to_string(), to_args(&["/C", command])));
+    }
+
+    let shell = env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "sh".to_string());
+
+    Some((shell, to_args(&["-c", command])))
+}
+
+pub fn is_on_path(cmd: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(cmd).is_file()))
+        .unwrap_or(false)
+}
+
 pub fn run(config: &Config) -> Result<String, Error> {
+    run_command(config, None)
+}
+
+// Like run, but the output is printed while the command runs, each line prefixed with the
+// command name. The output isn't repeated in the error when the command fails, it was
+// already printed
+pub fn run_streamed(config: &Config) -> Result<String, Error> {
+    let tag = Path::new(&config.cmd)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| config.cmd.clone());
+
+    run_command(config, Some(tag)).map_err(|err| match err {
+        Error::ExitFailure {
+            command,
+            exit_status,
+            ..
+        } => Error::ExitFailure {
+            command,
+            stdout: String::new(),
+            stderr: String::new(),
+            exit_status,
+        },
+        err => err,
+    })
+}
+
+// Like run, but the command gets the terminal so it can prompt. The output goes straight
+// to the terminal, so it isn't returned or written to the build log
+pub fn run_interactive(config: &Config) -> Result<(), Error> {
     log(config);
 
-    Command::new(&config.cmd)
-        .current_dir(&config.work_dir)
+    let mut child = command(config)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(Error::FailedToExecute)?;
+
+    let status = wait(config, &mut child)?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::ExitFailure {
+            command: command_line(config),
+            stdout: String::new(),
+            stderr: String::new(),
+            exit_status: status.code(),
+        })
+    }
+}
+
+fn command(config: &Config) -> Command {
+    let mut cmd = Command::new(&config.cmd);
+
+    if config.env_mode == EnvMode::Clear {
+        cmd.env_clear();
+    }
+
+    cmd.current_dir(&config.work_dir)
         .args(&config.args)
-        .output()
-        .map(|output| Output(output))
-        .map_err(Error::FailedToExecute)
-        .and_then(|output| output.read_stdout())
+        .envs(config.env.iter().map(|(key, value)| (key, value)));
+
+    cmd
 }
 
-fn log(config: &Config) {
-    if config.args.len() > 0 {
-        let args = config.args.join(" ");
-        println!("Executing: '{} {}'", config.cmd, args);
+fn run_command(config: &Config, tag: Option<String>) -> Result<String, Error> {
+    log(config);
+
+    let mut child = command(config)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(Error::FailedToExecute)?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| stream(pipe, tag.clone(), Stream::Stdout));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| stream(pipe, tag, Stream::Stderr));
+
+    let status = wait(config, &mut child)?;
+    let output = Output(process::Output {
+        status,
+        stdout: join_stream(stdout)?,
+        stderr: join_stream(stderr)?,
+    });
+
+    output.append_to_build_log();
+    output.into_stdout(config)
+}
+
+// Kills the command when it runs past the timeout. Processes started by the command
+// may keep running, the output of those isn't waited for
+fn wait(config: &Config, child: &mut Child) -> Result<ExitStatus, Error> {
+    let timeout = match config.timeout {
+        Some(timeout) => timeout,
+        None => return child.wait().map_err(Error::FailedToExecute),
+    };
+
+    let started_at = Instant::now();
+
+    loop {
+        if let Some(status) = child.try_wait().map_err(Error::FailedToExecute)? {
+            return Ok(status);
+        }
+
+        if started_at.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+
+            let line = format!("Killed '{}' after {:?}", command_line(config), timeout);
+            build_log::append(&format!("{}\n", line));
+
+            return Err(Error::TimedOut {
+                command: command_line(config),
+                timeout,
+            });
+        }
+
+        thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+// Prints the lines as they come when there is a tag and returns everything that was read,
+// the pipes are read while the command runs so that it doesn't block on a full pipe
+fn stream<R: Read + Send + 'static>(
+    pipe: R,
+    tag: Option<String>,
+    stream: Stream,
+) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut content = Vec::new();
+        let mut line = Vec::new();
+
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            if let Some(tag) = &tag {
+                write_line(tag, &line, stream);
+            }
+
+            content.append(&mut line);
+        }
+
+        Ok(content)
+    })
+}
+
+fn join_stream(handle: Option<thread::JoinHandle<io::Result<Vec<u8>>>>) -> Result<Vec<u8>, Error> {
+    match handle {
+        Some(handle) => handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("reader panicked")))
+            .map_err(Error::FailedToStream),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn write_line(tag: &str, line: &[u8], stream: Stream) {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end_matches(['\n', '\r']);
+
+    let _guard = OUTPUT.lock().unwrap_or_else(|err| err.into_inner());
+
+    let _ = match stream {
+        Stream::Stdout => {
+            let mut out = io::stdout().lock();
+            let prefix = format_tag(tag, out.is_terminal());
+            writeln!(out, "{} {}", prefix, line)
+        }
+
+        Stream::Stderr => {
+            let mut out = io::stderr().lock();
+            let prefix = format_tag(tag, out.is_terminal());
+            writeln!(out, "{} {}", prefix, line)
+        }
+    };
+}
+
+// The command with its dir and environment, for dry runs
+pub fn describe(config: &Config) -> String {
+    let work_dir =
+        std::path::absolute(&config.work_dir).unwrap_or_else(|_| config.work_dir.clone());
+    let mut lines = vec![
+        format!("Would run '{}'", command_line(config)),
+        format!("    dir: {}", work_dir.display()),
+    ];
+
+    if config.env_mode == EnvMode::Clear {
+        lines.push("    env: only the variables below".to_string());
+    }
+
+    for (key, value) in &config.env {
+        lines.push(format!("    env: {}={}", key, value));
+    }
+
+    if let Some(timeout) = config.timeout {
+        lines.push(format!("    timeout: {:?}", timeout));
+    }
+
+    lines.join("\n")
+}
+
+fn command_line(config: &Config) -> String {
+    if config.args.is_empty() {
+        config.cmd.clone()
     } else {
-        println!("Executing: '{}'", config.cmd);
+        format!("{} {}", config.cmd, config.args.join(" "))
     }
 }
 
+fn format_tag(tag: &str, color: bool) -> String {
+    if !color {
+        return format!("[{}]", tag);
+    }
+
+    let index = tag.bytes().map(usize::from).sum::<usize>() % TAG_COLORS.len();
+    format!("\x1b[{}m[{}]\x1b[0m", TAG_COLORS[index], tag)
+}
+
+fn log(config: &Config) {
+    let line = format!("Executing: '{}'", command_line(config));
+
+    println!("{}", line);
+    build_log::append(&format!("{}\n", line));
+}
+
 #[derive(Debug)]
 pub struct Output(process::Output);
 
 impl Output {
-    pub fn read_stdout(self) -> Result<String, Error> {
-        if self.0.status.success() {
-            String::from_utf8(self.0.stdout).map_err(Error::FailedToReadStdout)
-        } else {
-            let stdout = String::from_utf8(self.0.stdout).map_err(Error::FailedToReadStdout)?;
-            let stderr = String::from_utf8(self.0.stderr).map_err(Error::FailedToReadStderr)?;
-            let exit_status = self.0.status.code();
+    // Both streams are kept, warnings on stderr are otherwise lost on success
+    fn append_to_build_log(&self) {
+        build_log::append(&String::from_utf8_lossy(&self.0.stdout));
+        build_log::append(&String::from_utf8_lossy(&self.0.stderr));
+    }
 
-            Err(Error::ExitFailure {
-                stdout,
-                stderr,
-                exit_status,
-            })
+    // The output of a failed command is read lossily, invalid utf-8 shouldn't hide the error
+    fn into_stdout(self, config: &Config) -> Result<String, Error> {
+        if self.0.status.success() {
+            return String::from_utf8(self.0.stdout).map_err(Error::FailedToReadStdout);
         }
+
+        Err(Error::ExitFailure {
+            command: command_line(config),
+            stdout: String::from_utf8_lossy(&self.0.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&self.0.stderr).to_string(),
+            exit_status: self.0.status.code(),
+        })
     }
 }
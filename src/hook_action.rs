@@ -0,0 +1,224 @@
+use crate::asset_hasher;
+use crate::build::Profile;
+use crate::exec;
+use crate::util::compress;
+use crate::util::file_util;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use walkdir::WalkDir;
+
+const COPY_PREFIX: &str = "copy:";
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidCopy(String),
+    Copy {
+        src: PathBuf,
+        dst: PathBuf,
+        err: io::Error,
+    },
+    ReadDist(walkdir::Error),
+    Gzip(PathBuf, io::Error),
+    Deploy(exec::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::InvalidCopy(name) => write!(
+                f,
+                "Invalid hook action '{}', expected copy:<src>:<dst>",
+                name
+            ),
+
+            Error::Copy { src, dst, err } => write!(
+                f,
+                "Failed to copy {} to {}: {}",
+                src.display(),
+                dst.display(),
+                err
+            ),
+
+            Error::ReadDist(err) => write!(f, "Failed to read the dist dir: {}", err),
+
+            Error::Gzip(path, err) => write!(f, "Failed to gzip {}: {}", path.display(), err),
+
+            Error::Deploy(err) => write!(f, "Deploying to Cloudflare Pages failed: {}", err),
+        }
+    }
+}
+
+// Common post build chores that can be listed in [hooks] by name instead of writing a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    // `wrangler pages deploy` of dist, to the Pages project named after the project
+    CloudflarePagesDeploy,
+    // A file or dir, relative to the project dir
+    Copy { src: PathBuf, dst: PathBuf },
+    // A .gz next to each dist file that compresses, for hosts that serve them as they are
+    GzipDist,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Action::CloudflarePagesDeploy => write!(f, "cloudflare-pages-deploy"),
+            Action::Copy { src, dst } => {
+                write!(f, "{}{}:{}", COPY_PREFIX, src.display(), dst.display())
+            }
+            Action::GzipDist => write!(f, "gzip-dist"),
+        }
+    }
+}
+
+impl Action {
+    // None when the name isn't one of the actions
+    pub fn parse(name: &str) -> Option<Result<Action, Error>> {
+        let name = name.trim();
+
+        if let Some(paths) = name.strip_prefix(COPY_PREFIX) {
+            let action = match paths.split_once(':') {
+                Some((src, dst)) if !src.is_empty() && !dst.is_empty() => Ok(Action::Copy {
+                    src: PathBuf::from(src),
+                    dst: PathBuf::from(dst),
+                }),
+                _ => Err(Error::InvalidCopy(name.to_string())),
+            };
+
+            return Some(action);
+        }
+
+        match name {
+            "cloudflare-pages-deploy" => Some(Ok(Action::CloudflarePagesDeploy)),
+            "gzip-dist" => Some(Ok(Action::GzipDist)),
+            _ => None,
+        }
+    }
+
+    pub fn run(&self, profile: &Profile) -> Result<(), Error> {
+        println!("Running {}", self);
+
+        match self {
+            Action::CloudflarePagesDeploy => {
+                exec::run_streamed(&deploy_command(profile)).map_err(Error::Deploy)?;
+            }
+
+            Action::Copy { src, dst } => copy(src, dst)?,
+
+            Action::GzipDist => {
+                let count = gzip_dir(&profile.dist_path)?;
+                println!(
+                    "Wrote {} .gz file(s) in {}",
+                    count,
+                    profile.dist_path.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    // For --dry-run
+    pub fn describe(&self, profile: &Profile) -> String {
+        match self {
+            Action::CloudflarePagesDeploy => exec::describe(&deploy_command(profile)),
+
+            Action::Copy { src, dst } => {
+                format!("Would copy {} to {}", src.display(), dst.display())
+            }
+
+            Action::GzipDist => format!(
+                "Would write a .gz of each file in {}",
+                profile.dist_path.display()
+            ),
+        }
+    }
+}
+
+// npx is used when wrangler isn't installed globally, like the cloudflare template does
+fn deploy_command(profile: &Profile) -> exec::Config {
+    let mut args = exec::to_args(&["pages", "deploy", &profile.dist_path.to_string_lossy()]);
+
+    // Pages project names can't contain underscores
+    if let Some(project_name) = &profile.project_name {
+        args.push("--project-name".to_string());
+        args.push(project_name.replace('_', "-"));
+    }
+
+    let (cmd, args) = if exec::is_on_path("wrangler") {
+        ("wrangler".to_string(), args)
+    } else {
+        (
+            "npx".to_string(),
+            [exec::to_args(&["wrangler"]), args].concat(),
+        )
+    };
+
+    exec::Config {
+        work_dir: ".".into(),
+        cmd,
+        args,
+        env: profile.script_env(),
+        env_mode: exec::EnvMode::Inherit,
+        timeout: None,
+    }
+}
+
+fn copy(src: &Path, dst: &Path) -> Result<(), Error> {
+    let to_error = |err| Error::Copy {
+        src: src.to_path_buf(),
+        dst: dst.to_path_buf(),
+        err,
+    };
+
+    if src.is_dir() {
+        file_util::sync_dir_contents(src, dst).map_err(to_error)?;
+    } else {
+        if let Some(parent) = dst.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(to_error)?;
+        }
+
+        fs::copy(src, dst).map_err(to_error)?;
+    }
+
+    println!("Copied {} to {}", src.display(), dst.display());
+
+    Ok(())
+}
+
+// Files that don't get smaller are skipped, the host falls back to the original then
+fn gzip_dir(dir: &Path) -> Result<usize, Error> {
+    let mut count = 0;
+
+    for entry in WalkDir::new(dir) {
+        let entry = entry.map_err(Error::ReadDist)?;
+        let path = entry.path();
+        let ext = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+
+        if !entry.file_type().is_file()
+            || asset_hasher::COMPRESSED_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        {
+            continue;
+        }
+
+        let to_error = |err| Error::Gzip(path.to_path_buf(), err);
+        let content = fs::read(path).map_err(to_error)?;
+        let gzip = compress::gzip(&content, 9).map_err(to_error)?;
+
+        if gzip.len() >= content.len() {
+            continue;
+        }
+
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".gz");
+
+        fs::write(path.with_file_name(file_name), gzip).map_err(to_error)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,241 @@
+use crate::exec;
+use crate::poly_config::ImagesConfig;
+use crate::size_report;
+use crate::ProjectInfo;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use walkdir::WalkDir;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadImage(PathBuf, io::Error),
+    Exec(exec::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadImage(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::Exec(err) => write!(f, "Failed to optimize image: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub dist_path: PathBuf,
+    pub webp: bool,
+    pub avif: bool,
+    pub warn_size_kib: u64,
+}
+
+impl Config {
+    pub fn from_project_info(project_info: &ProjectInfo, images_config: &ImagesConfig) -> Self {
+        Self {
+            dist_path: project_info.dist_path.clone(),
+            webp: images_config.webp,
+            avif: images_config.avif,
+            warn_size_kib: images_config.warn_size_kib,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Png,
+    Jpeg,
+    Svg,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension().and_then(OsStr::to_str)?.to_lowercase();
+
+        match ext.as_str() {
+            "png" => Some(Format::Png),
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+
+    // Lossless optimizers that rewrite the file in place
+    fn optimizer(&self, path: &str) -> (&'static str, Vec<String>) {
+        match self {
+            Format::Png => (
+                "oxipng",
+                exec::to_args(&["-o", "4", "--strip", "safe", path]),
+            ),
+            Format::Jpeg => ("jpegoptim", exec::to_args(&["--strip-all", path])),
+            Format::Svg => (
+                "svgo",
+                exec::to_args(&["--multipass", "-i", path, "-o", path]),
+            ),
+        }
+    }
+}
+
+pub struct ImageOptimizer {
+    config: Config,
+    // Missing tools are only reported once
+    missing_tools: HashSet<&'static str>,
+}
+
+impl ImageOptimizer {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            missing_tools: HashSet::new(),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), Error> {
+        let images: Vec<(PathBuf, Format)> = WalkDir::new(&self.config.dist_path)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let format = Format::from_path(entry.path())?;
+                Some((entry.into_path(), format))
+            })
+            .collect();
+
+        for (path, format) in images {
+            self.optimize(&path, format)?;
+
+            // Vector images have no raster variants
+            if format != Format::Svg {
+                if self.config.webp {
+                    self.convert(&path, "webp", "cwebp", &["-q", "80", "-o"])?;
+                }
+
+                if self.config.avif {
+                    self.convert(&path, "avif", "avifenc", &[])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn optimize(&mut self, path: &Path, format: Format) -> Result<(), Error> {
+        let path_str = path.to_string_lossy();
+        let (tool, args) = format.optimizer(&path_str);
+        let size_before = file_size(path)?;
+
+        if self.run_tool(path, tool, args)? {
+            let size_after = file_size(path)?;
+
+            println!(
+                "[Image] {}: {} -> {}",
+                file_name(path),
+                size_report::format_size(size_before),
+                size_report::format_size(size_after)
+            );
+
+            self.warn_if_large(path, size_after);
+        } else {
+            self.warn_if_large(path, size_before);
+        }
+
+        Ok(())
+    }
+
+    // Variants are written next to the image, `logo.png` gets `logo.webp`. Existing files are kept
+    fn convert(
+        &mut self,
+        path: &Path,
+        ext: &str,
+        tool: &'static str,
+        args: &[&str],
+    ) -> Result<(), Error> {
+        let output = path.with_extension(ext);
+
+        if output.exists() {
+            return Ok(());
+        }
+
+        let path_str = path.to_string_lossy();
+        let output_str = output.to_string_lossy();
+        let mut tool_args = exec::to_args(args);
+
+        // cwebp takes the output with -o, avifenc as the last positional arg
+        if tool == "cwebp" {
+            tool_args.insert(0, path_str.to_string());
+            tool_args.push(output_str.to_string());
+        } else {
+            tool_args.extend([path_str.to_string(), output_str.to_string()]);
+        }
+
+        if self.run_tool(path, tool, tool_args)? {
+            println!(
+                "[Image] {}: {}",
+                file_name(&output),
+                size_report::format_size(file_size(&output)?)
+            );
+        }
+
+        Ok(())
+    }
+
+    // Returns false when the tool isn't installed
+    fn run_tool(
+        &mut self,
+        path: &Path,
+        tool: &'static str,
+        args: Vec<String>,
+    ) -> Result<bool, Error> {
+        if !exec::is_on_path(tool) {
+            if self.missing_tools.insert(tool) {
+                eprintln!(
+                    "Warning: {} not found on PATH, skipping it for {}",
+                    tool,
+                    file_name(path)
+                );
+            }
+
+            return Ok(false);
+        }
+
+        exec::run(&exec::Config {
+            work_dir: self.config.dist_path.clone(),
+            cmd: tool.into(),
+            args,
+            env: Vec::new(),
+            env_mode: exec::EnvMode::Inherit,
+            timeout: None,
+        })
+        .map_err(Error::Exec)?;
+
+        Ok(true)
+    }
+
+    fn warn_if_large(&self, path: &Path, size: u64) {
+        if size > self.config.warn_size_kib * 1024 {
+            eprintln!(
+                "Warning: {} is {}, larger than warn_size_kib = {} in [assets.images]",
+                file_name(path),
+                size_report::format_size(size),
+                self.config.warn_size_kib
+            );
+        }
+    }
+}
+
+fn file_size(path: &Path) -> Result<u64, Error> {
+    fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|err| Error::ReadImage(path.to_path_buf(), err))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
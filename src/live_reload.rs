@@ -0,0 +1,169 @@
+use crate::dev_session;
+use sha1::Digest;
+use sha1::Sha1;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const RELOAD_PATH: &str = "/_poly/reload";
+pub const RELOAD_MESSAGE: &str = "reload";
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// Sockets that fall further behind than this miss messages
+const MAX_QUEUED_MESSAGES: usize = 64;
+
+#[derive(Debug, Default)]
+struct Messages {
+    last_id: u64,
+    recent: VecDeque<(u64, String)>,
+}
+
+// Shared by all dev sockets of a serve process, every socket sends each broadcast message
+#[derive(Debug, Default)]
+pub struct Hub {
+    messages: Mutex<Messages>,
+    changed: Condvar,
+    connections: AtomicUsize,
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn last_id(&self) -> u64 {
+        self.messages
+            .lock()
+            .map(|messages| messages.last_id)
+            .unwrap_or_default()
+    }
+
+    pub fn broadcast(&self, message: &str) {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.last_id += 1;
+            let id = messages.last_id;
+            messages.recent.push_back((id, message.to_string()));
+
+            if messages.recent.len() > MAX_QUEUED_MESSAGES {
+                messages.recent.pop_front();
+            }
+
+            self.changed.notify_all();
+        }
+    }
+
+    // Returns the messages after `seen` once there are any, or none when the timeout expires
+    pub fn wait_for_messages(&self, seen: u64, timeout: Duration) -> (u64, Vec<String>) {
+        let messages = match self.messages.lock() {
+            Ok(messages) => messages,
+            Err(_) => return (seen, Vec::new()),
+        };
+
+        match self
+            .changed
+            .wait_timeout_while(messages, timeout, |messages| messages.last_id == seen)
+        {
+            Ok((messages, _)) => {
+                let new_messages = messages
+                    .recent
+                    .iter()
+                    .filter(|(id, _)| *id > seen)
+                    .map(|(_, message)| message.clone())
+                    .collect();
+
+                (messages.last_id, new_messages)
+            }
+
+            Err(_) => (seen, Vec::new()),
+        }
+    }
+
+    pub fn connected(&self) {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn disconnected(&self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn has_connections(&self) -> bool {
+        self.connections.load(Ordering::SeqCst) > 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub url: String,
+    pub session_dir: PathBuf,
+    pub port: u32,
+}
+
+impl Config {
+    pub fn from_port(session_dir: &Path, port: u32) -> Self {
+        Self {
+            url: format!("http://127.0.0.1:{}{}", port, RELOAD_PATH),
+            session_dir: session_dir.to_path_buf(),
+            port,
+        }
+    }
+}
+
+// Used by `poly watch` to tell a running `poly serve --dev` to reload the page
+#[derive(Debug, Clone)]
+pub struct LiveReload {
+    config: Config,
+}
+
+impl LiveReload {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    // Nothing to reload when serve isn't running, so failures are ignored. The token
+    // is read on every notify since serve makes a new one each time it starts
+    pub fn notify(&self) {
+        let token = match dev_session::read_token(&self.config.session_dir, self.config.port) {
+            Some(token) => token,
+            None => return,
+        };
+
+        let _ = ureq::post(&self.config.url)
+            .timeout(Duration::from_secs(1))
+            .set(dev_session::TOKEN_HEADER, &token)
+            .send_string("");
+    }
+}
+
+pub fn websocket_accept_key(key: &str) -> String {
+    let digest = Sha1::digest(format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes());
+    base64::encode(digest)
+}
+
+// Server frames are never masked
+pub fn websocket_text_frame(text: &str) -> Vec<u8> {
+    let len = text.len();
+    let mut frame = vec![0x81];
+
+    if len < 126 {
+        frame.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+pub fn websocket_ping_frame() -> Vec<u8> {
+    vec![0x89, 0]
+}
@@ -1,36 +1,116 @@
+mod access_log;
+mod artifact_uploader;
 mod asset_hasher;
 mod backlog_builder;
 mod build;
+mod build_log;
+mod build_pipeline;
+mod change_analyzer;
 mod cleaner;
+mod dev_client;
+mod dev_console;
+mod dev_session;
 mod exec;
+mod hook_action;
+mod image_optimizer;
+mod live_reload;
+mod monorepo;
+mod packager;
+mod plugin;
+mod poly_config;
+mod preflight;
 mod project;
 mod project_info;
+mod project_info_cache;
+mod proxy;
+mod route_report;
 mod rust_builder;
+mod sandbox;
+mod script_pins;
 mod script_runner;
 mod serve;
+mod size_report;
+mod target_matrix;
+mod template_archive;
+mod template_cache;
+mod template_lock;
+mod template_manifest;
+mod template_record;
+mod tls;
+mod user_config;
 mod util;
 mod watch;
 mod web_builder;
+mod webhook;
+mod workspace;
 
+use crate::artifact_uploader::ArtifactUploader;
 use crate::asset_hasher::AssetHasher;
 use crate::backlog_builder::BacklogBuilder;
-use crate::build::Runner;
+use crate::build_log::BuildLog;
+use crate::build_pipeline::Pipeline;
+use crate::change_analyzer::ChangeAnalyzer;
 use crate::cleaner::Cleaner;
+use crate::hook_action::Action;
+use crate::image_optimizer::ImageOptimizer;
+use crate::live_reload::LiveReload;
+use crate::packager::Packager;
+use crate::plugin::PluginStep;
+use crate::poly_config::PolyConfig;
+use crate::preflight::Scope;
 use crate::project::Project;
+use crate::route_report::RouteReport;
 use crate::rust_builder::RustBuilder;
+use crate::sandbox::Sandbox;
+use crate::script_runner::Event;
+use crate::script_runner::Hooks;
 use crate::script_runner::ScriptRunner;
+use crate::target_matrix::TargetMatrix;
+use crate::template_lock::TemplateLock;
+use crate::template_record::TemplateRecord;
+use crate::user_config::UserConfig;
 use crate::web_builder::WebBuilder;
+use crate::webhook::BuildEvent;
+use crate::webhook::Notifier;
 use build::Env;
+use build::Profile;
 use clap::{Parser, Subcommand};
 use project_info::ProjectInfo;
-use std::{path::PathBuf, process};
+use std::time::Instant;
+use std::{path::Path, path::PathBuf, process};
 
 #[derive(Debug, Parser)]
 #[clap(name = "poly")]
 #[clap(about = "CLI helper for working with poly projects", long_about = None)]
+#[clap(after_help = "ENVIRONMENT:
+    POLY_DIST_DIR, POLY_WEB_DIR, POLY_CORE_DIR, POLY_WASM_DIR, POLY_CLOUDFLARE_DIR
+            Override the dirs in [layout] of poly.toml, relative to the project dir
+    POLY_PORT
+            Override the port of every [serve] preset
+
+    Command line flags take precedence over these variables, which take precedence over
+    poly.toml. Hooks, plugins and npm get the dist dir of the build as POLY_OUT_DIR")]
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Config file to use instead of poly.toml in the current dir
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// App of a monorepo to work on, as listed in [apps] of the root poly.toml
+    #[clap(long, global = true)]
+    app: Option<String>,
+
+    /// Print the commands, dirs and environment of each step instead of running them,
+    /// supported by build, watch, clean and upgrade
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Don't check that cargo, wasm-pack and npm are installed and recent enough before
+    /// building
+    #[clap(long, global = true)]
+    skip_preflight: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -38,8 +118,52 @@ enum Commands {
     /// Create a new project
     #[clap(arg_required_else_help = true)]
     New {
-        /// Post build script to run after build
+        /// Project name, or . to create the project in the current dir
         name: String,
+
+        /// Dir to create the project in, must be empty if it exists
+        #[clap(long)]
+        dir: Option<PathBuf>,
+
+        /// Template name (see `poly template list`) or git repository url (<url>[#<ref>[:<subdir>]])
+        #[clap(long, default_value = "counter-tailwind")]
+        template: String,
+
+        /// Download the template even if it's cached
+        #[clap(long)]
+        refresh: bool,
+
+        /// Value for a placeholder declared in the template manifest, <name>=<value>
+        #[clap(long = "var", parse(try_from_str = parse_var))]
+        vars: Vec<(String, String)>,
+
+        /// Run the post create commands of the template without asking
+        #[clap(long)]
+        yes: bool,
+
+        /// Don't run the post create commands of the template
+        #[clap(long, conflicts_with = "yes")]
+        no_hooks: bool,
+
+        /// Run the post create commands in a sandbox without network access, needs bubblewrap
+        #[clap(long, conflicts_with = "no-hooks")]
+        sandbox: bool,
+
+        /// Css framework, for templates with css variants
+        #[clap(long, possible_values = &["tailwind", "vanilla", "none"])]
+        css: Option<String>,
+
+        /// Init a git repo with a .gitignore and an initial commit (default)
+        #[clap(long, overrides_with = "no-git")]
+        git: bool,
+
+        /// Don't init a git repo
+        #[clap(long, overrides_with = "git")]
+        no_git: bool,
+
+        /// Run npm install and cargo fetch in the new project
+        #[clap(long)]
+        install: bool,
     },
 
     Add {
@@ -47,6 +171,26 @@ enum Commands {
         command: AddCommand,
     },
 
+    /// Rename the project, its crates and packages
+    #[clap(arg_required_else_help = true)]
+    Rename {
+        /// New project name
+        name: String,
+    },
+
+    /// Apply changes of the template the project was created from
+    Upgrade {
+        /// Download the template even if it's cached
+        #[clap(long)]
+        refresh: bool,
+    },
+
+    /// Project templates
+    Template {
+        #[clap(subcommand)]
+        command: TemplateCommand,
+    },
+
     /// Build the project
     #[clap(arg_required_else_help = false)]
     Build {
@@ -58,17 +202,61 @@ enum Commands {
         #[clap(long)]
         hash_assets: bool,
 
-        /// Post build script to run after build
+        /// Keep unhashed copies of assets renamed with filename versioning
+        #[clap(long, requires = "hash-assets")]
+        hash_keep_original: bool,
+
+        /// Fail when dist files reference missing assets after hashing, instead of warning
+        #[clap(long, requires = "hash-assets")]
+        strict: bool,
+
+        /// Deprecated, list post build scripts under [hooks] in poly.toml instead
         #[clap(long)]
         script: Option<String>,
+
+        /// Upload dist to the storage configured in poly.toml
+        #[clap(long)]
+        upload: bool,
+
+        /// Also build SIMD and threads variants of the wasm with a feature detecting loader,
+        /// the threads variants require a nightly toolchain
+        #[clap(long)]
+        target_matrix: bool,
+
+        /// Pin the current checksums of the post build script and enabled plugins in poly.toml
+        #[clap(long)]
+        accept_script_changes: bool,
+
+        /// Number of independent build jobs run at the same time, like the variants of
+        /// --target-matrix, defaults to the number of cpus
+        #[clap(long)]
+        jobs: Option<usize>,
+    },
+
+    /// Upload dist or a single artifact to object storage
+    Upload {
+        /// Upload this file instead of the dist dir
+        #[clap(long)]
+        file: Option<PathBuf>,
+    },
+
+    /// Package dist into a reproducible .tar.zst archive
+    Package {
+        /// Archive path, defaults to <project name>.tar.zst
+        #[clap(long)]
+        output: Option<PathBuf>,
     },
 
     /// Watch for changes and build
     #[clap(arg_required_else_help = false)]
     Watch {
-        /// Post build script to run after build
+        /// Deprecated, list post build scripts under [hooks] in poly.toml instead
         #[clap(long)]
         script: Option<String>,
+
+        /// Pin the current checksums of the post build script and enabled plugins in poly.toml
+        #[clap(long)]
+        accept_script_changes: bool,
     },
 
     Serve {
@@ -83,48 +271,357 @@ enum Commands {
         /// Additional response headers
         #[clap(long)]
         header: Vec<String>,
+
+        /// Add a named set of response headers, "security" or a preset from poly.toml
+        #[clap(long)]
+        headers_preset: Vec<String>,
+
+        /// Serve preset from poly.toml
+        #[clap(long)]
+        preset: Option<String>,
+
+        /// Port to listen on
+        #[clap(long)]
+        port: Option<u32>,
+
+        /// Serve index.html for unknown page paths
+        #[clap(long)]
+        spa: bool,
+
+        /// Gzip compress responses
+        #[clap(long)]
+        compress: bool,
+
+        /// Cache-Control by glob, e.g. "*.wasm=public,max-age=31536000,immutable"
+        #[clap(long)]
+        cache: Vec<String>,
+
+        /// Serve over https with a cached self-signed certificate
+        #[clap(long)]
+        https: bool,
+
+        /// Certificate to use for https (PEM)
+        #[clap(long, requires = "key")]
+        cert: Option<PathBuf>,
+
+        /// Private key to use for https (PEM)
+        #[clap(long, requires = "cert")]
+        key: Option<PathBuf>,
+
+        /// Forward requests under a path prefix to a backend, e.g. "/api=http://localhost:3000"
+        #[clap(long)]
+        proxy: Vec<String>,
+
+        /// Page to serve for missing files, defaults to 404.html in the static dir
+        #[clap(long)]
+        not_found_page: Option<PathBuf>,
+
+        /// Page to serve when a request fails, defaults to 500.html in the static dir
+        #[clap(long)]
+        error_page: Option<PathBuf>,
+
+        /// Log requests to stdout, or append them to the given file
+        #[clap(long)]
+        access_log: Option<Option<PathBuf>>,
+
+        /// Access log format: common, combined or json
+        #[clap(long, default_value = "combined")]
+        access_log_format: access_log::Format,
+
+        /// Allow cross-origin requests from these origins, or from any origin without a value
+        #[clap(long, min_values = 0, use_value_delimiter = true)]
+        cors: Option<Vec<String>>,
+
+        /// Inject the dev client into html, forwards browser errors and panics to this terminal
+        /// and reloads the page after `poly watch` rebuilds
+        #[clap(long)]
+        dev: bool,
+    },
+
+    Routes {
+        #[clap(subcommand)]
+        command: RoutesCommand,
+    },
+
+    Logs {
+        #[clap(subcommand)]
+        command: LogsCommand,
+    },
+
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Remove build output, the dist dirs and generated wasm by default
+    Clean {
+        /// Only remove build output (default)
+        #[clap(long, conflicts_with = "all")]
+        dist: bool,
+
+        /// Also remove target/ and node_modules/
+        #[clap(long)]
+        all: bool,
+    },
+
+    /// Run cargo check on all workspace members, including non-frontend ones
+    Check,
+
+    /// Run cargo test on all workspace members, including non-frontend ones
+    Test,
+
+    /// Evaluate JavaScript in the page served by `poly serve --dev`
+    Console {
+        /// Port of the running serve, defaults to the one `poly serve` would use
+        #[clap(long)]
+        port: Option<u32>,
     },
 }
 
 #[derive(Debug, Subcommand)]
 enum AddCommand {
-    /// Create a new project
+    /// Add a page and register it in the marked route and entry point lists
     #[clap(arg_required_else_help = true)]
     Page {
         /// Page name
         name: String,
+
+        /// Download the template even if it's cached
+        #[clap(long)]
+        refresh: bool,
+    },
+
+    /// Add a view component to the core project
+    #[clap(arg_required_else_help = true)]
+    Component {
+        /// Component name, like UserCard
+        name: String,
+
+        /// Also add a typescript module to the web project
+        #[clap(long)]
+        ts: bool,
+    },
+
+    /// Add a request handler to the cloudflare worker
+    #[clap(arg_required_else_help = true)]
+    Worker {
+        /// Handler name, like UserProfile
+        name: String,
+
+        /// Route of the handler, defaults to /api/<name>
+        #[clap(long)]
+        route: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TemplateCommand {
+    /// List built-in templates and templates from the user config
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Print the loaded config and the file it was loaded from
+    Print,
+}
+
+#[derive(Debug, Subcommand)]
+enum LogsCommand {
+    /// List archived build logs
+    List,
+
+    /// Show an archived build log
+    Show {
+        /// Show the latest build (default)
+        #[clap(long)]
+        last: bool,
+
+        /// Show the build with this id
+        #[clap(long, conflicts_with = "last")]
+        build: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum RoutesCommand {
+    /// Show which files belong to each route
+    Report {
+        /// Output as json
+        #[clap(long)]
+        json: bool,
     },
 }
 
 fn main() {
     let args = Cli::parse();
+    // Made absolute since commands run in the project root
+    let config_file = args.config.map(|path| get_current_dir().join(path));
+    let config_file = config_file.as_deref();
+    let app = args.app.as_deref();
+    let dry_run = args.dry_run;
+    let skip_preflight = args.skip_preflight;
+
+    if dry_run && !supports_dry_run(&args.command) {
+        eprintln!("--dry-run is only supported by build, watch, clean and upgrade");
+        process::exit(1);
+    }
 
     match args.command {
-        Commands::New { name } => {
+        Commands::New {
+            name,
+            dir,
+            template,
+            refresh,
+            vars,
+            yes,
+            no_hooks,
+            sandbox,
+            css,
+            git: _,
+            no_git,
+            install,
+        } => {
             let current_dir = get_current_dir();
+
+            // `poly new .` names the project after the current dir
+            let (name, dir) = match (name.as_str(), dir) {
+                (".", Some(_)) => {
+                    eprintln!("Use either . or --dir");
+                    process::exit(1);
+                }
+
+                (".", None) => {
+                    let dir_name = current_dir
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .unwrap_or_default()
+                        .to_string();
+
+                    (dir_name, Some(current_dir.clone()))
+                }
+
+                (_, dir) => (name, dir),
+            };
+
+            let name = normalize_project_name(&name);
+
+            let template = resolve_template(&template).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+
             let project = Project::new(project::Config {
                 current_dir,
                 name: name.clone(),
-                template: project::Template::CounterTailwind,
+                template,
+                refresh_template: refresh,
+                vars,
+                post_create: if yes {
+                    project::PostCreate::Run
+                } else if no_hooks {
+                    project::PostCreate::Skip
+                } else {
+                    project::PostCreate::Ask
+                },
+                sandbox_post_create: sandbox || load_user_config().sandbox_post_create,
+                css,
+                git: !no_git,
+                install,
+                dest_dir: dir,
             });
 
-            let res = project.create();
-            println!("{:?}", res);
+            if let Err(err) = project.create() {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+
+        Commands::Upgrade { refresh } => {
+            let current_dir = get_project_dir(app);
+            let project_info = load_project_info(&current_dir, config_file, Scope::Project);
+            let record = TemplateRecord::read(&current_dir).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+            let lock = TemplateLock::read(&current_dir).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+
+            let project = Project::new(project::Config {
+                current_dir: current_dir.clone(),
+                name: project_info.project_name.clone(),
+                template: project::Template::from_record(&record, &lock),
+                refresh_template: refresh,
+                vars: record.vars,
+                post_create: project::PostCreate::Skip,
+                sandbox_post_create: false,
+                css: record.css,
+                git: false,
+                install: false,
+                dest_dir: Some(current_dir),
+            });
+
+            if let Err(err) = project.upgrade(dry_run) {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
         }
 
         Commands::Add { command } => {
             // fmt
             match command {
-                AddCommand::Page { name } => {
-                    let current_dir = get_current_dir();
-                    let project_info = ProjectInfo::from_dir(&current_dir).unwrap();
+                AddCommand::Page { name, refresh } => {
+                    let current_dir = get_project_dir(app);
+                    let project_info = load_project_info(&current_dir, config_file, Scope::Project);
                     let project = Project::new(project::Config {
                         current_dir: current_dir.clone(),
                         name: project_info.project_name.clone(),
                         template: project::Template::CounterTailwind,
+                        refresh_template: refresh,
+                        vars: vec![],
+                        post_create: project::PostCreate::Skip,
+                        sandbox_post_create: false,
+                        css: None,
+                        git: false,
+                        install: false,
+                        dest_dir: None,
                     });
-                    let res = project.add_page(&project_info, &name);
-                    println!("{:?}", res);
+                    if let Err(err) = project.add_page(&project_info, &name) {
+                        eprintln!("{}", err);
+                        process::exit(1);
+                    }
+                }
+
+                AddCommand::Component { name, ts } => {
+                    let current_dir = get_project_dir(app);
+                    let project_info = load_project_info(&current_dir, config_file, Scope::Project);
+                    let poly_config = load_poly_config(&current_dir, config_file);
+                    let project = scaffold_project(&current_dir, &project_info);
+                    let templates_dir = current_dir.join(&poly_config.scaffold.templates);
+
+                    if let Err(err) =
+                        project.add_component(&project_info, &name, ts, &templates_dir)
+                    {
+                        eprintln!("{}", err);
+                        process::exit(1);
+                    }
+                }
+
+                AddCommand::Worker { name, route } => {
+                    let current_dir = get_project_dir(app);
+                    let project_info = load_project_info(&current_dir, config_file, Scope::Project);
+                    let poly_config = load_poly_config(&current_dir, config_file);
+                    let project = scaffold_project(&current_dir, &project_info);
+                    let templates_dir = current_dir.join(&poly_config.scaffold.templates);
+
+                    if let Err(err) =
+                        project.add_worker(&project_info, &name, route.as_deref(), &templates_dir)
+                    {
+                        eprintln!("{}", err);
+                        process::exit(1);
+                    }
                 }
             }
         }
@@ -133,123 +630,240 @@ fn main() {
             script,
             release,
             hash_assets,
+            hash_keep_original,
+            strict,
+            upload,
+            target_matrix,
+            accept_script_changes,
+            jobs,
         } => {
             let env = if release { Env::Release } else { Env::Dev };
-            let current_dir = get_current_dir();
-            let project_info = ProjectInfo::from_dir(&current_dir).unwrap();
+            let current_dir = get_project_dir(app);
+            let project_info = load_project_info(
+                &current_dir,
+                config_file,
+                build_scope(dry_run, skip_preflight),
+            );
+            let profile = Profile::from_project_info(env, &project_info, hash_assets);
 
             print_project_info(&project_info);
 
+            let mut poly_config = load_poly_config(&current_dir, config_file);
+            if accept_script_changes {
+                pin_scripts(&current_dir, &mut poly_config, script.as_deref());
+            }
+
             let cleaner = Cleaner::new(cleaner::Config::from_project_info(&project_info));
 
-            let rust_builder =
-                RustBuilder::new(rust_builder::Config::from_project_info(&env, &project_info));
+            let rust_builder = RustBuilder::new(rust_builder::Config::from_project_info(
+                &profile,
+                &project_info,
+                &poly_config,
+            ));
+
+            let web_builder = WebBuilder::new(web_builder::Config::from_project_info(
+                &profile,
+                &project_info,
+                &poly_config,
+            ));
 
-            let web_builder =
-                WebBuilder::new(web_builder::Config::from_project_info(&env, &project_info));
+            let plugins = discover_plugins(&current_dir, &profile, &poly_config);
+            print_plugins(&plugins);
 
-            cleaner.run().expect("Cleaner failed");
+            let target_matrix = target_matrix.then(|| {
+                TargetMatrix::new(target_matrix::Config::from_project_info(
+                    &profile,
+                    &project_info,
+                    jobs.unwrap_or_else(build::default_jobs),
+                ))
+            });
 
-            if let Err(err) = rust_builder.run() {
-                eprintln!("Rust build failed: {}", err);
-                process::exit(1);
-            }
+            let hooks = load_hooks(&current_dir, &poly_config, &profile, script.as_deref());
 
-            if let Err(err) = web_builder.run() {
-                eprintln!("Web build failed: {}", err);
-                process::exit(1);
+            let image_optimizer = poly_config.assets.images.optimize.then(|| {
+                ImageOptimizer::new(image_optimizer::Config::from_project_info(
+                    &project_info,
+                    &poly_config.assets.images,
+                ))
+            });
+
+            let asset_hasher = hash_assets.then(|| {
+                AssetHasher::new(asset_hasher::Config {
+                    keep_original: hash_keep_original,
+                    strict,
+                    ..asset_hasher::Config::from_project_info(&project_info, &poly_config)
+                })
+            });
+
+            let uploader = upload.then(|| {
+                artifact_uploader(&project_info, &poly_config).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                })
+            });
+
+            let mut pipeline = Pipeline {
+                dist_path: &project_info.dist_path,
+                cleaner: &cleaner,
+                rust_builder: &rust_builder,
+                web_builder: &web_builder,
+                target_matrix: target_matrix.as_ref(),
+                plugins: &plugins,
+                hooks: &hooks,
+                image_optimizer,
+                asset_hasher,
+                uploader,
+            };
+
+            if dry_run {
+                pipeline.print_plan();
+                return;
             }
 
-            if let Some(script_name) = &script {
-                let script_path = current_dir.join(script_name);
-                let script_runner = ScriptRunner::new(script_path, &env);
-                script_runner
-                    .run(script_runner::Event::BeforeAssetHash)
-                    .expect("Post build runner failed");
+            let build_log = start_build_log(&current_dir, &poly_config);
+            let notifier = Notifier::new(webhook::Config::from_project_info(
+                &profile,
+                &project_info,
+                &poly_config.webhooks,
+                build_log.as_ref().map(BuildLog::id),
+            ));
+
+            run_pipeline(&mut pipeline, &notifier, build_log.as_ref());
+        }
+
+        Commands::Upload { file } => {
+            let file = file.map(|file| get_current_dir().join(file));
+            let current_dir = get_project_dir(app);
+            let project_info = load_project_info(&current_dir, config_file, Scope::Project);
+            let poly_config = load_poly_config(&current_dir, config_file);
+
+            if let Err(err) = upload_artifacts(&project_info, &poly_config, file.as_deref()) {
+                eprintln!("{}", err);
+                process::exit(1);
             }
+        }
 
-            if hash_assets {
-                let asset_hasher =
-                    AssetHasher::new(asset_hasher::Config::from_project_info(&project_info));
+        Commands::Package { output } => {
+            let output = output.map(|output| get_current_dir().join(output));
+            let current_dir = get_project_dir(app);
+            let project_info = load_project_info(&current_dir, config_file, Scope::Project);
+            let packager = Packager::new(packager::Config::from_project_info(
+                &current_dir,
+                &project_info,
+                output,
+            ));
 
-                hash_assets_helper(
-                    &asset_hasher,
-                    &rust_builder,
-                    &web_builder,
-                    &script,
-                    &current_dir,
-                    &env,
-                );
+            match packager.run() {
+                Ok(package) => {
+                    println!(
+                        "Packaged {} file(s) into {}",
+                        package.file_count,
+                        package.archive_path.display()
+                    );
+                    println!("[sha256] {}", package.sha256);
+                    println!("[Checksum file] {}", package.checksum_path.display());
+                }
 
-                // Hash again now that assets contains the correct hash
-                hash_assets_helper(
-                    &asset_hasher,
-                    &rust_builder,
-                    &web_builder,
-                    &script,
-                    &current_dir,
-                    &env,
-                );
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
             }
         }
 
-        Commands::Watch { script } => {
-            let env = Env::Dev;
-            let current_dir = get_current_dir();
-            let project_info = ProjectInfo::from_dir(&current_dir).unwrap();
+        Commands::Watch {
+            script,
+            accept_script_changes,
+        } => {
+            let current_dir = get_project_dir(app);
+            let project_info = load_project_info(
+                &current_dir,
+                config_file,
+                build_scope(dry_run, skip_preflight),
+            );
+            let profile = Profile::from_project_info(Env::Dev, &project_info, false);
 
             print_project_info(&project_info);
 
+            let mut poly_config = load_poly_config(&current_dir, config_file);
+            if accept_script_changes {
+                pin_scripts(&current_dir, &mut poly_config, script.as_deref());
+            }
+
             let cleaner = Cleaner::new(cleaner::Config::from_project_info(&project_info));
 
             let rust_builder = rust_builder::RustBuilder::new(
-                rust_builder::Config::from_project_info(&env, &project_info),
+                rust_builder::Config::from_project_info(&profile, &project_info, &poly_config),
             );
 
             let web_builder = web_builder::WebBuilder::new(web_builder::Config::from_project_info(
-                &env,
+                &profile,
                 &project_info,
+                &poly_config,
             ));
 
-            let post_build_runner = if let Some(script_name) = script {
-                let script_path = current_dir.join(script_name);
-                if script_path.exists() {
-                    Some(ScriptRunner::new(script_path, &env))
-                } else {
-                    eprintln!("Could not find script: {}", script_path.display());
-                    None
-                }
-            } else {
-                None
+            let plugins = discover_plugins(&current_dir, &profile, &poly_config);
+            print_plugins(&plugins);
+
+            let build_log = start_build_log(&current_dir, &poly_config);
+            let notifier = Notifier::new(webhook::Config::from_project_info(
+                &profile,
+                &project_info,
+                &poly_config.webhooks,
+                build_log.as_ref().map(BuildLog::id),
+            ));
+
+            let hooks = load_hooks(&current_dir, &poly_config, &profile, script.as_deref());
+
+            // Watch never hashes, optimizes images or uploads
+            let mut pipeline = Pipeline {
+                dist_path: &project_info.dist_path,
+                cleaner: &cleaner,
+                rust_builder: &rust_builder,
+                web_builder: &web_builder,
+                target_matrix: None,
+                plugins: &plugins,
+                hooks: &hooks,
+                image_optimizer: None,
+                asset_hasher: None,
+                uploader: None,
             };
 
-            // Do initial build
-            cleaner.run().expect("Cleaner failed");
+            if dry_run {
+                pipeline.print_plan();
 
-            if let Err(err) = rust_builder.run() {
-                eprintln!("Rust build failed: {}", err);
-                process::exit(1);
-            }
+                println!(
+                    "[watch] Would watch {} and rebuild on changes",
+                    current_dir.display()
+                );
 
-            if let Err(err) = web_builder.run() {
-                eprintln!("Web build failed: {}", err);
-                process::exit(1);
+                return;
             }
 
-            post_build_runner.as_ref().map(|runner| {
-                runner
-                    .run(script_runner::Event::BeforeAssetHash)
-                    .expect("Post build runner failed")
-            });
+            // Do initial build
+            run_pipeline(&mut pipeline, &notifier, build_log.as_ref());
+
+            let live_reload = LiveReload::new(live_reload::Config::from_port(
+                &dev_session::session_dir(&current_dir),
+                serve_port(&current_dir, &poly_config),
+            ));
+            live_reload.notify();
 
             let builder = BacklogBuilder::new(backlog_builder::Config {
                 rust_builder,
                 web_builder,
-                post_build_runner,
+                hooks,
+                plugins,
+                notifier,
+                live_reload,
+                change_analyzer: ChangeAnalyzer::new(&change_analyzer::Config::from_project_info(
+                    &current_dir,
+                    &project_info,
+                )),
             });
 
             println!("Watching for changes...");
-            let watcher_config = watch::Config::new(&current_dir, builder);
+            let watcher_config = watch::Config::new(&current_dir, &project_info, builder);
             watch::watch(watcher_config);
         }
 
@@ -257,49 +871,775 @@ fn main() {
             static_,
             routes,
             header,
+            headers_preset,
+            preset,
+            port,
+            spa,
+            compress,
+            cache,
+            https,
+            cert,
+            key,
+            proxy,
+            not_found_page,
+            error_page,
+            access_log,
+            access_log_format,
+            cors,
+            dev,
         } => {
-            let default_path = get_current_dir().join("dist");
-            let static_base_path = static_.unwrap_or(default_path);
-            let parsed_routes = routes
-                .map(|path| serve::read_routes(&path))
+            let current_dir = get_current_dir();
+            let poly_config = load_poly_config(&current_dir, config_file);
+            let serve_preset = poly_config
+                .serve_preset(preset.as_deref())
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                });
+
+            let default_path = poly_config.layout.dist_path(&current_dir);
+            let static_base_path = static_.or(serve_preset.static_).unwrap_or(default_path);
+            let routes_path = routes.or(serve_preset.routes);
+            let parsed_routes = routes_path
+                .as_ref()
+                .map(serve::read_routes)
+                .unwrap_or_default();
+            let redirects = routes_path
+                .as_ref()
+                .map(serve::read_redirects)
                 .unwrap_or_default();
 
+            // Command line rules take precedence since the first match wins
+            let cache_rules = serve::parse_cache_rules(&[cache, serve_preset.cache].concat())
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                });
+
+            let cert_files = match (cert.or(serve_preset.cert), key.or(serve_preset.key)) {
+                (Some(cert_path), Some(key_path)) => Some(Ok(tls::CertFiles {
+                    cert_path,
+                    key_path,
+                })),
+
+                _ if https || serve_preset.https.unwrap_or(false) => {
+                    Some(tls::self_signed(&current_dir.join(".poly").join("tls")))
+                }
+
+                _ => None,
+            };
+
+            let tls_config = cert_files
+                .map(|files| files.and_then(|files| tls::server_config(&files)))
+                .transpose()
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                });
+
+            // Headers given one by one override the ones from presets
+            let preset_headers = serve::preset_headers(
+                &[serve_preset.headers_preset, headers_preset].concat(),
+                &poly_config.header_presets,
+                tls_config.is_some(),
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+
+            let proxy_rules = proxy::parse_rules(&[serve_preset.proxy, proxy].concat())
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                });
+
+            let error_pages = serve::ErrorPages::new(
+                &static_base_path,
+                not_found_page.or(serve_preset.not_found_page),
+                error_page.or(serve_preset.error_page),
+            );
+
+            let access_log = access_log.map(|path| {
+                access_log::AccessLog::new(access_log_format, path.as_deref()).unwrap_or_else(
+                    |err| {
+                        eprintln!("Failed to open access log: {}", err);
+                        process::exit(1);
+                    },
+                )
+            });
+
+            // Serving works outside of projects too, the profile is made from the served dir
+            let profile = Profile {
+                env: Env::Dev,
+                project_name: None,
+                git_sha: build::git_sha(),
+                dist_path: static_base_path.clone(),
+                hash_assets: false,
+            };
+            let hooks = load_hooks(&current_dir, &poly_config, &profile, None);
+
+            if let Err(err) = hooks.run(Event::PreServe) {
+                eprintln!("The {} hook failed: {}", Event::PreServe, err);
+                process::exit(1);
+            }
+
             let config = serve::Config {
                 static_base_path,
                 routes: parsed_routes,
-                response_headers: header,
+                redirects,
+                response_headers: [preset_headers, serve_preset.headers, header].concat(),
+                port: port.or(serve_preset.port),
+                spa: spa || serve_preset.spa.unwrap_or(false),
+                compress: compress || serve_preset.compress.unwrap_or(false),
+                cache_rules,
+                tls: tls_config,
+                dev_client: dev || serve_preset.dev.unwrap_or(false),
+                dev_session_dir: dev_session::session_dir(
+                    &project_info::find_project_root(&current_dir).unwrap_or(current_dir),
+                ),
+                proxy: proxy::Proxy::new(proxy_rules),
+                cors: cors.or(serve_preset.cors).map(|origins| serve::Cors {
+                    allowed_origins: if origins.is_empty() {
+                        vec!["*".to_string()]
+                    } else {
+                        origins
+                    },
+                }),
+                error_pages,
+                access_log,
             };
 
             if let Err(err) = serve::start(&config) {
                 eprintln!("Error: {:?}", err);
             }
         }
+
+        Commands::Clean { dist: _, all } => {
+            let current_dir = get_project_dir(app);
+            let project_info = load_project_info(&current_dir, config_file, Scope::Project);
+            let cleaner = Cleaner::new(cleaner::Config::from_project_info(&project_info));
+            let scope = if all {
+                cleaner::Scope::All
+            } else {
+                cleaner::Scope::Dist
+            };
+
+            match cleaner.clean(scope, dry_run) {
+                Ok(paths) => {
+                    let action = if dry_run { "Would remove" } else { "Removed" };
+
+                    for path in &paths {
+                        println!("{} {}", action, path.display());
+                    }
+
+                    if paths.is_empty() {
+                        println!("Nothing to clean");
+                    }
+                }
+
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            }
+        }
+
+        Commands::Check => run_workspace_task(workspace::Task::Check, config_file, app),
+
+        Commands::Test => run_workspace_task(workspace::Task::Test, config_file, app),
+
+        Commands::Console { port } => {
+            let current_dir = get_project_dir(app);
+            let poly_config = load_poly_config(&current_dir, config_file);
+            let port = port.unwrap_or_else(|| serve_port(&current_dir, &poly_config));
+
+            let config =
+                dev_console::Config::from_port(&dev_session::session_dir(&current_dir), port);
+
+            if let Err(err) = dev_console::run(&config) {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+
+        Commands::Logs { command } => {
+            let current_dir = get_project_dir(app);
+            let poly_config = load_poly_config(&current_dir, config_file);
+            let config = build_log::Config::new(&current_dir, poly_config.logs.keep);
+
+            let result = match command {
+                LogsCommand::List => build_log::list(&config).map(|entries| {
+                    for entry in entries {
+                        let duration = entry
+                            .duration_ms
+                            .map(|ms| format!("{:.1}s", ms as f64 / 1000.0))
+                            .unwrap_or_default();
+
+                        println!(
+                            "{}  {:<9}  {:>7}  poly {}",
+                            entry.id, entry.status, duration, entry.command
+                        );
+                    }
+                }),
+
+                LogsCommand::Show { last: _, build } => build_log::read(&config, build.as_deref())
+                    .map(|(entry, content)| {
+                        println!("[Build] {} ({})", entry.id, entry.status);
+                        println!("[Started at] {}", entry.started_at);
+                        println!();
+                        print!("{}", content);
+                    }),
+            };
+
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+
+        Commands::Rename { name } => {
+            let current_dir = get_project_dir(app);
+            let name = normalize_project_name(&name);
+            let project_info = load_project_info(&current_dir, config_file, Scope::Project);
+            let project = Project::new(project::Config {
+                current_dir,
+                name,
+                template: project::Template::CounterTailwind,
+                refresh_template: false,
+                vars: vec![],
+                post_create: project::PostCreate::Skip,
+                sandbox_post_create: false,
+                css: None,
+                git: false,
+                install: false,
+                dest_dir: None,
+            });
+
+            if let Err(err) = project.rename(&project_info) {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+
+        Commands::Template { command } => match command {
+            TemplateCommand::List => {
+                let user_config = load_user_config();
+
+                let header = ["Name", "Description", "Url"].map(|s| s.to_string());
+
+                let builtin_rows = project::Template::builtin().into_iter().map(|template| {
+                    vec![
+                        template.name().to_string(),
+                        template.description().to_string(),
+                        template.info().source(),
+                    ]
+                });
+
+                let user_rows = user_config.templates.iter().map(|(name, template)| {
+                    vec![
+                        name.clone(),
+                        template.description.clone(),
+                        template.url.clone(),
+                    ]
+                });
+
+                let rows: Vec<Vec<String>> = builtin_rows.chain(user_rows).collect();
+                println!("{}", route_report::format_table(&header, &rows));
+            }
+        },
+
+        Commands::Config { command } => match command {
+            ConfigCommand::Print => {
+                let current_dir = get_project_dir(app);
+                let poly_config = load_poly_config(&current_dir, config_file);
+
+                match (&poly_config.source_path, config_file) {
+                    (Some(path), Some(_)) => {
+                        println!("# Loaded from {} (--config)", path.display())
+                    }
+                    (Some(path), None) => println!("# Loaded from {}", path.display()),
+                    (None, _) => println!(
+                        "# No {} found, using defaults",
+                        poly_config::CONFIG_FILE_NAME
+                    ),
+                }
+
+                match poly_config.to_toml() {
+                    Ok(content) => print!("{}", content),
+
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        process::exit(1);
+                    }
+                }
+            }
+        },
+
+        Commands::Routes { command } => {
+            // fmt
+            match command {
+                RoutesCommand::Report { json } => {
+                    let current_dir = get_project_dir(app);
+                    let project_info = load_project_info(&current_dir, config_file, Scope::Project);
+                    let route_report = RouteReport::new(route_report::Config::from_project_info(
+                        &current_dir,
+                        &project_info,
+                    ));
+
+                    let routes = route_report.collect().expect("Failed to collect routes");
+
+                    if json {
+                        let output = route_report.to_json(&routes).unwrap();
+                        println!("{}", output);
+                    } else {
+                        println!("{}", route_report.to_table(&routes));
+                    }
+                }
+            }
+        }
     }
 }
 
-fn hash_assets_helper(
-    asset_hasher: &AssetHasher,
-    rust_builder: &RustBuilder,
-    web_builder: &WebBuilder,
-    script: &Option<String>,
-    current_dir: &PathBuf,
-    env: &Env,
-) {
-    let assets = asset_hasher.collect_hashed_dist_assets().unwrap();
-    asset_hasher
-        .replace_checksum_in_source_files(&assets)
-        .unwrap();
+// Built-in templates take precedence over templates from the user config
+fn resolve_template(name: &str) -> Result<project::Template, String> {
+    if let Some(template) = project::Template::from_name(name) {
+        return Ok(template);
+    }
+
+    let user_config = load_user_config();
 
-    rust_builder.run().expect("Rust build failed");
-    web_builder.run().expect("Web build failed");
+    match user_config.templates.get(name) {
+        Some(user_template) => Ok(project::Template::Custom(
+            project::TemplateInfo::from_git_url(&user_template.url),
+        )),
+
+        None => Err(project::Error::UnknownTemplate(name.to_string()).to_string()),
+    }
+}
 
-    if let Some(script_name) = &script {
+// For `poly add` commands that don't use the project template
+fn scaffold_project(current_dir: &Path, project_info: &ProjectInfo) -> Project {
+    Project::new(project::Config {
+        current_dir: current_dir.to_path_buf(),
+        name: project_info.project_name.clone(),
+        template: project::Template::CounterTailwind,
+        refresh_template: false,
+        vars: vec![],
+        post_create: project::PostCreate::Skip,
+        sandbox_post_create: false,
+        css: None,
+        git: false,
+        install: false,
+        dest_dir: None,
+    })
+}
+
+fn normalize_project_name(name: &str) -> String {
+    let normalized = project::normalize_name(name).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    if normalized != name {
+        println!(
+            "Using project name '{}' instead of '{}', the name must be a valid crate and npm package name",
+            normalized, name
+        );
+    }
+
+    normalized
+}
+
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("Expected <name>=<value>, got '{}'", s))
+}
+
+fn load_user_config() -> UserConfig {
+    UserConfig::load().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    })
+}
+
+// Dry runs don't need the build tools
+fn build_scope(dry_run: bool, skip_preflight: bool) -> Scope {
+    if dry_run || skip_preflight {
+        Scope::Project
+    } else {
+        Scope::Build
+    }
+}
+
+fn supports_dry_run(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Build { .. }
+            | Commands::Watch { .. }
+            | Commands::Clean { .. }
+            | Commands::Upgrade { .. }
+    )
+}
+
+fn upload_artifacts(
+    project_info: &ProjectInfo,
+    poly_config: &PolyConfig,
+    file: Option<&Path>,
+) -> Result<(), String> {
+    artifact_uploader(project_info, poly_config)?
+        .run(file)
+        .map_err(|err| format!("Upload failed: {}", err))
+}
+
+fn artifact_uploader(
+    project_info: &ProjectInfo,
+    poly_config: &PolyConfig,
+) -> Result<ArtifactUploader, String> {
+    let upload_config = poly_config
+        .upload
+        .as_ref()
+        .ok_or("No [upload] section in poly.toml")?;
+
+    Ok(ArtifactUploader::new(
+        artifact_uploader::Config::from_project_info(project_info, upload_config),
+    ))
+}
+
+// Reports the start and the outcome of a full build to the webhooks and the build log
+fn run_pipeline(pipeline: &mut Pipeline, notifier: &Notifier, build_log: Option<&BuildLog>) {
+    let started_at = Instant::now();
+    notifier.notify(&BuildEvent::Started);
+
+    if let Err(err) = pipeline.run() {
+        fail_build(notifier, build_log, started_at, err);
+    }
+
+    finish_build(notifier, build_log, started_at);
+}
+
+fn finish_build(notifier: &Notifier, build_log: Option<&BuildLog>, started_at: Instant) {
+    let duration = started_at.elapsed();
+
+    if let Some(build_log) = build_log {
+        build_log.finish(build_log::Status::Succeeded, duration);
+    }
+
+    notifier.notify(&BuildEvent::Succeeded { duration });
+}
+
+fn fail_build(
+    notifier: &Notifier,
+    build_log: Option<&BuildLog>,
+    started_at: Instant,
+    error: String,
+) -> ! {
+    let duration = started_at.elapsed();
+    eprintln!("{}", error);
+
+    if let Some(build_log) = build_log {
+        build_log::append(&format!("{}\n", error));
+        build_log.finish(build_log::Status::Failed, duration);
+    }
+
+    notifier.notify(&BuildEvent::Failed { duration, error });
+
+    process::exit(1);
+}
+
+fn run_workspace_task(task: workspace::Task, config_file: Option<&Path>, app: Option<&str>) {
+    let current_dir = get_project_dir(app);
+    let poly_config = load_poly_config(&current_dir, config_file);
+    let config = workspace::Config::new(&current_dir, &poly_config.workspace);
+
+    if let Err(err) = workspace::run(&config, task) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+// The port `poly serve` listens on when started without flags
+fn serve_port(current_dir: &Path, poly_config: &PolyConfig) -> u32 {
+    let serve_preset = poly_config.serve_preset(None).unwrap_or_default();
+    let static_base_path = serve_preset
+        .static_
+        .unwrap_or_else(|| poly_config.layout.dist_path(current_dir));
+
+    serve_preset
+        .port
+        .unwrap_or_else(|| serve::default_port(&static_base_path))
+}
+
+// A build log that can't be created is reported but doesn't stop the build
+fn start_build_log(current_dir: &Path, poly_config: &PolyConfig) -> Option<BuildLog> {
+    let config = build_log::Config::new(current_dir, poly_config.logs.keep);
+    let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+
+    match BuildLog::start(config, &command) {
+        Ok(build_log) => {
+            println!("[Build log] {}", build_log.path().display());
+            Some(build_log)
+        }
+
+        Err(err) => {
+            eprintln!("Warning: {}", err);
+            None
+        }
+    }
+}
+
+// Pins everything that may run during this build, before any of it runs
+fn pin_scripts(current_dir: &Path, poly_config: &mut PolyConfig, script: Option<&str>) {
+    let scripts: Vec<(String, PathBuf)> = script
+        .into_iter()
+        .chain(
+            [
+                &poly_config.hooks.events,
+                &poly_config.hooks.dev,
+                &poly_config.hooks.release,
+            ]
+            .into_iter()
+            .flat_map(|events| Event::ALL.iter().flat_map(|event| event.entries(events)))
+            .filter_map(|entry| hook_script(current_dir, entry)),
+        )
+        .map(|script_name| {
+            (
+                script_pins::script_key(script_name),
+                current_dir.join(script_name),
+            )
+        })
+        .filter(|(_, script_path)| script_path.exists())
+        .collect();
+
+    let plugins = plugin::find_enabled_executables(&poly_config.plugins);
+
+    let config_path = poly_config.write_path(current_dir);
+
+    if let Err(err) = script_pins::accept(&config_path, &mut poly_config.pins, &scripts, &plugins) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+// The scripts of [hooks] by event, a --script runs first at the asset hash events like it
+// used to
+fn load_hooks(
+    current_dir: &Path,
+    poly_config: &PolyConfig,
+    profile: &Profile,
+    script: Option<&str>,
+) -> Hooks {
+    if script.is_some() {
+        eprintln!("Warning: --script is deprecated, list the script under [hooks] in poly.toml");
+    }
+
+    let mut hooks = Hooks::default();
+
+    let script_runner = |script_name: &str| {
         let script_path = current_dir.join(script_name);
-        let script_runner = ScriptRunner::new(script_path, &env);
-        script_runner
-            .run(script_runner::Event::AfterAssetHash)
-            .expect("Post build runner failed");
+
+        if !script_path.exists() {
+            eprintln!("Could not find hook script: {}", script_path.display());
+            process::exit(1);
+        }
+
+        ScriptRunner::new(
+            script_path,
+            profile,
+            script_pin(poly_config, script_name),
+            script_sandbox(poly_config, profile, script_name),
+        )
+    };
+
+    for event in Event::ALL {
+        // The deprecated --script always ran with before_asset_hash after the build, and with
+        // after_asset_hash when hashing. The asset hash events don't happen without hashing,
+        // so it runs at post_build then but is still told before_asset_hash
+        if let Some(script_name) = script {
+            match event {
+                Event::BeforeAssetHash | Event::AfterAssetHash if profile.hash_assets => {
+                    hooks.add(event, script_runner(script_name));
+                }
+
+                Event::PostBuild if !profile.hash_assets => {
+                    hooks.add(
+                        event,
+                        script_runner(script_name).with_reported_event(Event::BeforeAssetHash),
+                    );
+                }
+
+                _ => {}
+            }
+        }
+
+        let entries = event
+            .entries(&poly_config.hooks.events)
+            .iter()
+            .chain(event.entries(poly_config.hooks.for_env(&profile.env)));
+
+        for entry in entries {
+            let runner = match (hook_action(entry), hook_script(current_dir, entry)) {
+                (Some(action), _) => {
+                    ScriptRunner::action(action, profile).with_on_failure(entry.on_failure())
+                }
+
+                (None, Some(script_name)) => {
+                    script_runner(script_name).with_on_failure(entry.on_failure())
+                }
+
+                (None, None) => {
+                    ScriptRunner::inline(entry, profile, command_sandbox(poly_config, profile))
+                        .unwrap_or_else(|| {
+                            eprintln!("Empty command in the {} hooks of poly.toml", event);
+                            process::exit(1);
+                        })
+                }
+            };
+
+            hooks.add(event, runner);
+        }
+    }
+
+    hooks
+}
+
+// A built-in action is named by an entry not run by the shell, like `gzip-dist`
+fn hook_action(entry: &exec::CommandEntry) -> Option<Action> {
+    let text = match entry {
+        exec::CommandEntry::Plain(text) => text,
+        exec::CommandEntry::Table {
+            run, shell: false, ..
+        } => run,
+        exec::CommandEntry::Table { .. } => return None,
+    };
+
+    Action::parse(text)?
+        .map_err(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        })
+        .ok()
+}
+
+// An entry not run by the shell is a script when it names a file, or looks like the path
+// of a missing one, so a typo is reported as a missing script instead of an unknown command
+fn hook_script<'a>(current_dir: &Path, entry: &'a exec::CommandEntry) -> Option<&'a str> {
+    let text = match entry {
+        exec::CommandEntry::Plain(text) => text.trim(),
+        exec::CommandEntry::Table {
+            run, shell: false, ..
+        } => run.trim(),
+        exec::CommandEntry::Table { .. } => return None,
+    };
+
+    if hook_action(entry).is_some() {
+        return None;
+    }
+
+    let looks_like_path =
+        !text.contains(char::is_whitespace) && (text.contains('/') || text.contains('\\'));
+
+    (looks_like_path || current_dir.join(text).is_file()).then_some(text)
+}
+
+fn script_pin(poly_config: &PolyConfig, script_name: &str) -> Option<String> {
+    poly_config
+        .pins
+        .scripts
+        .get(&script_pins::script_key(script_name))
+        .cloned()
+}
+
+fn script_sandbox(
+    poly_config: &PolyConfig,
+    profile: &Profile,
+    script_name: &str,
+) -> Option<Sandbox> {
+    poly_config
+        .sandbox
+        .includes_script(&script_pins::script_key(script_name))
+        .then(|| Sandbox::new(&profile.dist_path, &poly_config.sandbox))
+}
+
+fn command_sandbox(poly_config: &PolyConfig, profile: &Profile) -> Option<Sandbox> {
+    poly_config
+        .sandbox
+        .commands
+        .then(|| Sandbox::new(&profile.dist_path, &poly_config.sandbox))
+}
+
+fn discover_plugins(
+    current_dir: &Path,
+    profile: &Profile,
+    poly_config: &PolyConfig,
+) -> Vec<PluginStep> {
+    plugin::discover(current_dir, profile, poly_config).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    })
+}
+
+fn print_plugins(plugins: &[PluginStep]) {
+    for plugin in plugins {
+        println!("[Plugin] {}", build::describe_step(plugin));
     }
+
+    if !plugins.is_empty() {
+        println!();
+    }
+}
+
+fn load_poly_config(current_dir: &Path, config_file: Option<&Path>) -> PolyConfig {
+    PolyConfig::load(current_dir, config_file).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    })
+}
+
+fn load_project_info(
+    current_dir: &PathBuf,
+    config_file: Option<&Path>,
+    scope: Scope,
+) -> ProjectInfo {
+    let poly_config = load_poly_config(current_dir, config_file);
+    let project_info =
+        project_info_cache::load(current_dir, &poly_config.layout).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+
+    if let Err(err) = preflight::check(&project_info, scope) {
+        eprint!("{}", err);
+        process::exit(1);
+    }
+
+    project_info
+}
+
+// Commands run from a subdir of a project work in the project root, like cargo does,
+// so that relative paths used by the build steps resolve the same way. In a monorepo
+// the project is the selected app or the app the current dir is in
+fn get_project_dir(app: Option<&str>) -> PathBuf {
+    let current_dir = get_current_dir();
+
+    let app_dir = monorepo::find_app_dir(&current_dir, app).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let project_dir = app_dir
+        .or_else(|| project_info::find_project_root(&current_dir))
+        .unwrap_or_else(|| current_dir.clone());
+
+    if project_dir != current_dir {
+        std::env::set_current_dir(&project_dir).unwrap_or_else(|err| {
+            eprintln!("Failed to enter {}: {}", project_dir.display(), err);
+            process::exit(1);
+        });
+    }
+
+    project_dir
 }
 
 fn get_current_dir() -> PathBuf {
@@ -312,9 +1652,13 @@ fn print_project_info(info: &ProjectInfo) {
     println!("[Web project dir] {}", info.web_project_path.display());
     println!("[Core project dir] {}", info.core_project_path.display());
     println!("[Wasm project dir] {}", info.wasm_project_path.display());
-    println!(
-        "[Cloudflare project dir] {}",
-        info.cloudflare_project_path.display()
-    );
+
+    if info.cloudflare_project_path.exists() {
+        println!(
+            "[Cloudflare project dir] {}",
+            info.cloudflare_project_path.display()
+        );
+    }
+
     println!("");
 }
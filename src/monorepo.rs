@@ -0,0 +1,83 @@
+use crate::poly_config;
+use crate::poly_config::PolyConfig;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    LoadConfig(poly_config::Error),
+    NoApps(PathBuf),
+    UnknownApp(String, Vec<String>),
+    AppRequired(PathBuf, Vec<String>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::LoadConfig(err) => write!(f, "{}", err),
+            Error::NoApps(dir) => write!(
+                f,
+                "--app needs a {} with [apps] in {} or a parent dir",
+                poly_config::CONFIG_FILE_NAME,
+                dir.display()
+            ),
+            Error::UnknownApp(name, available) => write!(
+                f,
+                "Unknown app '{}', available apps: [{}]",
+                name,
+                available.join(", ")
+            ),
+            Error::AppRequired(root, available) => write!(
+                f,
+                "{} has several apps, use --app or run poly inside an app dir, available apps: [{}]",
+                root.display(),
+                available.join(", ")
+            ),
+        }
+    }
+}
+
+// The dir of the selected app, or of the app `current_dir` is in. None outside of a monorepo
+pub fn find_app_dir(current_dir: &Path, app: Option<&str>) -> Result<Option<PathBuf>, Error> {
+    let (root, config) = match find_monorepo_root(current_dir)? {
+        Some(found) => found,
+        None if app.is_some() => return Err(Error::NoApps(current_dir.to_path_buf())),
+        None => return Ok(None),
+    };
+
+    let app_names = || config.apps.keys().cloned().collect::<Vec<_>>();
+
+    if let Some(name) = app {
+        return match config.apps.get(name) {
+            Some(app_config) => Ok(Some(root.join(&app_config.dir))),
+            None => Err(Error::UnknownApp(name.to_string(), app_names())),
+        };
+    }
+
+    config
+        .apps
+        .values()
+        .map(|app_config| root.join(&app_config.dir))
+        .find(|app_dir| current_dir.starts_with(app_dir))
+        .map(|app_dir| Ok(Some(app_dir)))
+        .unwrap_or_else(|| Err(Error::AppRequired(root, app_names())))
+}
+
+// The closest dir at or above `dir` with a poly.toml that lists apps, the apps can have
+// a poly.toml of their own
+fn find_monorepo_root(dir: &Path) -> Result<Option<(PathBuf, PolyConfig)>, Error> {
+    for dir in dir.ancestors() {
+        if !dir.join(poly_config::CONFIG_FILE_NAME).exists() {
+            continue;
+        }
+
+        let config = PolyConfig::from_dir(dir).map_err(Error::LoadConfig)?;
+
+        if !config.apps.is_empty() {
+            return Ok(Some((dir.to_path_buf(), config)));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,226 @@
+use crate::ProjectInfo;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use walkdir::WalkDir;
+
+pub const MANIFEST_FILE_NAME: &str = "poly-manifest.json";
+
+// A fixed compression level keeps the archive byte for byte reproducible
+const ZSTD_LEVEL: i32 = 19;
+
+#[derive(Debug)]
+pub enum Error {
+    EmptyDist(PathBuf),
+    ReadFile(PathBuf, io::Error),
+    SerializeManifest(serde_json::Error),
+    CreateArchive(PathBuf, io::Error),
+    WriteChecksum(PathBuf, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::EmptyDist(path) => write!(f, "No files to package in {}", path.display()),
+            Error::ReadFile(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::SerializeManifest(err) => write!(f, "Failed to serialize manifest: {}", err),
+            Error::CreateArchive(path, err) => {
+                write!(f, "Failed to create {}: {}", path.display(), err)
+            }
+            Error::WriteChecksum(path, err) => {
+                write!(f, "Failed to write {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub project_name: String,
+    pub dist_path: PathBuf,
+    pub output_path: PathBuf,
+}
+
+impl Config {
+    pub fn from_project_info(
+        current_dir: &Path,
+        project_info: &ProjectInfo,
+        output: Option<PathBuf>,
+    ) -> Self {
+        let default_output = format!("{}.tar.zst", project_info.project_name);
+
+        Self {
+            project_name: project_info.project_name.clone(),
+            dist_path: project_info.dist_path.clone(),
+            output_path: output.unwrap_or_else(|| current_dir.join(default_output)),
+        }
+    }
+
+    fn checksum_path(&self) -> PathBuf {
+        let mut path = self.output_path.clone().into_os_string();
+        path.push(".sha256");
+        PathBuf::from(path)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub project: String,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug)]
+pub struct Package {
+    pub archive_path: PathBuf,
+    pub checksum_path: PathBuf,
+    pub sha256: String,
+    pub file_count: usize,
+}
+
+struct PackageFile {
+    archive_path: String,
+    content: Vec<u8>,
+}
+
+pub struct Packager {
+    config: Config,
+}
+
+impl Packager {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<Package, Error> {
+        let files = self.collect_files()?;
+
+        if files.is_empty() {
+            return Err(Error::EmptyDist(self.config.dist_path.clone()));
+        }
+
+        let manifest = Manifest {
+            project: self.config.project_name.clone(),
+            files: files
+                .iter()
+                .map(|file| ManifestEntry {
+                    path: file.archive_path.clone(),
+                    size: file.content.len() as u64,
+                    sha256: hex_sha256(&file.content),
+                })
+                .collect(),
+        };
+
+        let manifest_json =
+            serde_json::to_vec_pretty(&manifest).map_err(Error::SerializeManifest)?;
+
+        let archive = self
+            .build_archive(&manifest_json, &files)
+            .map_err(|err| Error::CreateArchive(self.config.output_path.clone(), err))?;
+
+        fs::write(&self.config.output_path, &archive)
+            .map_err(|err| Error::CreateArchive(self.config.output_path.clone(), err))?;
+
+        let sha256 = hex_sha256(&archive);
+        let checksum_path = self.config.checksum_path();
+        let checksum_line = format!("{}  {}\n", sha256, file_name(&self.config.output_path));
+
+        fs::write(&checksum_path, checksum_line)
+            .map_err(|err| Error::WriteChecksum(checksum_path.clone(), err))?;
+
+        Ok(Package {
+            archive_path: self.config.output_path.clone(),
+            checksum_path,
+            sha256,
+            file_count: files.len(),
+        })
+    }
+
+    // Entries are sorted by path so the walk order of the file system
+    // doesn't leak into the archive
+    fn collect_files(&self) -> Result<Vec<PackageFile>, Error> {
+        let mut files = WalkDir::new(&self.config.dist_path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let rel_path = entry.path().strip_prefix(&self.config.dist_path).ok()?;
+                Some((entry.path().to_path_buf(), to_archive_path(rel_path)))
+            })
+            .map(|(path, archive_path)| {
+                fs::read(&path)
+                    .map(|content| PackageFile {
+                        archive_path,
+                        content,
+                    })
+                    .map_err(|err| Error::ReadFile(path, err))
+            })
+            .collect::<Result<Vec<PackageFile>, Error>>()?;
+
+        files.sort_by(|a, b| a.archive_path.cmp(&b.archive_path));
+
+        Ok(files)
+    }
+
+    fn build_archive(&self, manifest: &[u8], files: &[PackageFile]) -> Result<Vec<u8>, io::Error> {
+        let encoder = zstd::Encoder::new(Vec::new(), ZSTD_LEVEL)?;
+        let mut builder = tar::Builder::new(encoder);
+
+        append_entry(&mut builder, MANIFEST_FILE_NAME, manifest)?;
+
+        for file in files {
+            append_entry(&mut builder, &file.archive_path, &file.content)?;
+        }
+
+        let encoder = builder.into_inner()?;
+        encoder.finish()
+    }
+}
+
+// Owner, permissions and timestamps are fixed so that the archive only
+// depends on the file paths and their content
+fn append_entry<W: io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    content: &[u8],
+) -> Result<(), io::Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+
+    builder.append_data(&mut header, path, content)
+}
+
+fn to_archive_path(rel_path: &Path) -> String {
+    rel_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    data_encoding::HEXLOWER.encode(&Sha256::digest(data))
+}
@@ -0,0 +1,323 @@
+use crate::build;
+use crate::build::BuildStep;
+use crate::build::Profile;
+use crate::exec;
+use crate::poly_config::PluginsConfig;
+use crate::poly_config::PolyConfig;
+use crate::sandbox;
+use crate::sandbox::Sandbox;
+use crate::script_pins;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+const PLUGIN_PREFIX: &str = "poly-plugin-";
+
+#[derive(Debug)]
+pub enum Error {
+    Describe(exec::Error),
+    ParseDescription(serde_json::Error),
+    SerializeContext(serde_json::Error),
+    Run(exec::Error),
+    Pin(script_pins::Error),
+    Sandbox(sandbox::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::Describe(err) => write!(f, "Failed to describe plugin: {}", err),
+            Error::ParseDescription(err) => write!(f, "Invalid plugin description: {}", err),
+            Error::SerializeContext(err) => write!(f, "Failed to serialize context: {}", err),
+            Error::Run(err) => write!(f, "Plugin failed: {}", err),
+            Error::Pin(err) => write!(f, "{}", err),
+            Error::Sandbox(err) => write!(f, "Sandboxed plugin failed: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    PreBuild,
+    #[default]
+    PostBuild,
+}
+
+// Response of `poly-plugin-<name> describe`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Description {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub inputs: Vec<PathBuf>,
+    #[serde(default)]
+    pub outputs: Vec<PathBuf>,
+    #[serde(default)]
+    pub phase: Phase,
+}
+
+// Passed as json to `poly-plugin-<name> run <context>`
+#[derive(Debug, Clone, Serialize)]
+struct RunContext<'a> {
+    name: &'a str,
+    env: String,
+    git_sha: Option<&'a str>,
+    dist_dir: &'a Path,
+    hash_assets: bool,
+    project_dir: &'a Path,
+    inputs: &'a [PathBuf],
+    outputs: &'a [PathBuf],
+}
+
+#[derive(Debug, Clone)]
+pub struct PluginStep {
+    name: String,
+    executable: PathBuf,
+    project_dir: PathBuf,
+    profile: Profile,
+    inputs: Vec<PathBuf>,
+    outputs: Vec<PathBuf>,
+    phase: Phase,
+    pinned_sha256: Option<String>,
+    sandbox: Option<Sandbox>,
+}
+
+impl PluginStep {
+    pub fn from_executable(
+        executable: &Path,
+        project_dir: &Path,
+        profile: &Profile,
+        pinned_sha256: Option<String>,
+        sandbox: Option<Sandbox>,
+    ) -> Result<PluginStep, Error> {
+        // describe already runs the executable, so the pin is checked first
+        script_pins::verify(executable, pinned_sha256.as_deref()).map_err(Error::Pin)?;
+
+        let exec_config = exec::Config {
+            work_dir: project_dir.to_path_buf(),
+            cmd: executable.to_string_lossy().into(),
+            args: exec::to_args(&["describe"]),
+            env: Vec::new(),
+            env_mode: exec::EnvMode::Inherit,
+            timeout: None,
+        };
+
+        let stdout = match &sandbox {
+            Some(sandbox) => sandbox.run(&exec_config).map_err(Error::Sandbox)?,
+            None => exec::run(&exec_config).map_err(Error::Describe)?,
+        };
+
+        let description: Description =
+            serde_json::from_str(&stdout).map_err(Error::ParseDescription)?;
+
+        let name = description
+            .name
+            .unwrap_or_else(|| plugin_name_from_path(executable).unwrap_or_default());
+
+        Ok(PluginStep {
+            name,
+            executable: executable.to_path_buf(),
+            project_dir: project_dir.to_path_buf(),
+            profile: profile.clone(),
+            inputs: description.inputs,
+            outputs: description.outputs,
+            phase: description.phase,
+            pinned_sha256,
+            sandbox,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn matches_input(&self, rel_path: &Path) -> bool {
+        self.inputs.iter().any(|input| rel_path.starts_with(input))
+    }
+
+    pub fn matches_output(&self, rel_path: &Path) -> bool {
+        self.outputs
+            .iter()
+            .any(|output| rel_path.starts_with(output))
+    }
+
+    fn run_plugin(&self) -> Result<(), Error> {
+        script_pins::verify(&self.executable, self.pinned_sha256.as_deref()).map_err(Error::Pin)?;
+
+        let exec_config = self.run_command()?;
+
+        match &self.sandbox {
+            Some(sandbox) => sandbox.run(&exec_config).map_err(Error::Sandbox)?,
+            None => exec::run(&exec_config).map_err(Error::Run)?,
+        };
+
+        Ok(())
+    }
+
+    fn run_command(&self) -> Result<exec::Config, Error> {
+        let context = RunContext {
+            name: &self.name,
+            env: self.profile.name(),
+            git_sha: self.profile.git_sha.as_deref(),
+            dist_dir: &self.profile.dist_path,
+            hash_assets: self.profile.hash_assets,
+            project_dir: &self.project_dir,
+            inputs: &self.inputs,
+            outputs: &self.outputs,
+        };
+
+        let context_json = serde_json::to_string(&context).map_err(Error::SerializeContext)?;
+
+        Ok(exec::Config {
+            work_dir: self.project_dir.clone(),
+            cmd: self.executable.to_string_lossy().into(),
+            args: vec!["run".to_string(), context_json],
+            env: self.profile.script_env(),
+            env_mode: exec::EnvMode::Inherit,
+            timeout: None,
+        })
+    }
+}
+
+impl BuildStep for PluginStep {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn inputs(&self) -> Vec<PathBuf> {
+        self.inputs.clone()
+    }
+
+    fn outputs(&self) -> Vec<PathBuf> {
+        self.outputs.clone()
+    }
+
+    fn commands(&self) -> Vec<exec::Config> {
+        self.run_command().into_iter().collect()
+    }
+
+    fn run(&self) -> Result<(), String> {
+        self.run_plugin().map_err(|err| err.to_string())
+    }
+}
+
+pub fn run_phase(plugins: &[PluginStep], phase: Phase) -> Result<(), String> {
+    plugins
+        .iter()
+        .filter(|plugin| plugin.phase == phase)
+        .try_for_each(|plugin| {
+            build::run_step(plugin)
+                .map_err(|err| format!("Plugin '{}' failed: {}", plugin.name, err))
+        })
+}
+
+pub fn print_phase_plan(plugins: &[PluginStep], phase: Phase) {
+    plugins
+        .iter()
+        .filter(|plugin| plugin.phase == phase)
+        .for_each(build::print_plan);
+}
+
+// The executables of the plugins enabled in [plugins], nothing else on PATH is run or pinned
+pub fn find_enabled_executables(config: &PluginsConfig) -> BTreeMap<String, PathBuf> {
+    find_executables()
+        .into_iter()
+        .filter(|(name, _)| config.is_enabled(name))
+        .collect()
+}
+
+// Finds `poly-plugin-*` executables on PATH, the first match of a name wins
+fn find_executables() -> BTreeMap<String, PathBuf> {
+    let mut executables: BTreeMap<String, PathBuf> = BTreeMap::new();
+
+    let search_paths = env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    for dir in search_paths {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+
+            if let Some(name) = plugin_name_from_path(&path) {
+                if is_executable(&path) && !executables.contains_key(&name) {
+                    executables.insert(name, path);
+                }
+            }
+        }
+    }
+
+    executables
+}
+
+// Only runs the plugins enabled in [plugins]. A changed pinned plugin or a sandbox that can't be
+// set up is an error, other failures only skip the plugin
+pub fn discover(
+    project_dir: &Path,
+    profile: &Profile,
+    poly_config: &PolyConfig,
+) -> Result<Vec<PluginStep>, Error> {
+    let mut plugins = Vec::new();
+    let executables = find_enabled_executables(&poly_config.plugins);
+
+    for name in &poly_config.plugins.enabled {
+        if !executables.contains_key(name) {
+            eprintln!(
+                "Warning: Plugin {} is enabled but {}{} is not on PATH",
+                name, PLUGIN_PREFIX, name
+            );
+        }
+    }
+
+    for (name, path) in executables {
+        let pinned_sha256 = poly_config.pins.plugins.get(&name).cloned();
+        let sandbox = poly_config
+            .sandbox
+            .includes_plugin(&name)
+            .then(|| Sandbox::new(&profile.dist_path, &poly_config.sandbox));
+
+        match PluginStep::from_executable(&path, project_dir, profile, pinned_sha256, sandbox) {
+            Ok(step) => plugins.push(step),
+
+            Err(err @ Error::Pin(_)) | Err(err @ Error::Sandbox(sandbox::Error::HelperMissing)) => {
+                return Err(err)
+            }
+
+            Err(err) => {
+                eprintln!("Warning: Ignoring plugin {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    Ok(plugins)
+}
+
+fn plugin_name_from_path(path: &Path) -> Option<String> {
+    let file_stem = path.file_stem()?.to_str()?;
+    let name = file_stem.strip_prefix(PLUGIN_PREFIX)?;
+
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file() && path.extension().map(|ext| ext == "exe").unwrap_or(false)
+}
@@ -0,0 +1,497 @@
+use crate::build::Env;
+use crate::exec;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const CONFIG_FILE_NAME: &str = "poly.toml";
+
+#[derive(Debug)]
+pub enum Error {
+    ReadConfig(PathBuf, io::Error),
+    ParseConfig(PathBuf, toml::de::Error),
+    SerializeConfig(toml::ser::Error),
+    UnknownServePreset(String, Vec<String>),
+    InvalidEnvVar(&'static str, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadConfig(path, err) => {
+                write!(f, "Failed to read {}: {}", path.display(), err)
+            }
+
+            Error::ParseConfig(path, err) => {
+                write!(f, "Failed to parse {}: {}", path.display(), err)
+            }
+
+            Error::SerializeConfig(err) => write!(f, "Failed to serialize config: {}", err),
+
+            Error::InvalidEnvVar(name, value) => {
+                write!(f, "Invalid value for {}: '{}'", name, value)
+            }
+
+            Error::UnknownServePreset(name, available) => {
+                write!(
+                    f,
+                    "Unknown serve preset '{}', available presets: [{}]",
+                    name,
+                    available.join(", ")
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PolyConfig {
+    pub serve: BTreeMap<String, ServePreset>,
+    pub header_presets: BTreeMap<String, Vec<String>>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub upload: Option<UploadConfig>,
+    pub logs: LogsConfig,
+    pub workspace: WorkspaceConfig,
+    pub plugins: PluginsConfig,
+    pub pins: PinsConfig,
+    pub sandbox: SandboxConfig,
+    pub hooks: HooksConfig,
+    pub env: BTreeMap<String, StepEnvConfig>,
+    // Seconds the commands of the `rust`, `wasm` or `web` step may run before they're killed
+    pub timeouts: BTreeMap<String, u64>,
+    pub assets: AssetsConfig,
+    pub scaffold: ScaffoldConfig,
+    pub layout: LayoutConfig,
+    pub apps: BTreeMap<String, AppConfig>,
+    // The file the config was read from, None when no config file exists
+    #[serde(skip)]
+    pub source_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServePreset {
+    #[serde(rename = "static")]
+    pub static_: Option<PathBuf>,
+    pub routes: Option<PathBuf>,
+    pub headers: Vec<String>,
+    pub headers_preset: Vec<String>,
+    pub port: Option<u32>,
+    pub spa: Option<bool>,
+    pub compress: Option<bool>,
+    pub cache: Vec<String>,
+    pub https: Option<bool>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub dev: Option<bool>,
+    pub proxy: Vec<String>,
+    pub cors: Option<Vec<String>>,
+    pub not_found_page: Option<PathBuf>,
+    pub error_page: Option<PathBuf>,
+}
+
+// Workspace members that aren't part of the frontend, e.g. a backend binary
+// depending on the core crate. They're left out of builds but checked and tested
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    pub exclude: Vec<String>,
+}
+
+// `poly-plugin-<name>` executables on PATH only run in projects that enable them by name
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PluginsConfig {
+    pub enabled: Vec<String>,
+}
+
+impl PluginsConfig {
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.enabled.iter().any(|enabled| enabled == name)
+    }
+}
+
+// sha256 of post-build scripts by path and of plugin executables by plugin name,
+// a pinned executable that changed is not run
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PinsConfig {
+    pub scripts: BTreeMap<String, String>,
+    pub plugins: BTreeMap<String, String>,
+}
+
+// Environment variables for the `rust` (cargo build), `wasm` (wasm-pack) or `web` (npm) step,
+// the `dev` and `release` tables override them for that env
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StepEnvConfig {
+    pub dev: BTreeMap<String, String>,
+    pub release: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub vars: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AssetsConfig {
+    pub hash_length: usize,
+    pub hash_algorithm: HashAlgorithm,
+    pub versioning: Versioning,
+    // Path of renamed assets relative to dist, with [dir], [name], [hash] and [ext]
+    pub filename_pattern: String,
+    // Query param holding the hash in asset urls, like `/app.css?v=<hash>`
+    pub version_param: String,
+    // Adds integrity attributes to script and link tags in dist html
+    pub integrity: bool,
+    // Writes .gz and .br siblings of compressible dist files for hosts that serve them as is
+    pub precompress: bool,
+    pub images: ImagesConfig,
+    // Uri globs of dist assets that keep unhashed urls, like `/img/og/*`
+    pub exclude: Vec<String>,
+    pub preload: PreloadConfig,
+    pub sourcemaps: SourceMaps,
+}
+
+impl Default for AssetsConfig {
+    fn default() -> Self {
+        Self {
+            hash_length: 7,
+            hash_algorithm: HashAlgorithm::Sha256,
+            versioning: Versioning::Query,
+            filename_pattern: "[dir]/[name].[hash].[ext]".into(),
+            version_param: "hash".into(),
+            integrity: false,
+            precompress: false,
+            images: ImagesConfig::default(),
+            exclude: Vec::new(),
+            preload: PreloadConfig::default(),
+            sourcemaps: SourceMaps::Follow,
+        }
+    }
+}
+
+// blake3 and xxhash are much faster on large image dirs, xxhash is not meant to be
+// collision resistant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+    Xxhash,
+}
+
+// Optimizes dist images with external tools before hashing, missing tools are skipped
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ImagesConfig {
+    pub optimize: bool,
+    pub webp: bool,
+    pub avif: bool,
+    pub warn_size_kib: u64,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self {
+            optimize: false,
+            webp: false,
+            avif: false,
+            warn_size_kib: 500,
+        }
+    }
+}
+
+// Preload hints for the wasm, its js glue and the listed assets, with their hashed urls
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PreloadConfig {
+    // Link tags in the head of dist html
+    pub links: bool,
+    // Link headers in dist/_headers, for hosts like Cloudflare Pages
+    pub headers: bool,
+    // Dist uris like `/app.css`
+    pub assets: Vec<String>,
+}
+
+// Source maps follow the versioned name of their file, or are removed from dist with their links
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceMaps {
+    Follow,
+    Exclude,
+}
+
+// Where the hash goes, `/app.css?hash=<hash>` or a renamed file like `/app.<hash>.css`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Versioning {
+    Query,
+    Filename,
+}
+
+// Overrides for the files generated by `poly add`, i.e. component.rs and component.ts
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ScaffoldConfig {
+    pub templates: PathBuf,
+}
+
+impl Default for ScaffoldConfig {
+    fn default() -> Self {
+        Self {
+            templates: PathBuf::from("scaffold"),
+        }
+    }
+}
+
+// Paths of the project parts relative to the project dir, for repos that don't follow
+// the <name>_core, <name>_wasm, <name>_web and <name>_cloudflare naming
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    // Used for the wasm output names, defaults to the prefix of the *_core workspace member
+    pub name: Option<String>,
+    pub core: Option<PathBuf>,
+    pub wasm: Option<PathBuf>,
+    pub web: Option<PathBuf>,
+    pub cloudflare: Option<PathBuf>,
+    pub dist: Option<PathBuf>,
+}
+
+impl LayoutConfig {
+    pub fn dist_path(&self, project_dir: &Path) -> PathBuf {
+        project_dir.join(self.dist.as_deref().unwrap_or_else(|| Path::new("dist")))
+    }
+}
+
+// An app of a monorepo, selected with --app or by running poly inside its dir
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppConfig {
+    // Relative to the dir of the poly.toml listing the apps
+    pub dir: PathBuf,
+}
+
+// Post-build scripts by path and plugins by name that run in the sandbox
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    pub scripts: Vec<String>,
+    pub plugins: Vec<String>,
+    // Runs the inline commands of [hooks] in the sandbox too
+    pub commands: bool,
+    pub network: bool,
+    // Only scrub the environment when bubblewrap is missing instead of failing
+    pub allow_env_only: bool,
+}
+
+impl SandboxConfig {
+    pub fn includes_script(&self, script_key: &str) -> bool {
+        self.scripts
+            .iter()
+            .any(|script| script.trim_start_matches("./") == script_key)
+    }
+
+    pub fn includes_plugin(&self, name: &str) -> bool {
+        self.plugins.iter().any(|plugin| plugin == name)
+    }
+}
+
+// Scripts and commands run at the events of a build in the listed order. An entry naming a
+// file relative to the project dir is a script, anything else a command like the post
+// create commands of templates. The asset hash events only happen when the assets are
+// hashed, so never in watch. Watch rebuilds run the build events except pre_clean, and
+// on_watch_rebuild at the end
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    // [hooks.dev] and [hooks.release] run after the hooks of the event for every build
+    pub dev: HookEventsConfig,
+    pub release: HookEventsConfig,
+    #[serde(flatten)]
+    pub events: HookEventsConfig,
+}
+
+impl HooksConfig {
+    pub fn for_env(&self, env: &Env) -> &HookEventsConfig {
+        match env {
+            Env::Dev => &self.dev,
+            Env::Release => &self.release,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HookEventsConfig {
+    pub pre_clean: Vec<exec::CommandEntry>,
+    pub pre_build: Vec<exec::CommandEntry>,
+    pub post_rust_build: Vec<exec::CommandEntry>,
+    pub post_web_build: Vec<exec::CommandEntry>,
+    pub before_asset_hash: Vec<exec::CommandEntry>,
+    pub after_asset_hash: Vec<exec::CommandEntry>,
+    pub post_build: Vec<exec::CommandEntry>,
+    pub pre_serve: Vec<exec::CommandEntry>,
+    pub on_watch_rebuild: Vec<exec::CommandEntry>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogsConfig {
+    pub keep: Option<usize>,
+}
+
+// Without a template the raw build event is posted, with a template a
+// Slack compatible `{"text": "..."}` payload is posted instead
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub template: Option<String>,
+}
+
+// Credentials are read from the environment, never from poly.toml
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UploadConfig {
+    pub provider: UploadProvider,
+    pub endpoint: Option<String>,
+    pub bucket: Option<String>,
+    pub region: Option<String>,
+    pub url: Option<String>,
+    pub prefix: Option<String>,
+    pub parallelism: Option<usize>,
+    pub retries: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadProvider {
+    S3,
+    Gcs,
+    Azure,
+    Http,
+}
+
+impl PolyConfig {
+    // An explicitly given config file takes precedence over poly.toml in the dir,
+    // the POLY_* environment variables take precedence over both
+    pub fn load(dir: &Path, config_file: Option<&Path>) -> Result<PolyConfig, Error> {
+        let config = match config_file {
+            Some(path) => PolyConfig::from_file(path),
+            None => PolyConfig::from_dir(dir),
+        }?;
+
+        config.with_env_overrides(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    // Lets CI redirect outputs without changing the repo, command line flags still win.
+    // Paths are relative to the project dir like the ones in poly.toml
+    fn with_env_overrides(
+        mut self,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<PolyConfig, Error> {
+        let layout_vars = [
+            ("POLY_DIST_DIR", &mut self.layout.dist),
+            ("POLY_WEB_DIR", &mut self.layout.web),
+            ("POLY_CORE_DIR", &mut self.layout.core),
+            ("POLY_WASM_DIR", &mut self.layout.wasm),
+            ("POLY_CLOUDFLARE_DIR", &mut self.layout.cloudflare),
+        ];
+
+        for (name, path) in layout_vars {
+            if let Some(value) = var(name) {
+                *path = Some(PathBuf::from(value));
+            }
+        }
+
+        if let Some(value) = var("POLY_PORT") {
+            let port = value
+                .parse()
+                .map_err(|_| Error::InvalidEnvVar("POLY_PORT", value))?;
+
+            self.serve.entry("default".to_string()).or_default();
+            for preset in self.serve.values_mut() {
+                preset.port = Some(port);
+            }
+        }
+
+        Ok(self)
+    }
+
+    // A missing poly.toml is not an error, all settings are optional
+    pub fn from_dir(dir: &Path) -> Result<PolyConfig, Error> {
+        let path = dir.join(CONFIG_FILE_NAME);
+
+        if path.exists() {
+            PolyConfig::from_file(&path)
+        } else {
+            Ok(PolyConfig::default())
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<PolyConfig, Error> {
+        let content =
+            fs::read_to_string(path).map_err(|err| Error::ReadConfig(path.to_path_buf(), err))?;
+
+        let config: PolyConfig =
+            toml::from_str(&content).map_err(|err| Error::ParseConfig(path.to_path_buf(), err))?;
+
+        Ok(PolyConfig {
+            source_path: Some(path.to_path_buf()),
+            ..config
+        })
+    }
+
+    pub fn step_env(&self, step: &str, env: &Env) -> Vec<(String, String)> {
+        let step_env = match self.env.get(step) {
+            Some(step_env) => step_env,
+            None => return Vec::new(),
+        };
+
+        let env_vars = match env {
+            Env::Dev => &step_env.dev,
+            Env::Release => &step_env.release,
+        };
+
+        let mut vars = step_env.vars.clone();
+        vars.extend(env_vars.clone());
+        vars.into_iter().collect()
+    }
+
+    pub fn step_timeout(&self, step: &str) -> Option<Duration> {
+        self.timeouts.get(step).copied().map(Duration::from_secs)
+    }
+
+    // Where changes like script pins are written back to
+    pub fn write_path(&self, dir: &Path) -> PathBuf {
+        self.source_path
+            .clone()
+            .unwrap_or_else(|| dir.join(CONFIG_FILE_NAME))
+    }
+
+    // Tables go through toml::Value so they're written after plain values
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::Value::try_from(self)
+            .and_then(|value| toml::to_string(&value))
+            .map_err(Error::SerializeConfig)
+    }
+
+    // Uses the `default` preset when no name is given
+    pub fn serve_preset(&self, name: Option<&str>) -> Result<ServePreset, Error> {
+        match name {
+            Some(name) => self.serve.get(name).cloned().ok_or_else(|| {
+                Error::UnknownServePreset(name.to_string(), self.serve.keys().cloned().collect())
+            }),
+
+            None => Ok(self.serve.get("default").cloned().unwrap_or_default()),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::exec;
+use crate::project_info::ProjectInfo;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+// Edition 2021
+const CARGO_MINIMUM: Version = Version(1, 56, 0);
+// The dev builds pass --no-opt, added in 0.12.0
+const WASM_PACK_MINIMUM: Version = Version(0, 12, 0);
+// Older versions don't read the lockfile v2 of the template package-lock.json files
+const NPM_MINIMUM: Version = Version(7, 0, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    // The project dirs
+    Project,
+    // The project dirs and the tools used by the build steps, with their versions
+    Build,
+}
+
+#[derive(Debug)]
+pub enum Problem {
+    MissingDir {
+        part: &'static str,
+        path: PathBuf,
+    },
+    MissingTool {
+        name: &'static str,
+        hint: String,
+    },
+    OutdatedTool {
+        name: &'static str,
+        found: Version,
+        minimum: Version,
+        hint: &'static str,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Problem::MissingDir { part, path } => write!(
+                f,
+                "The {} project {} doesn't exist, set layout.{} in poly.toml if it's somewhere else",
+                part,
+                path.display(),
+                part
+            ),
+
+            Problem::MissingTool { name, hint } => {
+                write!(f, "'{}' wasn't found in PATH, {}", name, hint)
+            }
+
+            Problem::OutdatedTool {
+                name,
+                found,
+                minimum,
+                hint,
+            } => write!(
+                f,
+                "'{}' is version {}, at least {} is needed, {}",
+                name, found, minimum, hint
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    problems: Vec<Problem>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        writeln!(f, "Some project requirements are missing:")?;
+
+        for problem in &self.problems {
+            writeln!(f, "  - {}", problem)?;
+        }
+
+        writeln!(
+            f,
+            "Use --skip-preflight to build anyway if the tools are set up some other way"
+        )?;
+
+        Ok(())
+    }
+}
+
+// Reports everything that's missing at once, instead of failing halfway through a build
+pub fn check(project_info: &ProjectInfo, scope: Scope) -> Result<(), Error> {
+    let mut problems = missing_dirs(project_info);
+
+    if scope == Scope::Build {
+        problems.extend(missing_tools(project_info));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error { problems })
+    }
+}
+
+fn missing_dirs(project_info: &ProjectInfo) -> Vec<Problem> {
+    [
+        ("core", &project_info.core_project_path),
+        ("wasm", &project_info.wasm_project_path),
+        ("web", &project_info.web_project_path),
+    ]
+    .into_iter()
+    .filter(|(_, path)| !path.exists())
+    .map(|(part, path)| Problem::MissingDir {
+        part,
+        path: path.clone(),
+    })
+    .collect()
+}
+
+struct Tool {
+    name: &'static str,
+    minimum: Version,
+    install_hint: String,
+    update_hint: &'static str,
+}
+
+fn missing_tools(project_info: &ProjectInfo) -> Vec<Problem> {
+    let mut tools = vec![
+        Tool {
+            name: "cargo",
+            minimum: CARGO_MINIMUM,
+            install_hint: "install rust with rustup: https://rustup.rs".to_string(),
+            update_hint: "update it with `rustup update`",
+        },
+        Tool {
+            name: "wasm-pack",
+            minimum: WASM_PACK_MINIMUM,
+            install_hint:
+                "install it with `cargo install wasm-pack` or see https://rustwasm.github.io/wasm-pack/installer/"
+                    .to_string(),
+            update_hint: "update it with `cargo install wasm-pack --force`",
+        },
+    ];
+
+    // Web projects without a package.json are built without npm
+    let package_json = project_info.web_project_path.join("package.json");
+    if package_json.exists() {
+        tools.push(Tool {
+            name: "npm",
+            minimum: NPM_MINIMUM,
+            install_hint: format!(
+                "install node.js from https://nodejs.org, it's used because of {}",
+                package_json.display()
+            ),
+            update_hint: "install a newer node.js from https://nodejs.org",
+        });
+    }
+
+    tools.into_iter().filter_map(check_tool).collect()
+}
+
+fn check_tool(tool: Tool) -> Option<Problem> {
+    if !exec::is_on_path(tool.name) {
+        return Some(Problem::MissingTool {
+            name: tool.name,
+            hint: tool.install_hint,
+        });
+    }
+
+    // A version that can't be read isn't reported, the build shows what's wrong if anything
+    let found = installed_version(tool.name)?;
+
+    if found < tool.minimum {
+        Some(Problem::OutdatedTool {
+            name: tool.name,
+            found,
+            minimum: tool.minimum,
+            hint: tool.update_hint,
+        })
+    } else {
+        None
+    }
+}
+
+// The first x.y.z in the output of `<tool> --version`, i.e. `cargo 1.75.0 (1d8b05cdd 2023-11-20)`
+fn installed_version(name: &str) -> Option<Version> {
+    let output = Command::new(name)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .find_map(Version::parse)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(u64, u64, u64);
+
+impl Version {
+    fn parse(s: &str) -> Option<Version> {
+        let parts: Vec<u64> = s
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+
+        match parts[..] {
+            [major, minor, patch] => Some(Version(major, minor, patch)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
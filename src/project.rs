@@ -1,20 +1,62 @@
+use crate::exec;
+use crate::poly_config::LayoutConfig;
+use crate::poly_config::SandboxConfig;
 use crate::project_info;
 use crate::project_info::ProjectInfo;
+use crate::route_report;
+use crate::sandbox;
+use crate::sandbox::Sandbox;
+use crate::template_archive;
+use crate::template_cache;
+use crate::template_cache::TemplateCache;
+use crate::template_lock;
+use crate::template_lock::LockedTemplate;
+use crate::template_lock::TemplateLock;
+use crate::template_manifest;
+use crate::template_manifest::TemplateManifest;
+use crate::template_record;
+use crate::template_record::TemplateRecord;
+use crate::user_config;
 use crate::util::file_util;
-use convert_case::{Case, Casing};
-use std::convert::identity;
+use crate::util::spinner::Spinner;
+use convert_case::{Boundary, Case, Casing};
+use globset::GlobSet;
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
 use std::io;
-use std::io::Cursor;
-use std::io::Read;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process;
 use walkdir::WalkDir;
 
 pub struct Config {
     pub name: String,
     pub template: Template,
     pub current_dir: PathBuf,
+    pub refresh_template: bool,
+    // Values for the placeholders declared in the template manifest
+    pub vars: Vec<(String, String)>,
+    pub post_create: PostCreate,
+    // Run the post create commands in the sandbox, without network access
+    pub sandbox_post_create: bool,
+    // Css framework variant of the template, the template default when None
+    pub css: Option<String>,
+    // Init a git repo with an initial commit in the new project
+    pub git: bool,
+    // Fetch npm and cargo dependencies of the new project
+    pub install: bool,
+    // Where the project is created, a dir named after the project in current_dir when None
+    pub dest_dir: Option<PathBuf>,
+}
+
+// What to do with the post_create commands of the template manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostCreate {
+    Ask,
+    Run,
+    Skip,
 }
 
 pub struct Project {
@@ -23,20 +65,98 @@ pub struct Project {
 
 #[derive(Debug)]
 pub enum Error {
-    InvalidProjectName,
+    InvalidProjectName(String, String),
     TempDir(io::Error),
-    GetUrl(ureq::Error),
-    ReadResponse(io::Error),
-    ZipExtract(zip_extract::ZipExtractError),
+    DownloadTemplate(template_cache::Error),
+    ExtractTemplate(template_archive::Error),
     ReadFile(io::Error),
     WriteFile(io::Error),
     RenameDir(io::Error),
     CopyToDestination(fs_extra::error::Error),
-    RenameTemplateDir(io::Error),
     TemplateProjectInfo(project_info::Error),
     ReadCoreHomePage(io::Error),
     WriteCoreHomePage(io::Error),
     ReadLibFile(io::Error),
+    InvalidScaffoldName(String),
+    ReadComponentTemplate(PathBuf, io::Error),
+    UnknownTemplate(String),
+    CloudflareProjectNotFound(PathBuf),
+    TemplateRecord(template_record::Error),
+    TemplateLock(template_lock::Error),
+    DestinationNotEmpty(PathBuf),
+    CreateDestination(io::Error),
+    TemplateManifest(template_manifest::Error),
+    TemplateCondition(PathBuf, template_manifest::Error),
+    RemoveTemplatePath(PathBuf, io::Error),
+    ReadConfirmation(io::Error),
+    PostCreateCommand(String, exec::Error),
+    PostCreateSandbox(String, sandbox::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::InvalidProjectName(name, reason) => {
+                write!(f, "Invalid project name '{}': {}", name, reason)
+            }
+            Error::TempDir(err) => write!(f, "Failed to create temp dir: {}", err),
+            Error::DownloadTemplate(err) => write!(f, "{}", err),
+            Error::ExtractTemplate(err) => write!(f, "{}", err),
+            Error::ReadFile(err) => write!(f, "Failed to read file: {}", err),
+            Error::WriteFile(err) => write!(f, "Failed to write file: {}", err),
+            Error::RenameDir(err) => write!(f, "Failed to rename dir: {}", err),
+            Error::CopyToDestination(err) => write!(f, "Failed to copy project: {}", err),
+            Error::TemplateProjectInfo(err) => write!(f, "Invalid template: {}", err),
+            Error::ReadCoreHomePage(err) => write!(f, "Failed to read template page: {}", err),
+            Error::WriteCoreHomePage(err) => write!(f, "Failed to write page: {}", err),
+            Error::ReadLibFile(err) => write!(f, "Failed to read lib.rs: {}", err),
+            Error::InvalidScaffoldName(kind) => write!(
+                f,
+                "Invalid {} name, use ascii letters and digits, like UserCard",
+                kind
+            ),
+            Error::ReadComponentTemplate(path, err) => {
+                write!(f, "Failed to read {}: {}", path.display(), err)
+            }
+            Error::TemplateManifest(err) => write!(f, "Invalid template: {}", err),
+            Error::TemplateCondition(path, err) => {
+                write!(f, "Invalid template file {}: {}", path.display(), err)
+            }
+            Error::RemoveTemplatePath(path, err) => {
+                write!(f, "Failed to remove {}: {}", path.display(), err)
+            }
+            Error::ReadConfirmation(err) => write!(f, "Failed to read answer: {}", err),
+            Error::PostCreateCommand(cmd, err) => {
+                write!(f, "Post create command '{}' failed: {}", cmd, err)
+            }
+            Error::PostCreateSandbox(cmd, err) => {
+                write!(f, "Sandboxed post create command '{}' failed: {}", cmd, err)
+            }
+            Error::DestinationNotEmpty(path) => {
+                write!(f, "Destination {} exists and is not empty", path.display())
+            }
+            Error::CreateDestination(err) => write!(f, "Failed to create project dir: {}", err),
+            Error::TemplateRecord(err) => write!(f, "{}", err),
+            Error::TemplateLock(err) => write!(f, "{}", err),
+            Error::CloudflareProjectNotFound(path) => {
+                write!(f, "Cloudflare project not found: {}", path.display())
+            }
+
+            Error::UnknownTemplate(name) => {
+                let templates = Template::builtin()
+                    .iter()
+                    .map(|template| format!("  {:<18} {}", template.name(), template.description()))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                write!(
+                    f,
+                    "Unknown template '{}', available templates:\n{}\n\nRun `poly template list` to also see templates from the user config. A git repository url can also be used: https://github.com/<owner>/<repo>[#<ref>[:<subdir>]]",
+                    name, templates
+                )
+            }
+        }
+    }
 }
 
 impl Project {
@@ -46,20 +166,289 @@ impl Project {
 
     pub fn create(&self) -> Result<(), Error> {
         validate_name(&self.config.name)?;
+        ensure_empty_destination(&self.project_dir())?;
         let temp_dir = tempfile::tempdir().map_err(Error::TempDir)?;
-        let template_dir = self.prepare_template(&temp_dir)?;
-        copy_to_dest(&self.config.name, &template_dir, &self.config.current_dir)?;
+        let PreparedTemplate {
+            dir: template_dir,
+            manifest,
+            lock,
+        } = self.prepare_template(&temp_dir)?;
+        copy_to_dest(&template_dir, &self.project_dir())?;
+
+        self.template_record()
+            .write(&self.project_dir(), &template_dir)
+            .map_err(Error::TemplateRecord)?;
+        lock.write(&self.project_dir())
+            .map_err(Error::TemplateLock)?;
+
+        let replacements = manifest
+            .replacements(&self.config.name, &self.config.vars)
+            .map_err(Error::TemplateManifest)?;
+
+        let commands: Vec<exec::CommandEntry> = manifest
+            .post_create
+            .iter()
+            .map(|cmd| cmd.map_text(|text| template_manifest::replace_all(text, &replacements)))
+            .collect();
+
+        self.run_post_create(&commands)?;
+
+        // Before git init so the lock files are part of the initial commit
+        if self.config.install {
+            self.install_dependencies();
+        }
+
+        if self.config.git {
+            self.init_git()?;
+        }
+
+        if let Some(instructions) = &manifest.instructions {
+            println!();
+            println!(
+                "{}",
+                template_manifest::replace_all(instructions, &replacements).trim_end()
+            );
+        }
+
+        Ok(())
+    }
+
+    // Re-renders the template the project was created from and applies the changes since then,
+    // files changed on both sides are three-way merged with the previous render as base
+    pub fn upgrade(&self, dry_run: bool) -> Result<(), Error> {
+        let project_dir = self.project_dir();
+        let locked = TemplateLock::read(&project_dir).map_err(Error::TemplateLock)?;
+        let temp_dir = tempfile::tempdir().map_err(Error::TempDir)?;
+        let template = self.prepare_template(&temp_dir)?;
+
+        if template.lock.template.checksum == locked.template.checksum {
+            println!("Already up to date with {}", self.config.template.name());
+            return Ok(());
+        }
+
+        let base_dir = template_record::base_dir(&project_dir);
+        let new_files = collect_dir_entries(&template.dir).files;
+        let mut changes: Vec<(UpgradeChange, PathBuf)> = Vec::new();
+
+        for new_path in &new_files {
+            let rel_path = new_path.strip_prefix(&template.dir).unwrap_or(new_path);
+            let change = upgrade_file(rel_path, &base_dir, &template.dir, &project_dir, dry_run)?;
+
+            if let Some(change) = change {
+                changes.push((change, rel_path.to_path_buf()));
+            }
+        }
+
+        // Files the template no longer has are left to the user
+        for base_path in collect_dir_entries(&base_dir).files {
+            let rel_path = base_path.strip_prefix(&base_dir).unwrap_or(&base_path);
+
+            if !template.dir.join(rel_path).exists() && project_dir.join(rel_path).exists() {
+                changes.push((UpgradeChange::RemovedFromTemplate, rel_path.to_path_buf()));
+            }
+        }
+
+        changes.sort_by(|(_, a), (_, b)| a.cmp(b));
+
+        for (change, rel_path) in &changes {
+            println!("{} {}", change.label(), rel_path.display());
+        }
+
+        if changes.is_empty() {
+            println!("The template changes don't affect any files");
+        }
+
+        if dry_run {
+            println!("Dry run, no files were changed");
+            return Ok(());
+        }
+
+        self.template_record()
+            .write(&project_dir, &template.dir)
+            .map_err(Error::TemplateRecord)?;
+        template
+            .lock
+            .write(&project_dir)
+            .map_err(Error::TemplateLock)?;
+
+        let conflicts = changes
+            .iter()
+            .filter(|(change, _)| *change == UpgradeChange::Conflict)
+            .count();
+
+        if conflicts > 0 {
+            println!(
+                "{} file(s) have conflicts, resolve the conflict markers before building",
+                conflicts
+            );
+        }
+
+        Ok(())
+    }
+
+    fn project_dir(&self) -> PathBuf {
+        match &self.config.dest_dir {
+            Some(dir) => self.config.current_dir.join(dir),
+            None => self.config.current_dir.join(&self.config.name),
+        }
+    }
+
+    // Failures only warn, the dependencies are fetched by the first build anyway
+    fn install_dependencies(&self) {
+        let project_dir = self.project_dir();
+        let web_project_dir = project_dir.join(format!("{}_web", self.config.name));
+
+        let steps = [
+            ("npm", vec!["install"], web_project_dir, "package.json"),
+            ("cargo", vec!["fetch"], project_dir, "Cargo.toml"),
+        ];
+
+        for (cmd, args, work_dir, manifest_file) in steps {
+            if !work_dir.join(manifest_file).exists() {
+                continue;
+            }
+
+            if !exec::is_on_path(cmd) {
+                eprintln!(
+                    "Warning: {} not found, skipping {} {}",
+                    cmd,
+                    cmd,
+                    args.join(" ")
+                );
+                continue;
+            }
+
+            let spinner = Spinner::start(&format!("Running {} {}", cmd, args.join(" ")));
+
+            let res = exec::run(&exec::Config {
+                work_dir,
+                cmd: cmd.to_string(),
+                args: exec::to_args(&args),
+                env: vec![],
+                env_mode: exec::EnvMode::Inherit,
+                timeout: None,
+            });
+
+            spinner.stop();
+
+            if let Err(err) = res {
+                eprintln!("Warning: {} {} failed: {}", cmd, args.join(" "), err);
+            }
+        }
+    }
+
+    // Failing git commands only warn, the project itself was created
+    fn init_git(&self) -> Result<(), Error> {
+        let project_dir = self.project_dir();
+
+        if !exec::is_on_path("git") {
+            eprintln!("Warning: git not found, skipping git init");
+            return Ok(());
+        }
+
+        if is_inside_git_repo(&project_dir) {
+            println!("Already inside a git repo, skipping git init");
+            return Ok(());
+        }
+
+        write_gitignore(&project_dir)?;
+
+        let git_commands: [&[&str]; 3] = [
+            &["init", "--quiet"],
+            &["add", "--all"],
+            &["commit", "--quiet", "-m", "Initial commit"],
+        ];
+
+        for args in git_commands {
+            let res = exec::run(&exec::Config {
+                work_dir: project_dir.clone(),
+                cmd: "git".to_string(),
+                args: exec::to_args(args),
+                env: vec![],
+                env_mode: exec::EnvMode::Inherit,
+                timeout: None,
+            });
+
+            if let Err(err) = res {
+                eprintln!("Warning: git {} failed: {}", args[0], err);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_post_create(&self, commands: &[exec::CommandEntry]) -> Result<(), Error> {
+        if commands.is_empty() || self.config.post_create == PostCreate::Skip {
+            return Ok(());
+        }
+
+        println!();
+        println!("The template wants to run these commands:");
+        for cmd in commands {
+            println!("  {}", cmd.text());
+        }
+
+        if self.config.sandbox_post_create {
+            println!("They run in the sandbox, without network access");
+        }
+
+        if self.config.post_create == PostCreate::Ask && !confirm("Run them now?")? {
+            println!("Skipping post create commands");
+            return Ok(());
+        }
+
+        let project_dir = self.project_dir();
+
+        // Configured by the user and not by the template, whose poly.toml can't be trusted
+        // any more than its commands
+        let sandbox = self
+            .config
+            .sandbox_post_create
+            .then(|| Sandbox::new(&project_dir, &SandboxConfig::default()));
+
+        for command in commands {
+            let (cmd, args) = match command.to_cmd() {
+                Some(cmd) => cmd,
+                None => continue,
+            };
+
+            let exec_config = exec::Config {
+                work_dir: project_dir.clone(),
+                cmd,
+                args,
+                env: vec![],
+                env_mode: exec::EnvMode::Inherit,
+                timeout: None,
+            };
+
+            let res = run_post_create_command(command, &exec_config, sandbox.as_ref());
+
+            // A sandbox that can't be set up stops the commands, whatever on_failure says
+            match (res, command.on_failure()) {
+                (Err(err @ Error::PostCreateSandbox(_, sandbox::Error::HelperMissing)), _) => {
+                    return Err(err)
+                }
+
+                (Ok(()), _) | (Err(_), exec::OnFailure::Ignore) => {}
+
+                (Err(err), exec::OnFailure::Warn) => eprintln!("Warning: {}", err),
+
+                (Err(err), exec::OnFailure::Abort) => return Err(err),
+            }
+        }
 
         Ok(())
     }
 
     pub fn add_page(&self, project_info: &ProjectInfo, name: &str) -> Result<(), Error> {
         let page_name = PageName::new(name);
-        let template_page_name = self.config.template.info().default_page_name;
         let temp_dir = tempfile::tempdir().map_err(Error::TempDir)?;
-        let template_dir = self.prepare_template(&temp_dir)?;
-        let template_project_info =
-            ProjectInfo::from_dir(&template_dir).map_err(Error::TemplateProjectInfo)?;
+        let template = self.prepare_template(&temp_dir)?;
+        let template_dir = template.dir;
+        let template_page_name = PageName::new(&template.manifest.default_page);
+        let template_project_info = ProjectInfo::from_dir(&template_dir, &LayoutConfig::default())
+            .map_err(Error::TemplateProjectInfo)?;
 
         // Add page to core project
         copy_page_template(
@@ -94,22 +483,201 @@ impl Project {
             "ts",
         )?;
 
+        // Add page to routes, navigation, entry points etc.
+        register_page(project_info, &page_name)?;
+
+        Ok(())
+    }
+
+    // Replaces the project name detected from the workspace with the configured name,
+    // in file contents and dir names, like `poly new` does with the template placeholder
+    pub fn rename(&self, project_info: &ProjectInfo) -> Result<(), Error> {
+        let old_name = &project_info.project_name;
+        let new_name = &self.config.name;
+        validate_name(new_name)?;
+
+        if old_name == new_name {
+            println!("Project is already named {}", new_name);
+            return Ok(());
+        }
+
+        let paths = collect_project_entries(&self.config.current_dir);
+
+        for path in &paths.files {
+            rename_in_file(old_name, new_name, path)?;
+        }
+
+        // Deepest dirs first so parent paths stay valid
+        for path in paths.dirs.iter().rev() {
+            rename_dir(old_name, new_name, path)?;
+        }
+
+        Ok(())
+    }
+
+    // Adds a request handler module to the cloudflare project and registers it in files with
+    // poly:add-worker markers, like the router and wrangler.toml, a worker.ts or worker.js in
+    // templates_dir replaces the built-in handler
+    pub fn add_worker(
+        &self,
+        project_info: &ProjectInfo,
+        name: &str,
+        route: Option<&str>,
+        templates_dir: &Path,
+    ) -> Result<(), Error> {
+        validate_scaffold_name("worker", name)?;
+        let snake_name = name.to_case(Case::Snake);
+        let route = route
+            .map(|route| format!("/{}", route.trim_start_matches('/')))
+            .unwrap_or_else(|| format!("/api/{}", snake_name.to_case(Case::Kebab)));
+
+        let src_path = project_info.cloudflare_project_path.join("src");
+        if !src_path.exists() {
+            return Err(Error::CloudflareProjectNotFound(src_path));
+        }
+
+        let (ext, default_template) = if src_path.join("index.ts").exists() {
+            ("ts", WORKER_TS_TEMPLATE)
+        } else {
+            ("js", WORKER_JS_TEMPLATE)
+        };
+
+        let template_name = format!("worker.{}", ext);
+        let template = component_template(templates_dir, &template_name, default_template)?;
+        let expand =
+            |pattern: &str| expand_placeholders(pattern, &snake_name).replace("[route]", &route);
+
+        // Add handler module to cloudflare project
+        write_component(
+            &src_path.join(format!("routes/{}.{}", snake_name, ext)),
+            &expand(&template),
+        )?;
+
+        // Add handler to router, wrangler config etc.
+        register_marked_lines(project_info, ADD_WORKER_MARKER, &expand)?;
+
         Ok(())
     }
 
-    fn prepare_template(&self, temp_dir: &tempfile::TempDir) -> Result<PathBuf, Error> {
+    // Component templates in templates_dir replace the built-in component.rs and component.ts
+    pub fn add_component(
+        &self,
+        project_info: &ProjectInfo,
+        name: &str,
+        with_ts: bool,
+        templates_dir: &Path,
+    ) -> Result<(), Error> {
+        validate_scaffold_name("component", name)?;
+        let snake_name = name.to_case(Case::Snake);
+
+        // Add component to core project
+        let core_src_path = project_info.core_project_path.join("src");
+        let rs_template = component_template(templates_dir, "component.rs", COMPONENT_RS_TEMPLATE)?;
+        write_component(
+            &core_src_path.join(format!("components/{}.rs", snake_name)),
+            &expand_placeholders(&rs_template, &snake_name),
+        )?;
+
+        // Add component to core lib, next to the other components
+        let components_path = core_src_path.join("components.rs");
+        let components_mod_path = if components_path.exists() {
+            components_path
+        } else {
+            core_src_path.join("components/mod.rs")
+        };
+        add_module(&core_src_path.join("lib.rs"), "components")?;
+        add_module(&components_mod_path, &snake_name)?;
+
+        // Add component to web project
+        if with_ts {
+            let ts_template =
+                component_template(templates_dir, "component.ts", COMPONENT_TS_TEMPLATE)?;
+            write_component(
+                &project_info
+                    .web_project_path
+                    .join(format!("src/components/{}.ts", snake_name)),
+                &expand_placeholders(&ts_template, &snake_name),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn prepare_template(&self, temp_dir: &tempfile::TempDir) -> Result<PreparedTemplate, Error> {
         let template_info = self.config.template.info();
-        let temp_dir_path = temp_dir.path();
-        let template_dir = temp_dir_path.join(&template_info.path);
+        let template_dir = temp_dir.path().join("archive");
+
+        let template_cache = TemplateCache::new(template_cache::Config {
+            cache_dir: user_config::cache_dir().map(|dir| dir.join("templates")),
+            refresh: self.config.refresh_template,
+        });
+
+        let archive = template_cache
+            .fetch(&template_info.url)
+            .map_err(Error::DownloadTemplate)?;
+
+        let lock = TemplateLock {
+            template: LockedTemplate {
+                url: template_info.url.clone(),
+                path: template_info.path.clone(),
+                commit: archive.commit(),
+                etag: archive.etag.clone(),
+                checksum: template_lock::checksum(&archive.bytes),
+            },
+        };
+
+        template_archive::extract(archive.bytes, &template_info.path, &template_dir)
+            .map_err(Error::ExtractTemplate)?;
+
+        // The manifest is only used for creating, it's not part of the project
+        let manifest =
+            TemplateManifest::from_dir(&template_dir).map_err(Error::TemplateManifest)?;
+        let manifest_path = template_dir.join(template_manifest::MANIFEST_FILE_NAME);
+        if manifest_path.exists() {
+            fs::remove_file(&manifest_path).map_err(Error::WriteFile)?;
+        }
+
+        let replacements = manifest
+            .replacements(&self.config.name, &self.config.vars)
+            .map_err(Error::TemplateManifest)?;
+        let skip_set = manifest.skip_set().map_err(Error::TemplateManifest)?;
+
+        let css = manifest
+            .css_choice(self.config.css.as_deref())
+            .map_err(Error::TemplateManifest)?;
+
+        let mut choices = Vec::new();
+        if let Some(css) = css {
+            remove_template_paths(&template_dir, &manifest.css_removals(&css))?;
+            choices.push(("css".to_string(), css));
+        }
 
-        let bytes = download_file(&template_info)?;
-        extract_zip(bytes, temp_dir_path)?;
-        replace_placeholders(&self.config.name, &template_info, &template_dir)?;
+        replace_placeholders(&replacements, &choices, &skip_set, &template_dir)?;
 
-        Ok(template_dir)
+        Ok(PreparedTemplate {
+            dir: template_dir,
+            manifest,
+            lock,
+        })
+    }
+
+    fn template_record(&self) -> TemplateRecord {
+        TemplateRecord {
+            name: self.config.template.name().to_string(),
+            vars: self.config.vars.clone(),
+            css: self.config.css.clone(),
+            poly_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
     }
 }
 
+// A downloaded template with placeholders replaced
+struct PreparedTemplate {
+    dir: PathBuf,
+    manifest: TemplateManifest,
+    lock: TemplateLock,
+}
+
 struct Paths {
     files: Vec<PathBuf>,
     dirs: Vec<PathBuf>,
@@ -118,6 +686,8 @@ struct Paths {
 #[derive(Clone)]
 pub enum Template {
     CounterTailwind,
+    Minimal,
+    Ssr,
     Custom(TemplateInfo),
 }
 
@@ -125,22 +695,62 @@ pub enum Template {
 pub struct TemplateInfo {
     url: String,
     path: String,
-    placeholder: String,
-    default_page_name: PageName,
 }
 
+const BUILTIN_TEMPLATES_URL: &str =
+    "https://github.com/glotlabs/poly-templates/archive/refs/heads/main.zip";
+
 impl Template {
+    pub fn builtin() -> Vec<Template> {
+        vec![Template::CounterTailwind, Template::Minimal, Template::Ssr]
+    }
+
+    pub fn from_name(name: &str) -> Option<Template> {
+        if is_url(name) {
+            return Some(Template::Custom(TemplateInfo::from_git_url(name)));
+        }
+
+        Template::builtin()
+            .into_iter()
+            .find(|template| template.name() == name)
+    }
+
+    // The template a project was created from, custom templates keep the recorded url
+    pub fn from_record(record: &TemplateRecord, lock: &TemplateLock) -> Template {
+        Template::builtin()
+            .into_iter()
+            .find(|template| template.name() == record.name)
+            .unwrap_or_else(|| {
+                Template::Custom(TemplateInfo {
+                    url: lock.template.url.clone(),
+                    path: lock.template.path.clone(),
+                })
+            })
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Template::CounterTailwind => "counter-tailwind",
+            Template::Minimal => "minimal",
+            Template::Ssr => "ssr",
+            Template::Custom(info) => &info.url,
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        match self {
+            Template::CounterTailwind => "Counter example styled with tailwind",
+            Template::Minimal => "Single page with plain css, builds without node or npm",
+            Template::Ssr => "Counter example with server side rendering in a cloudflare worker",
+            Template::Custom(_) => "Custom template",
+        }
+    }
+
     pub fn info(&self) -> TemplateInfo {
         match self {
-            Template::CounterTailwind => {
-                // fmt
-                TemplateInfo{
-                    url: "https://github.com/glotlabs/poly-templates/archive/refs/heads/main.zip".to_string(),
-                    path: "counter-tailwind".to_string(),
-                    placeholder: "myapp".to_string(),
-                    default_page_name: PageName::new("home_page"),
-                }
-            }
+            Template::CounterTailwind => builtin_template_info("counter-tailwind"),
+            Template::Minimal => builtin_template_info("minimal"),
+            Template::Ssr => builtin_template_info("counter-tailwind-ssr"),
 
             Template::Custom(info) => {
                 // fmt
@@ -150,31 +760,221 @@ impl Template {
     }
 }
 
-fn download_file(template_info: &TemplateInfo) -> Result<Vec<u8>, Error> {
-    let response = ureq::get(&template_info.url)
-        .call()
-        .map_err(Error::GetUrl)?;
+impl TemplateInfo {
+    pub fn source(&self) -> String {
+        if self.path.is_empty() {
+            self.url.clone()
+        } else {
+            format!("{} ({})", self.url, self.path)
+        }
+    }
+
+    // Accepts <repo url>[#<branch or tag>[:<subdir>]] or a direct link to a zip archive
+    pub fn from_git_url(template_url: &str) -> TemplateInfo {
+        let (repo_url, fragment) = template_url.split_once('#').unwrap_or((template_url, ""));
+
+        let (git_ref, path) = fragment.split_once(':').unwrap_or((fragment, ""));
+        let git_ref = if git_ref.is_empty() { "HEAD" } else { git_ref };
 
-    let mut buffer = Vec::new();
+        TemplateInfo {
+            url: archive_url(repo_url.trim_end_matches('/'), git_ref),
+            path: path.trim_matches('/').to_string(),
+        }
+    }
+}
 
-    response
-        .into_reader()
-        .read_to_end(&mut buffer)
-        .map_err(Error::ReadResponse)?;
+fn is_url(s: &str) -> bool {
+    s.starts_with("https://") || s.starts_with("http://")
+}
 
-    Ok(buffer)
+fn archive_url(repo_url: &str, git_ref: &str) -> String {
+    let repo_url = repo_url.trim_end_matches(".git");
+
+    if repo_url.ends_with(".zip") {
+        repo_url.to_string()
+    } else if repo_url.contains("gitlab") {
+        let repo_name = repo_url.rsplit('/').next().unwrap_or_default();
+        format!(
+            "{}/-/archive/{}/{}-{}.zip",
+            repo_url,
+            git_ref,
+            repo_name,
+            git_ref.replace('/', "-")
+        )
+    } else {
+        format!("{}/archive/{}.zip", repo_url, git_ref)
+    }
 }
 
-fn extract_zip(bytes: Vec<u8>, base_path: &Path) -> Result<(), Error> {
-    let mut cursor = Cursor::new(bytes);
-    zip_extract::extract(&mut cursor, base_path, true).map_err(Error::ZipExtract)?;
+// The built-in templates are dirs in the poly-templates repo
+fn builtin_template_info(path: &str) -> TemplateInfo {
+    TemplateInfo {
+        url: BUILTIN_TEMPLATES_URL.to_string(),
+        path: path.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UpgradeChange {
+    Added,
+    Updated,
+    Merged,
+    Conflict,
+    DeletedLocally,
+    RemovedFromTemplate,
+}
+
+impl UpgradeChange {
+    fn label(&self) -> &str {
+        match self {
+            UpgradeChange::Added => "added:     ",
+            UpgradeChange::Updated => "updated:   ",
+            UpgradeChange::Merged => "merged:    ",
+            UpgradeChange::Conflict => "conflict:  ",
+            UpgradeChange::DeletedLocally => "skipped (deleted locally):",
+            UpgradeChange::RemovedFromTemplate => "kept (removed from template):",
+        }
+    }
+}
+
+// None when the template didn't change the file or the project already has the new content
+fn upgrade_file(
+    rel_path: &Path,
+    base_dir: &Path,
+    new_dir: &Path,
+    project_dir: &Path,
+    dry_run: bool,
+) -> Result<Option<UpgradeChange>, Error> {
+    let read = |path: PathBuf| fs::read(path).ok();
+    let base = read(base_dir.join(rel_path));
+    let new = read(new_dir.join(rel_path)).unwrap_or_default();
+    let project_path = project_dir.join(rel_path);
+    let current = read(project_path.clone());
+
+    let change = match (&base, &current) {
+        (Some(base), _) if *base == new => return Ok(None),
+        (_, Some(current)) if *current == new => return Ok(None),
+        (Some(_), None) => return Ok(Some(UpgradeChange::DeletedLocally)),
+        (None, None) => UpgradeChange::Added,
+        (Some(base), Some(current)) if base == current => UpgradeChange::Updated,
+        (base, Some(current)) => {
+            let (change, content) = merge_file(current, base.as_deref().unwrap_or_default(), &new);
+
+            if !dry_run {
+                fs::write(&project_path, content).map_err(Error::WriteFile)?;
+            }
+
+            return Ok(Some(change));
+        }
+    };
+
+    if !dry_run {
+        write_new_file(&project_path, &String::from_utf8_lossy(&new))?;
+    }
+
+    Ok(Some(change))
+}
+
+// Uses git merge-file, without git the file is left with conflict markers around both versions
+fn merge_file(current: &[u8], base: &[u8], new: &[u8]) -> (UpgradeChange, Vec<u8>) {
+    let merged = tempfile::tempdir().ok().and_then(|dir| {
+        let paths = [
+            dir.path().join("current"),
+            dir.path().join("base"),
+            dir.path().join("new"),
+        ];
+
+        for (path, content) in paths.iter().zip([current, base, new]) {
+            fs::write(path, content).ok()?;
+        }
+
+        let output = process::Command::new("git")
+            .args([
+                "merge-file",
+                "-p",
+                "-L",
+                "project",
+                "-L",
+                "base",
+                "-L",
+                "template",
+            ])
+            .args(&paths)
+            .output()
+            .ok()?;
+
+        // The exit code is the number of conflicts, negative on errors
+        match output.status.code() {
+            Some(0) => Some((UpgradeChange::Merged, output.stdout)),
+            Some(code) if code > 0 => Some((UpgradeChange::Conflict, output.stdout)),
+            _ => None,
+        }
+    });
+
+    merged.unwrap_or_else(|| {
+        let content = [
+            b"<<<<<<< project\n".as_slice(),
+            current,
+            b"=======\n",
+            new,
+            b">>>>>>> template\n",
+        ]
+        .concat();
+
+        (UpgradeChange::Conflict, content)
+    })
+}
+
+fn run_post_create_command(
+    command: &exec::CommandEntry,
+    exec_config: &exec::Config,
+    sandbox: Option<&Sandbox>,
+) -> Result<(), Error> {
+    let text = command.text().to_string();
+
+    match (sandbox, command.is_interactive()) {
+        (Some(sandbox), true) => sandbox
+            .run_interactive(exec_config)
+            .map_err(|err| Error::PostCreateSandbox(text, err)),
+
+        (Some(sandbox), false) => sandbox
+            .run(exec_config)
+            .map(|_| ())
+            .map_err(|err| Error::PostCreateSandbox(text, err)),
+
+        (None, true) => {
+            exec::run_interactive(exec_config).map_err(|err| Error::PostCreateCommand(text, err))
+        }
+
+        (None, false) => exec::run(exec_config)
+            .map(|_| ())
+            .map_err(|err| Error::PostCreateCommand(text, err)),
+    }
+}
+
+fn remove_template_paths(template_dir: &Path, rel_paths: &[String]) -> Result<(), Error> {
+    for rel_path in rel_paths {
+        let path = template_dir.join(rel_path.trim_start_matches('/'));
+
+        let res = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else if path.exists() {
+            fs::remove_file(&path)
+        } else {
+            continue;
+        };
+
+        println!("Removing {}", path.display());
+        res.map_err(|err| Error::RemoveTemplatePath(path, err))?;
+    }
 
     Ok(())
 }
 
 fn replace_placeholders(
-    project_name: &str,
-    template_info: &TemplateInfo,
+    replacements: &[(String, String)],
+    choices: &[(String, String)],
+    skip_set: &GlobSet,
     template_dir: &PathBuf,
 ) -> Result<(), Error> {
     let paths = collect_dir_entries(template_dir);
@@ -182,13 +982,19 @@ fn replace_placeholders(
     paths
         .files
         .iter()
-        .map(|path| replace_placeholder_in_file(project_name, template_info, path))
+        .filter(|path| {
+            let rel_path = path.strip_prefix(template_dir).unwrap_or(path);
+            !skip_set.is_match(rel_path)
+        })
+        .map(|path| replace_placeholder_in_file(replacements, choices, path))
         .collect::<Result<(), Error>>()?;
 
+    // Deepest dirs first so parent paths stay valid
     paths
         .dirs
         .iter()
-        .map(|path| replace_placeholder_in_dir(project_name, template_info, path))
+        .rev()
+        .map(|path| replace_placeholder_in_dir(replacements, path))
         .collect::<Result<(), Error>>()?;
 
     Ok(())
@@ -226,22 +1032,17 @@ fn collect_dir_entries(template_dir: &PathBuf) -> Paths {
 }
 
 fn replace_placeholder_in_file(
-    project_name: &str,
-    template_info: &TemplateInfo,
+    replacements: &[(String, String)],
+    choices: &[(String, String)],
     file_path: &PathBuf,
 ) -> Result<(), Error> {
-    println!(
-        "Replacing placeholder: {} -> {} in {}",
-        template_info.placeholder,
-        project_name,
-        file_path.display()
-    );
+    println!("Replacing placeholders in {}", file_path.display());
 
     let old_file = file_util::read(file_path).map_err(Error::ReadFile)?;
 
-    let new_content = old_file
-        .content
-        .replace(&template_info.placeholder, &project_name);
+    let content = template_manifest::apply_conditions(&old_file.content, choices)
+        .map_err(|err| Error::TemplateCondition(file_path.clone(), err))?;
+    let new_content = template_manifest::replace_all(&content, replacements);
 
     let new_file = file_util::FileData {
         content: new_content,
@@ -254,14 +1055,13 @@ fn replace_placeholder_in_file(
 }
 
 fn replace_placeholder_in_dir(
-    project_name: &str,
-    template_info: &TemplateInfo,
+    replacements: &[(String, String)],
     dir_path: &PathBuf,
 ) -> Result<(), Error> {
     let dir_name = dir_path.file_name().and_then(|name| name.to_str());
 
     if let Some(old_dir_name) = dir_name {
-        let new_dir_name = old_dir_name.replace(&template_info.placeholder, &project_name);
+        let new_dir_name = template_manifest::replace_all(old_dir_name, replacements);
         let new_dir_path = dir_path.with_file_name(&new_dir_name);
 
         if new_dir_name != old_dir_name {
@@ -277,12 +1077,133 @@ fn replace_placeholder_in_dir(
     Ok(())
 }
 
-fn copy_to_dest(project_name: &str, template_dir: &PathBuf, dest: &PathBuf) -> Result<(), Error> {
-    let tmp_project_path = template_dir.with_file_name(project_name);
-    fs::rename(&template_dir, &tmp_project_path).map_err(Error::RenameTemplateDir)?;
+const RENAME_SKIP_DIRS: [&str; 5] = ["target", "node_modules", "dist", ".git", ".poly"];
+
+fn collect_project_entries(project_dir: &Path) -> Paths {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut dirs: Vec<PathBuf> = Vec::new();
+
+    let entries = WalkDir::new(project_dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !RENAME_SKIP_DIRS.iter().any(|dir| entry.file_name() == *dir))
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+
+            Err(err) => {
+                eprintln!("Warning: Can't access file: {}", err);
+                None
+            }
+        });
+
+    for entry in entries {
+        let file_type = entry.file_type();
+
+        if file_type.is_file() {
+            files.push(entry.path().to_path_buf());
+        } else if file_type.is_dir() {
+            dirs.push(entry.path().to_path_buf());
+        }
+    }
+
+    Paths { files, dirs }
+}
+
+fn rename_in_file(old_name: &str, new_name: &str, file_path: &PathBuf) -> Result<(), Error> {
+    let old_file = match file_util::read(file_path) {
+        Ok(file) => file,
+
+        // Binary files are left as is
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => return Ok(()),
+
+        Err(err) => return Err(Error::ReadFile(err)),
+    };
+
+    let new_content = replace_name(&old_file.content, old_name, new_name);
+
+    if new_content != old_file.content {
+        println!("Updating {}", file_path.display());
+
+        let new_file = file_util::FileData {
+            content: new_content,
+            permissions: old_file.permissions,
+        };
+
+        file_util::write(file_path, new_file).map_err(Error::WriteFile)?;
+    }
+
+    Ok(())
+}
+
+fn rename_dir(old_name: &str, new_name: &str, dir_path: &Path) -> Result<(), Error> {
+    let dir_name = dir_path.file_name().and_then(|name| name.to_str());
+
+    if let Some(old_dir_name) = dir_name {
+        let new_dir_name = replace_name(old_dir_name, old_name, new_name);
+
+        if new_dir_name != old_dir_name {
+            let new_dir_path = dir_path.with_file_name(&new_dir_name);
+            println!(
+                "Renaming {} -> {}",
+                dir_path.display(),
+                new_dir_path.display()
+            );
+            fs::rename(dir_path, new_dir_path).map_err(Error::RenameDir)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Only replaces the name when it's not part of a longer word, `shop_core` but not `shopping`
+fn replace_name(content: &str, old_name: &str, new_name: &str) -> String {
+    let is_word_char = |c: Option<char>| c.map(|c| c.is_ascii_alphanumeric()).unwrap_or(false);
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+    // The char of content in front of rest
+    let mut prev_char = None;
+
+    while let Some(index) = rest.find(old_name) {
+        let before = rest[..index].chars().last().or(prev_char);
+        let after = rest[index + old_name.len()..].chars().next();
 
-    fs_extra::dir::copy(tmp_project_path, dest, &fs_extra::dir::CopyOptions::new())
-        .map_err(Error::CopyToDestination)?;
+        result.push_str(&rest[..index]);
+
+        if is_word_char(before) || is_word_char(after) {
+            result.push_str(old_name);
+        } else {
+            result.push_str(new_name);
+        }
+
+        prev_char = old_name.chars().last();
+        rest = &rest[index + old_name.len()..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+// An existing dir is only used when it's empty, nothing is ever overwritten
+fn ensure_empty_destination(dest: &Path) -> Result<(), Error> {
+    match fs::read_dir(dest).map(|mut entries| entries.next().is_none()) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Error::DestinationNotEmpty(dest.to_path_buf())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(_) if dest.exists() => Err(Error::DestinationNotEmpty(dest.to_path_buf())),
+        Err(err) => Err(Error::CreateDestination(err)),
+    }
+}
+
+fn copy_to_dest(template_dir: &PathBuf, dest: &PathBuf) -> Result<(), Error> {
+    ensure_empty_destination(dest)?;
+    fs::create_dir_all(dest).map_err(Error::CreateDestination)?;
+
+    let options = fs_extra::dir::CopyOptions {
+        content_only: true,
+        ..fs_extra::dir::CopyOptions::new()
+    };
+
+    fs_extra::dir::copy(template_dir, dest, &options).map_err(Error::CopyToDestination)?;
 
     Ok(())
 }
@@ -321,26 +1242,37 @@ fn copy_page_template(
     Ok(())
 }
 
-fn add_page_to_lib(base_path: &PathBuf, page_name: &PageName) -> Result<(), Error> {
-    let lib_path = base_path.join("src/lib.rs");
-    let lib_file = file_util::read(&lib_path).map_err(Error::ReadLibFile)?;
-    let page_module = format!("pub mod {};", page_name.snake_case());
+fn add_page_to_lib(base_path: &Path, page_name: &PageName) -> Result<(), Error> {
+    add_module(&base_path.join("src/lib.rs"), &page_name.snake_case())
+}
+
+// Appends a `pub mod` declaration, the file is created if it doesn't exist
+fn add_module(path: &PathBuf, module_name: &str) -> Result<(), Error> {
+    let module = format!("pub mod {};", module_name);
+
+    if !path.exists() {
+        print_insertions(path, &[(0, module.clone())]);
+        return write_new_file(path, &format!("{}\n", module));
+    }
+
+    let module_file = file_util::read(path).map_err(Error::ReadLibFile)?;
 
-    let mut new_content = lib_file.content;
+    let mut new_content = module_file.content;
     if !new_content.ends_with('\n') {
         new_content.push_str("\n");
     }
 
-    if !new_content.contains(&page_module) {
-        new_content.push_str(&page_module);
+    if !new_content.lines().any(|line| line.trim() == module) {
+        print_insertions(path, &[(new_content.lines().count(), module.clone())]);
+        new_content.push_str(&module);
         new_content.push_str("\n");
     }
 
     file_util::write(
-        &lib_path,
+        path,
         file_util::FileData {
             content: new_content,
-            permissions: lib_file.permissions,
+            permissions: module_file.permissions,
         },
     )
     .map_err(Error::WriteFile)?;
@@ -348,20 +1280,383 @@ fn add_page_to_lib(base_path: &PathBuf, page_name: &PageName) -> Result<(), Erro
     Ok(())
 }
 
+const COMPONENT_RS_TEMPLATE: &str = r#"use maud::html;
+use maud::Markup;
+
+pub struct [pascal] {}
+
+impl [pascal] {
+    pub fn render(&self) -> Markup {
+        html! {
+            div id="[kebab]" {
+            }
+        }
+    }
+}
+"#;
+
+const COMPONENT_TS_TEMPLATE: &str = r#"export function init[pascal](element: HTMLElement): void {
+  console.debug("[title] ready", element);
+}
+"#;
+
+const WORKER_TS_TEMPLATE: &str = r#"export async function handle[pascal](request: Request, env: unknown): Promise<Response> {
+  return Response.json({ route: "[route]", method: request.method });
+}
+"#;
+
+const WORKER_JS_TEMPLATE: &str = r#"export async function handle[pascal](request, env) {
+  return Response.json({ route: "[route]", method: request.method });
+}
+"#;
+
+fn component_template(
+    templates_dir: &Path,
+    file_name: &str,
+    default: &str,
+) -> Result<String, Error> {
+    let path = templates_dir.join(file_name);
+
+    if path.exists() {
+        fs::read_to_string(&path).map_err(|err| Error::ReadComponentTemplate(path, err))
+    } else {
+        Ok(default.to_string())
+    }
+}
+
+fn write_component(path: &Path, content: &str) -> Result<(), Error> {
+    if path.exists() {
+        println!("Skipping existing file: {}", path.display());
+        Ok(())
+    } else {
+        println!("Adding file: {}", path.display());
+        write_new_file(path, content)
+    }
+}
+
+fn write_new_file(path: &Path, content: &str) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(Error::WriteFile)?;
+    }
+
+    fs::write(path, content).map_err(Error::WriteFile)
+}
+
+fn validate_scaffold_name(kind: &str, name: &str) -> Result<(), Error> {
+    let starts_with_letter = name
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic())
+        .unwrap_or(false);
+    let has_valid_chars = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    (starts_with_letter && has_valid_chars)
+        .then_some(())
+        .ok_or_else(|| Error::InvalidScaffoldName(kind.to_string()))
+}
+
+const ADD_PAGE_MARKER: &str = "poly:add-page";
+const ADD_WORKER_MARKER: &str = "poly:add-worker";
+const MARKER_SKIP_DIRS: [&str; 6] = [
+    "node_modules",
+    "target",
+    "dist",
+    "wasm",
+    ".git",
+    template_record::RECORD_DIR_NAME,
+];
+
+fn register_page(project_info: &ProjectInfo, page_name: &PageName) -> Result<(), Error> {
+    register_marked_lines(project_info, ADD_PAGE_MARKER, &|pattern| {
+        page_name.expand(pattern)
+    })
+}
+
+// Files with a `<marker> <line>` comment get <line> inserted above the marker,
+// i.e. `// poly:add-page [pascal],` in the route enum adds a variant for each new page
+fn register_marked_lines(
+    project_info: &ProjectInfo,
+    marker: &str,
+    expand: &dyn Fn(&str) -> String,
+) -> Result<(), Error> {
+    let project_paths = [
+        &project_info.core_project_path,
+        &project_info.wasm_project_path,
+        &project_info.web_project_path,
+        &project_info.cloudflare_project_path,
+    ];
+
+    let files = project_paths
+        .into_iter()
+        .filter(|path| path.exists())
+        .flat_map(|path| {
+            WalkDir::new(path)
+                .into_iter()
+                .filter_entry(|entry| !MARKER_SKIP_DIRS.iter().any(|dir| entry.file_name() == *dir))
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| entry.into_path())
+        });
+
+    for path in files {
+        // Binary and non utf-8 files can't contain markers
+        let file = match file_util::read(&path) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+
+        if !file.content.contains(marker) {
+            continue;
+        }
+
+        let (new_content, insertions) = insert_marked_lines(&file.content, marker, expand);
+
+        if !insertions.is_empty() {
+            print_insertions(&path, &insertions);
+
+            file_util::write(
+                &path,
+                file_util::FileData {
+                    content: new_content,
+                    permissions: file.permissions,
+                },
+            )
+            .map_err(Error::WriteFile)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Returns the new content and the inserted lines with their line index in the new content
+fn insert_marked_lines(
+    content: &str,
+    marker: &str,
+    expand: &dyn Fn(&str) -> String,
+) -> (String, Vec<(usize, String)>) {
+    let existing_lines: HashSet<&str> = content.lines().map(|line| line.trim()).collect();
+    let mut new_lines: Vec<String> = Vec::new();
+    let mut insertions = Vec::new();
+
+    for line in content.lines() {
+        if let Some((_, pattern)) = line.split_once(marker) {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            let new_line = expand(pattern.trim());
+
+            // Skip lines that are already there to make the command idempotent
+            if !new_line.is_empty() && !existing_lines.contains(new_line.as_str()) {
+                insertions.push((new_lines.len(), format!("{}{}", indent, new_line)));
+                new_lines.push(format!("{}{}", indent, new_line));
+            }
+        }
+
+        new_lines.push(line.to_string());
+    }
+
+    let mut new_content = new_lines.join("\n");
+    if content.ends_with('\n') {
+        new_content.push('\n');
+    }
+
+    (new_content, insertions)
+}
+
+fn print_insertions(path: &Path, insertions: &[(usize, String)]) {
+    println!("--- {}", path.display());
+    println!("+++ {}", path.display());
+
+    for (index, line) in insertions {
+        println!("@@ +{},1 @@", index + 1);
+        println!("+{}", line);
+    }
+}
+
+const GITIGNORE_ENTRIES: [&str; 7] = [
+    "/target",
+    "node_modules/",
+    "/dist",
+    "/*_web/wasm",
+    "/*_web/wasm_backend",
+    "/*_cloudflare/dist_backend",
+    "/.poly",
+];
+
+fn is_inside_git_repo(dir: &Path) -> bool {
+    process::Command::new("git")
+        .current_dir(dir)
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+// Entries of a .gitignore from the template are kept, missing poly entries are appended
+fn write_gitignore(project_dir: &Path) -> Result<(), Error> {
+    let path = project_dir.join(".gitignore");
+    let mut content = fs::read_to_string(&path).unwrap_or_default();
+
+    let missing: Vec<&str> = GITIGNORE_ENTRIES
+        .into_iter()
+        .filter(|entry| !content.lines().any(|line| line.trim() == *entry))
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+
+    for entry in missing {
+        content.push_str(entry);
+        content.push('\n');
+    }
+
+    fs::write(&path, content).map_err(Error::WriteFile)
+}
+
+// No input, i.e. stdin is closed, counts as no
+fn confirm(question: &str) -> Result<bool, Error> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush().map_err(Error::ReadConfirmation)?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(Error::ReadConfirmation)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// The name ends up bare in rust paths and js imports, so keywords and std crates can't be used
+const RESERVED_NAMES: [&str; 45] = [
+    "as",
+    "async",
+    "await",
+    "break",
+    "const",
+    "continue",
+    "crate",
+    "dyn",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "fn",
+    "for",
+    "if",
+    "impl",
+    "in",
+    "let",
+    "loop",
+    "match",
+    "mod",
+    "move",
+    "mut",
+    "pub",
+    "ref",
+    "return",
+    "self",
+    "static",
+    "struct",
+    "super",
+    "trait",
+    "true",
+    "type",
+    "unsafe",
+    "use",
+    "where",
+    "while",
+    "std",
+    "core",
+    "alloc",
+    "test",
+    "proc_macro",
+    "node_modules",
+    "favicon",
+    "poly",
+];
+
+const NAME_BOUNDARIES: [Boundary; 5] = [
+    Boundary::Underscore,
+    Boundary::Hyphen,
+    Boundary::Space,
+    Boundary::LowerUpper,
+    Boundary::Acronym,
+];
+
+// Converts a name like `My App` or `my-app` to a valid crate and npm package name,
+// i.e. `my_app`, names that can't be converted are rejected
+pub fn normalize_name(name: &str) -> Result<String, Error> {
+    // convert_case only handles ascii
+    let ascii_name: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { ' ' })
+        .collect();
+
+    // Digits stay part of the word, `shop2` and not `shop_2`
+    let snake_name: String = ascii_name
+        .from_case(Case::Snake)
+        .with_boundaries(&NAME_BOUNDARIES)
+        .to_case(Case::Snake)
+        .chars()
+        .filter(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '_')
+        .collect();
+
+    let normalized = snake_name
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+
+    if normalized.is_empty() && !name.trim().is_empty() {
+        return Err(Error::InvalidProjectName(
+            name.to_string(),
+            "the name has no ascii letters or digits".to_string(),
+        ));
+    }
+
+    validate_name(&normalized).map_err(|err| match err {
+        // Report the name as given, the normalized one would be confusing
+        Error::InvalidProjectName(_, reason) => Error::InvalidProjectName(name.to_string(), reason),
+        err => err,
+    })?;
+
+    Ok(normalized)
+}
+
 fn validate_name(name: &str) -> Result<(), Error> {
-    let not_empty = !name.is_empty();
-    let has_valid_chars = name.chars().all(|c| c.is_ascii_lowercase() || c == '_');
-    let first_char_is_ascii = name
+    let invalid = |reason: &str| {
+        Err(Error::InvalidProjectName(
+            name.to_string(),
+            reason.to_string(),
+        ))
+    };
+
+    let has_valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    let first_char_is_letter = name
         .chars()
-        .nth(0)
+        .next()
         .map(|c| c.is_ascii_lowercase())
         .unwrap_or(false);
 
-    [not_empty, has_valid_chars, first_char_is_ascii]
-        .into_iter()
-        .all(identity)
-        .then_some(())
-        .ok_or(Error::InvalidProjectName)
+    if name.is_empty() {
+        invalid("the name is empty")
+    } else if !has_valid_chars {
+        invalid("use lowercase ascii letters, digits and underscores")
+    } else if !first_char_is_letter {
+        invalid("the name must start with a letter")
+    } else if RESERVED_NAMES.contains(&name) {
+        invalid("the name is reserved")
+    } else {
+        Ok(())
+    }
 }
 
 fn replace_page_name(content: &str, from: &PageName, to: &PageName) -> String {
@@ -401,4 +1696,24 @@ impl PageName {
     pub fn title_case(&self) -> String {
         self.0.from_case(Case::Snake).to_case(Case::Title)
     }
+
+    pub fn route(&self) -> String {
+        route_report::route_from_page_name(&self.0)
+    }
+
+    fn expand(&self, pattern: &str) -> String {
+        expand_placeholders(pattern, &self.0).replace("[route]", &self.route())
+    }
+}
+
+// Replaces the name placeholders used in poly:add-page markers and component templates
+fn expand_placeholders(pattern: &str, snake_name: &str) -> String {
+    let convert = |case| snake_name.from_case(Case::Snake).to_case(case);
+
+    pattern
+        .replace("[snake]", snake_name)
+        .replace("[pascal]", &convert(Case::Pascal))
+        .replace("[camel]", &convert(Case::Camel))
+        .replace("[kebab]", &convert(Case::Kebab))
+        .replace("[title]", &convert(Case::Title))
 }
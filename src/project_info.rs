@@ -1,4 +1,8 @@
+use crate::poly_config;
+use crate::poly_config::LayoutConfig;
 use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::path::Path;
@@ -7,14 +11,64 @@ use std::path::PathBuf;
 #[derive(Debug)]
 pub enum Error {
     CurrentDirNotAbsolute(PathBuf),
-    NoProjectName,
-    WebProjectNotFound(PathBuf),
-    WasmProjectNotFound(PathBuf),
-    ReadCargoWorkspace(io::Error),
-    ParseCargoWorkspace(toml::de::Error),
+    // The workspace members are None when there is no Cargo.toml
+    ProjectNotFound {
+        dir: PathBuf,
+        workspace_members: Option<Vec<String>>,
+    },
+    ReadCargoWorkspace(PathBuf, io::Error),
+    ParseCargoWorkspace(PathBuf, toml::de::Error),
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::CurrentDirNotAbsolute(dir) => {
+                write!(f, "Expected an absolute project dir, got {}", dir.display())
+            }
+
+            Error::ProjectNotFound {
+                dir,
+                workspace_members,
+            } => {
+                writeln!(f, "No poly project found in {}", dir.display())?;
+                writeln!(f, "Looked for the project name in:")?;
+
+                match workspace_members {
+                    Some(members) => writeln!(
+                        f,
+                        "  - Cargo.toml: no workspace member ends with _core, members: [{}]",
+                        members.join(", ")
+                    )?,
+                    None => writeln!(f, "  - Cargo.toml: not found")?,
+                }
+
+                writeln!(f, "  - a <name>_core dir: not found")?;
+                writeln!(
+                    f,
+                    "  - [layout] name in {}: not set",
+                    poly_config::CONFIG_FILE_NAME
+                )?;
+                write!(
+                    f,
+                    "Run `poly new <name>` to create a project, or set the name and dirs under [layout] \
+                     in {} if the project uses other dir names",
+                    poly_config::CONFIG_FILE_NAME
+                )
+            }
+
+            Error::ReadCargoWorkspace(path, err) => {
+                write!(f, "Failed to read {}: {}", path.display(), err)
+            }
+
+            Error::ParseCargoWorkspace(path, err) => {
+                write!(f, "Failed to parse {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectInfo {
     pub project_name: String,
     pub dist_path: PathBuf,
@@ -22,37 +76,45 @@ pub struct ProjectInfo {
     pub core_project_path: PathBuf,
     pub wasm_project_path: PathBuf,
     pub cloudflare_project_path: PathBuf,
-    pub backend_dist_path: PathBuf,
+    // None without a cloudflare project, the backend wasm isn't built then
+    pub backend_dist_path: Option<PathBuf>,
 }
 
 impl ProjectInfo {
-    pub fn from_dir(current_dir: &PathBuf) -> Result<ProjectInfo, Error> {
+    // Paths not set in the layout follow the <name>_<part> naming
+    pub fn from_dir(current_dir: &PathBuf, layout: &LayoutConfig) -> Result<ProjectInfo, Error> {
         current_dir
             .is_absolute()
             .then_some(())
             .ok_or(Error::CurrentDirNotAbsolute(current_dir.clone()))?;
 
-        let cargo_workspace = CargoWorkspaceConfig::from_cargo_toml(current_dir)?;
-        let project_name = cargo_workspace.project_name().ok_or(Error::NoProjectName)?;
-        let web_project_path = current_dir.join(format!("{}_web", project_name));
-        let core_project_path = current_dir.join(format!("{}_core", project_name));
-        let wasm_project_path = current_dir.join(format!("{}_wasm", project_name));
-        let cloudflare_project_path = current_dir.join(format!("{}_cloudflare", project_name));
-        let backend_dist_path = cloudflare_project_path.join("dist_backend");
+        let crates = workspace_crates(current_dir);
 
-        Path::new(&web_project_path)
-            .exists()
-            .then_some(())
-            .ok_or(Error::WebProjectNotFound(web_project_path.clone()))?;
+        let project_name = match (&layout.name, crates.project_name()) {
+            (Some(name), _) => name.clone(),
+            (None, Some(name)) => name,
+            (None, None) => name_from_dirs(current_dir)?,
+        };
 
-        Path::new(&wasm_project_path)
+        // The crates are found by name, their dirs can be named anything
+        let part_path = |path: &Option<PathBuf>, suffix: &str| match path {
+            Some(path) => current_dir.join(path),
+            None => crates
+                .crate_dir(&format!("{}_{}", project_name, suffix))
+                .unwrap_or_else(|| current_dir.join(format!("{}_{}", project_name, suffix))),
+        };
+
+        let web_project_path = part_path(&layout.web, "web");
+        let core_project_path = part_path(&layout.core, "core");
+        let wasm_project_path = part_path(&layout.wasm, "wasm");
+        let cloudflare_project_path = part_path(&layout.cloudflare, "cloudflare");
+        let backend_dist_path = cloudflare_project_path
             .exists()
-            .then_some(())
-            .ok_or(Error::WasmProjectNotFound(wasm_project_path.clone()))?;
+            .then(|| cloudflare_project_path.join("dist_backend"));
 
         Ok(ProjectInfo {
             project_name,
-            dist_path: current_dir.join("dist"),
+            dist_path: layout.dist_path(current_dir),
             web_project_path,
             core_project_path,
             wasm_project_path,
@@ -60,20 +122,106 @@ impl ProjectInfo {
             backend_dist_path,
         })
     }
+}
+
+// The closest dir at or above `dir` with a poly.toml or a cargo workspace with a *_core member
+pub fn find_project_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join(poly_config::CONFIG_FILE_NAME).exists() || is_workspace_root(dir))
+        .map(|dir| dir.to_path_buf())
+}
 
-    pub fn core_project_path_src(&self) -> PathBuf {
-        self.core_project_path.join("src")
+// Crate names with dashes normalized to underscores, like cargo does for the lib names
+#[derive(Debug, Default)]
+struct WorkspaceCrates {
+    crates: Vec<(String, PathBuf)>,
+}
+
+impl WorkspaceCrates {
+    fn project_name(&self) -> Option<String> {
+        self.crates
+            .iter()
+            .find_map(|(name, _)| name.strip_suffix("_core"))
+            .map(String::from)
     }
 
-    pub fn web_project_path_src(&self) -> PathBuf {
-        self.web_project_path.join("src")
+    fn crate_dir(&self, name: &str) -> Option<PathBuf> {
+        self.crates
+            .iter()
+            .find(|(crate_name, _)| crate_name == name)
+            .map(|(_, dir)| dir.clone())
     }
+}
+
+// The workspace crates in `dir` according to cargo, empty when cargo can't read the
+// workspace so that the dir naming convention is used instead
+fn workspace_crates(dir: &Path) -> WorkspaceCrates {
+    let metadata = match cargo_metadata::MetadataCommand::new()
+        .current_dir(dir)
+        .no_deps()
+        .exec()
+    {
+        Ok(metadata) => metadata,
+        Err(_) => return WorkspaceCrates::default(),
+    };
+
+    let crates = metadata
+        .workspace_packages()
+        .into_iter()
+        .filter_map(|package| {
+            let crate_dir = package.manifest_path.parent()?.as_std_path();
+            crate_dir
+                .starts_with(dir)
+                .then(|| (package.name.replace('-', "_"), crate_dir.to_path_buf()))
+        })
+        .collect();
 
-    pub fn web_project_path_css(&self) -> PathBuf {
-        self.web_project_path.join("css")
+    WorkspaceCrates { crates }
+}
+
+// Falls back on the dir naming when cargo can't tell, apps of a monorepo are usually
+// members of the root workspace, without a Cargo.toml
+fn name_from_dirs(current_dir: &PathBuf) -> Result<String, Error> {
+    match CargoWorkspaceConfig::from_cargo_toml(current_dir) {
+        Ok(workspace) => workspace
+            .project_name()
+            .or_else(|| core_dir_name(current_dir))
+            .ok_or_else(|| Error::ProjectNotFound {
+                dir: current_dir.clone(),
+                workspace_members: Some(workspace.workspace.members.clone()),
+            }),
+        Err(Error::ReadCargoWorkspace(_, err)) if err.kind() == io::ErrorKind::NotFound => {
+            core_dir_name(current_dir).ok_or_else(|| Error::ProjectNotFound {
+                dir: current_dir.clone(),
+                workspace_members: None,
+            })
+        }
+        Err(err) => core_dir_name(current_dir).ok_or(err),
     }
 }
 
+fn core_dir_name(dir: &Path) -> Option<String> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_suffix("_core")
+                .map(String::from)
+        })
+        .next()
+}
+
+fn is_workspace_root(dir: &Path) -> bool {
+    CargoWorkspaceConfig::from_cargo_toml(&dir.to_path_buf())
+        .ok()
+        .and_then(|config| config.project_name())
+        .is_some()
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct CargoWorkspaceConfig {
     pub workspace: Workspace,
@@ -82,10 +230,11 @@ pub struct CargoWorkspaceConfig {
 impl CargoWorkspaceConfig {
     pub fn from_cargo_toml(path: &PathBuf) -> Result<Self, Error> {
         let cargo_workspace_path = path.join("Cargo.toml");
-        let cargo_workspace_toml =
-            fs::read_to_string(cargo_workspace_path).map_err(Error::ReadCargoWorkspace)?;
+        let cargo_workspace_toml = fs::read_to_string(&cargo_workspace_path)
+            .map_err(|err| Error::ReadCargoWorkspace(cargo_workspace_path.clone(), err))?;
 
-        toml::from_str(&cargo_workspace_toml).map_err(Error::ParseCargoWorkspace)
+        toml::from_str(&cargo_workspace_toml)
+            .map_err(|err| Error::ParseCargoWorkspace(cargo_workspace_path, err))
     }
 
     pub fn project_name(&self) -> Option<String> {
@@ -0,0 +1,124 @@
+use crate::poly_config::LayoutConfig;
+use crate::project_info;
+use crate::project_info::ProjectInfo;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+const CACHE_FILE_NAME: &str = "project-info.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Source {
+    // None when the file doesn't exist, so creating it invalidates the entry too
+    File {
+        path: PathBuf,
+        modified: Option<SystemTime>,
+    },
+    // Only the existence, the dirs are written to by the builds
+    Dir {
+        path: PathBuf,
+        exists: bool,
+    },
+}
+
+impl Source {
+    fn file(path: PathBuf) -> Source {
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        Source::File { path, modified }
+    }
+
+    fn dir(path: PathBuf) -> Source {
+        let exists = path.is_dir();
+
+        Source::Dir { path, exists }
+    }
+
+    fn is_fresh(&self) -> bool {
+        let current = match self {
+            Source::File { path, .. } => Source::file(path.clone()),
+            Source::Dir { path, .. } => Source::dir(path.clone()),
+        };
+
+        current == *self
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    poly_version: String,
+    layout: LayoutConfig,
+    sources: Vec<Source>,
+    project_info: ProjectInfo,
+}
+
+// Resolving the project info runs cargo metadata, the result is kept in .poly until one
+// of the manifests or dirs it was derived from changes
+pub fn load(
+    current_dir: &PathBuf,
+    layout: &LayoutConfig,
+) -> Result<ProjectInfo, project_info::Error> {
+    let cache_path = current_dir.join(".poly").join(CACHE_FILE_NAME);
+
+    if let Some(entry) = read_entry(&cache_path) {
+        let is_fresh = entry.poly_version == env!("CARGO_PKG_VERSION")
+            && entry.layout == *layout
+            && entry.sources.iter().all(Source::is_fresh);
+
+        if is_fresh {
+            return Ok(entry.project_info);
+        }
+    }
+
+    let project_info = ProjectInfo::from_dir(current_dir, layout)?;
+
+    let entry = Entry {
+        poly_version: env!("CARGO_PKG_VERSION").to_string(),
+        layout: layout.clone(),
+        sources: sources(current_dir, &project_info),
+        project_info,
+    };
+
+    // The cache is only an optimization, the project info is resolved again next time
+    let _ = write_entry(&cache_path, &entry);
+
+    Ok(entry.project_info)
+}
+
+fn read_entry(path: &Path) -> Option<Entry> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_entry(path: &Path, entry: &Entry) -> Option<()> {
+    fs::create_dir_all(path.parent()?).ok()?;
+    let json = serde_json::to_string_pretty(entry).ok()?;
+    fs::write(path, json).ok()
+}
+
+fn sources(current_dir: &Path, project_info: &ProjectInfo) -> Vec<Source> {
+    // Cargo reads the closest workspace, it can be in a parent dir for apps of a monorepo
+    let mut sources: Vec<Source> = current_dir
+        .ancestors()
+        .map(|dir| Source::file(dir.join("Cargo.toml")))
+        .collect();
+
+    // The crate names decide the project name, or the core dir name without a workspace
+    sources.push(Source::file(
+        project_info.core_project_path.join("Cargo.toml"),
+    ));
+    sources.push(Source::file(
+        project_info.wasm_project_path.join("Cargo.toml"),
+    ));
+    sources.push(Source::dir(project_info.core_project_path.clone()));
+
+    // The backend is only built when the cloudflare dir exists
+    sources.push(Source::dir(project_info.cloudflare_project_path.clone()));
+
+    sources
+}
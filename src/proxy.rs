@@ -0,0 +1,220 @@
+use http::Request;
+use http::Response;
+use http::StatusCode;
+use http::Uri;
+use std::io::Read;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+// Headers that only apply to a single connection and must not be forwarded
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "te",
+    "trailer",
+    "host",
+    "content-length",
+];
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub prefix: String,
+    pub target: Uri,
+}
+
+impl Rule {
+    fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.prefix.trim_end_matches('/')) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/') || self.prefix.ends_with('/'),
+            None => false,
+        }
+    }
+
+    // The prefix is kept when the target has no path, otherwise it's replaced by the target path
+    fn target_url(&self, uri: &Uri) -> String {
+        let path = uri.path();
+        let target_path = self.target.path().trim_end_matches('/');
+
+        let forwarded_path = if target_path.is_empty() {
+            path.to_string()
+        } else {
+            let rest = path
+                .strip_prefix(self.prefix.trim_end_matches('/'))
+                .unwrap_or(path);
+            format!("{}/{}", target_path, rest.trim_start_matches('/'))
+        };
+
+        let query = uri
+            .query()
+            .map(|query| format!("?{}", query))
+            .unwrap_or_default();
+
+        format!(
+            "{}://{}{}{}",
+            self.target.scheme_str().unwrap_or("http"),
+            self.target.authority().map(|a| a.as_str()).unwrap_or(""),
+            forwarded_path,
+            query
+        )
+    }
+}
+
+// Parses rules like `/api=http://localhost:3000`
+pub fn parse_rules(rules: &[String]) -> Result<Vec<Rule>, String> {
+    rules
+        .iter()
+        .map(|rule| {
+            let (prefix, target) = rule
+                .split_once('=')
+                .ok_or(format!("Invalid proxy rule: {}", rule))?;
+
+            let prefix = prefix.trim();
+            if !prefix.starts_with('/') {
+                return Err(format!("Proxy prefix must start with '/': {}", rule));
+            }
+
+            let target: Uri = target
+                .trim()
+                .parse()
+                .map_err(|err| format!("Invalid proxy target '{}': {}", target, err))?;
+
+            match target.scheme_str() {
+                Some("http") | Some("https") if target.authority().is_some() => Ok(Rule {
+                    prefix: prefix.to_string(),
+                    target,
+                }),
+
+                _ => Err(format!(
+                    "Proxy target must be an absolute http(s) url: {}",
+                    rule
+                )),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    rules: Vec<Rule>,
+    agent: ureq::Agent,
+}
+
+impl Proxy {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        // Redirects are passed on to the browser instead of being followed here
+        let agent = ureq::AgentBuilder::new()
+            .redirects(0)
+            .timeout(TIMEOUT)
+            .build();
+
+        Self { rules, agent }
+    }
+
+    pub fn match_rule(&self, req: &Request<()>) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(req.uri().path()))
+    }
+
+    // Answers with 502 when the request doesn't match a rule or the backend can't be reached
+    pub fn forward(&self, req: &Request<()>, body: &[u8], is_tls: bool) -> Response<Vec<u8>> {
+        let result = match self.match_rule(req) {
+            Some(rule) => {
+                let url = rule.target_url(req.uri());
+
+                self.send(&url, req, body, is_tls)
+                    .map_err(|err| format!("Proxy request to {} failed: {}", url, err))
+            }
+
+            None => Err(format!("No proxy rule matches {}", req.uri().path())),
+        };
+
+        result.unwrap_or_else(|err| {
+            eprintln!("{}", err);
+
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(err.into_bytes())
+                .unwrap()
+        })
+    }
+
+    fn send(
+        &self,
+        url: &str,
+        req: &Request<()>,
+        body: &[u8],
+        is_tls: bool,
+    ) -> Result<Response<Vec<u8>>, String> {
+        let mut proxy_req = self.agent.request(req.method().as_str(), url);
+
+        for (name, value) in req.headers() {
+            if is_hop_by_hop(name.as_str()) {
+                continue;
+            }
+
+            if let Ok(value) = value.to_str() {
+                proxy_req = proxy_req.set(name.as_str(), value);
+            }
+        }
+
+        if let Some(host) = req.headers().get("Host").and_then(|v| v.to_str().ok()) {
+            proxy_req = proxy_req.set("X-Forwarded-Host", host);
+        }
+
+        proxy_req = proxy_req.set("X-Forwarded-Proto", if is_tls { "https" } else { "http" });
+
+        let result = if body.is_empty() {
+            proxy_req.call()
+        } else {
+            proxy_req.send_bytes(body)
+        };
+
+        // Error statuses are responses like any other
+        let proxy_res = match result {
+            Ok(res) | Err(ureq::Error::Status(_, res)) => res,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let mut res_builder = Response::builder().status(proxy_res.status());
+
+        let mut header_names: Vec<String> = proxy_res
+            .headers_names()
+            .iter()
+            .map(|name| name.to_ascii_lowercase())
+            .collect();
+        header_names.sort();
+        header_names.dedup();
+
+        // Content-Length is kept so HEAD responses stay accurate, it's missing when ureq decompressed the body
+        let keep_header =
+            |name: &str| !is_hop_by_hop(name) || name.eq_ignore_ascii_case("content-length");
+
+        for name in header_names.iter().filter(|name| keep_header(name)) {
+            for value in proxy_res.all(name) {
+                res_builder = res_builder.header(name.as_str(), value);
+            }
+        }
+
+        let mut res_body = Vec::new();
+        proxy_res
+            .into_reader()
+            .read_to_end(&mut res_body)
+            .map_err(|err| format!("Failed to read response body: {}", err))?;
+
+        res_builder
+            .body(res_body)
+            .map_err(|err| format!("Invalid response: {}", err))
+    }
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP_HEADERS
+        .iter()
+        .any(|header| header.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,258 @@
+use crate::util::time_util::DateTime;
+use crate::ProjectInfo;
+use convert_case::{Case, Casing};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadCoreDir(io::Error),
+    SerializeJson(serde_json::Error),
+}
+
+pub struct Config {
+    pub current_dir: PathBuf,
+    pub dist_path: PathBuf,
+    pub core_project_path: PathBuf,
+    pub wasm_project_path: PathBuf,
+    pub web_project_path: PathBuf,
+}
+
+impl Config {
+    pub fn from_project_info(current_dir: &Path, project_info: &ProjectInfo) -> Self {
+        Self {
+            current_dir: current_dir.to_path_buf(),
+            dist_path: project_info.dist_path.clone(),
+            core_project_path: project_info.core_project_path.clone(),
+            wasm_project_path: project_info.wasm_project_path.clone(),
+            web_project_path: project_info.web_project_path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteInfo {
+    pub route: String,
+    pub page: String,
+    pub core_module: PathBuf,
+    pub wasm_module: Option<PathBuf>,
+    pub ts_entry: Option<PathBuf>,
+    pub html_output: Option<PathBuf>,
+    pub last_modified: Option<String>,
+    pub last_commit: Option<CommitInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitInfo {
+    pub hash: String,
+    pub author: String,
+    pub date: String,
+}
+
+pub struct RouteReport {
+    config: Config,
+}
+
+impl RouteReport {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    pub fn collect(&self) -> Result<Vec<RouteInfo>, Error> {
+        let core_src_path = self.config.core_project_path.join("src");
+        let entries = fs::read_dir(&core_src_path).map_err(Error::ReadCoreDir)?;
+
+        let mut page_names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                let file_stem = path.file_stem()?.to_str()?;
+                let is_page = path.extension()? == "rs" && file_stem.ends_with("_page");
+                is_page.then(|| file_stem.to_string())
+            })
+            .collect();
+
+        page_names.sort();
+
+        let routes = page_names
+            .iter()
+            .map(|page_name| self.route_info(page_name))
+            .collect();
+
+        Ok(routes)
+    }
+
+    pub fn to_json(&self, routes: &[RouteInfo]) -> Result<String, Error> {
+        serde_json::to_string_pretty(routes).map_err(Error::SerializeJson)
+    }
+
+    pub fn to_table(&self, routes: &[RouteInfo]) -> String {
+        let header = [
+            "Route",
+            "Core module",
+            "Wasm module",
+            "TS entry",
+            "HTML output",
+            "Last modified",
+            "Last commit",
+        ]
+        .map(|s| s.to_string())
+        .to_vec();
+
+        let rows: Vec<Vec<String>> = routes
+            .iter()
+            .map(|info| {
+                vec![
+                    info.route.clone(),
+                    self.display_path(Some(&info.core_module)),
+                    self.display_path(info.wasm_module.as_ref()),
+                    self.display_path(info.ts_entry.as_ref()),
+                    self.display_path(info.html_output.as_ref()),
+                    info.last_modified
+                        .clone()
+                        .unwrap_or_else(|| "-".to_string()),
+                    info.last_commit
+                        .as_ref()
+                        .map(|commit| {
+                            format!("{} {} ({})", commit.hash, commit.author, commit.date)
+                        })
+                        .unwrap_or_else(|| "-".to_string()),
+                ]
+            })
+            .collect();
+
+        format_table(&header, &rows)
+    }
+
+    fn route_info(&self, page_name: &str) -> RouteInfo {
+        let route = route_from_page_name(page_name);
+        let core_module = self
+            .config
+            .core_project_path
+            .join(format!("src/{}.rs", page_name));
+        let wasm_module = self
+            .config
+            .wasm_project_path
+            .join(format!("src/{}.rs", page_name));
+        let ts_entry = self
+            .config
+            .web_project_path
+            .join(format!("src/{}.ts", page_name));
+        let html_output = self
+            .config
+            .dist_path
+            .join(route.trim_start_matches('/'))
+            .join("index.html");
+
+        let source_files: Vec<PathBuf> = [&core_module, &wasm_module, &ts_entry]
+            .into_iter()
+            .filter(|path| path.exists())
+            .cloned()
+            .collect();
+
+        RouteInfo {
+            route,
+            page: page_name.to_string(),
+            core_module,
+            wasm_module: existing_path(wasm_module),
+            ts_entry: existing_path(ts_entry),
+            html_output: existing_path(html_output),
+            last_modified: last_modified(&source_files),
+            last_commit: self.last_commit(&source_files),
+        }
+    }
+
+    fn last_commit(&self, files: &[PathBuf]) -> Option<CommitInfo> {
+        let output = Command::new("git")
+            .current_dir(&self.config.current_dir)
+            .args(["log", "-1", "--format=%h%x09%an%x09%as", "--"])
+            .args(files)
+            .output()
+            .ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        let stdout = String::from_utf8(output.stdout).ok()?;
+        let parts: Vec<&str> = stdout.trim().split('\t').collect();
+
+        match parts[..] {
+            [hash, author, date] => Some(CommitInfo {
+                hash: hash.to_string(),
+                author: author.to_string(),
+                date: date.to_string(),
+            }),
+
+            _ => None,
+        }
+    }
+
+    fn display_path(&self, path: Option<&PathBuf>) -> String {
+        match path {
+            Some(path) => path
+                .strip_prefix(&self.config.current_dir)
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+
+            None => "-".to_string(),
+        }
+    }
+}
+
+pub fn route_from_page_name(page_name: &str) -> String {
+    let name = page_name.trim_end_matches("_page").trim_end_matches('_');
+
+    if name == "home" || name.is_empty() {
+        "/".to_string()
+    } else {
+        format!("/{}", name.from_case(Case::Snake).to_case(Case::Kebab))
+    }
+}
+
+fn existing_path(path: PathBuf) -> Option<PathBuf> {
+    path.exists().then_some(path)
+}
+
+fn last_modified(files: &[PathBuf]) -> Option<String> {
+    files
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .max()
+        .map(|time| DateTime::from_system_time(time).to_iso_string())
+}
+
+pub fn format_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .map(|(i, title)| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .fold(title.len(), usize::max)
+        })
+        .collect();
+
+    let format_row = |row: &[String]| {
+        row.iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+
+    [format_row(header), format_row(&separator)]
+        .into_iter()
+        .chain(rows.iter().map(|row| format_row(row)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,37 +1,79 @@
+use crate::build::BuildStep;
 use crate::build::Env;
+use crate::build::Profile;
 use crate::build::Runner;
 use crate::exec;
+use crate::poly_config::PolyConfig;
+use crate::size_report;
+use crate::util::file_util;
+use crate::util::generated_files;
 use crate::ProjectInfo;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fs;
 use std::io;
+use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub env: Env,
+    pub profile: Profile,
     pub project_name: String,
     pub frontend_dist_path: PathBuf,
-    pub backend_dist_path: PathBuf,
+    pub backend_dist_path: Option<PathBuf>,
     pub web_project_path: PathBuf,
+    pub core_project_path: PathBuf,
     pub wasm_project_path: PathBuf,
     pub cloudflare_project_path: PathBuf,
+    pub excluded_members: Vec<String>,
+    pub cargo_env: Vec<(String, String)>,
+    pub wasm_pack_env: Vec<(String, String)>,
+    pub cargo_timeout: Option<Duration>,
+    pub wasm_pack_timeout: Option<Duration>,
+    pub size_report: size_report::Config,
 }
 
 impl Config {
-    pub fn from_project_info(env: &Env, project_info: &ProjectInfo) -> Self {
+    pub fn from_project_info(
+        profile: &Profile,
+        project_info: &ProjectInfo,
+        poly_config: &PolyConfig,
+    ) -> Self {
         Self {
-            env: env.clone(),
+            profile: profile.clone(),
             project_name: project_info.project_name.clone(),
             frontend_dist_path: project_info.dist_path.clone(),
             backend_dist_path: project_info.backend_dist_path.clone(),
             web_project_path: project_info.web_project_path.clone(),
+            core_project_path: project_info.core_project_path.clone(),
             wasm_project_path: project_info.wasm_project_path.clone(),
             cloudflare_project_path: project_info.cloudflare_project_path.clone(),
+            excluded_members: poly_config.workspace.exclude.clone(),
+            cargo_env: poly_config.step_env("rust", &profile.env),
+            wasm_pack_env: poly_config.step_env("wasm", &profile.env),
+            cargo_timeout: poly_config.step_timeout("rust"),
+            wasm_pack_timeout: poly_config.step_timeout("wasm"),
+            size_report: size_report::Config::from_project_info(&profile.env, project_info),
         }
     }
 
+    fn cargo_build_args(&self, extra_args: &[&str]) -> Vec<String> {
+        let mut args = exec::to_args(&["build"]);
+        args.extend(exec::to_args(extra_args));
+
+        if !self.excluded_members.is_empty() {
+            args.push("--workspace".to_string());
+
+            for member in &self.excluded_members {
+                args.push("--exclude".to_string());
+                args.push(member.clone());
+            }
+        }
+
+        args
+    }
+
     fn web_project_wasm_frontend_path(&self) -> PathBuf {
         self.web_project_path.join("wasm")
     }
@@ -47,7 +89,7 @@ pub enum Error {
     CreateWebWasmDir(io::Error),
     CargoBuild(exec::Error),
     WasmPack(exec::Error),
-    CopyWasmToDist(fs_extra::error::Error),
+    CopyWasmToDist(io::Error),
     ReadBackendWasmGlue(io::Error),
     WriteBackendWasmGlue(io::Error),
 }
@@ -88,156 +130,125 @@ impl RustBuilder {
         Self { config: config }
     }
 
-    fn build_dev(&self) -> Result<(), Error> {
+    fn build(&self) -> Result<(), Error> {
         self.prepare_dirs()?;
 
-        exec::run(&exec::Config {
-            work_dir: ".".into(),
-            cmd: "cargo".into(),
-            args: exec::to_args(&["build", "--color", "always"]),
-        })
-        .map_err(Error::CargoBuild)?;
+        exec::run_streamed(&self.cargo_command()).map_err(Error::CargoBuild)?;
 
-        exec::run(&exec::Config {
-            work_dir: self.config.wasm_project_path.clone(),
-            cmd: "wasm-pack".into(),
-            args: exec::to_args(&[
-                "build",
-                "--dev",
-                "--no-opt",
-                "--target",
-                "web",
-                "--out-name",
-                &self.config.project_name,
-                "--out-dir",
-                &self
-                    .config
-                    .web_project_wasm_frontend_path()
-                    .to_string_lossy(),
-            ]),
-        })
-        .map_err(Error::WasmPack)?;
+        let frontend_path = self.config.web_project_wasm_frontend_path();
+        exec::run_streamed(&self.wasm_pack_command("web", &frontend_path))
+            .map_err(Error::WasmPack)?;
 
         self.copy_wasm_to_frontend_dist()?;
 
-        exec::run(&exec::Config {
-            work_dir: self.config.wasm_project_path.clone(),
-            cmd: "wasm-pack".into(),
-            args: exec::to_args(&[
-                "build",
-                "--dev",
-                "--no-opt",
-                "--target",
-                "nodejs",
-                "--out-name",
-                &self.config.project_name,
-                "--out-dir",
-                &self
-                    .config
-                    .web_project_wasm_backend_path()
-                    .to_string_lossy(),
-            ]),
-        })
-        .map_err(Error::WasmPack)?;
+        self.build_backend_wasm()
+    }
 
-        self.patch_backend_wasm_glue()?;
-        self.copy_wasm_to_backend_dist()?;
+    // The nodejs wasm for the cloudflare worker, skipped for projects without one
+    fn build_backend_wasm(&self) -> Result<(), Error> {
+        let backend_dist_path = match &self.config.backend_dist_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
 
-        Ok(())
+        let backend_path = self.config.web_project_wasm_backend_path();
+        exec::run_streamed(&self.wasm_pack_command("nodejs", &backend_path))
+            .map_err(Error::WasmPack)?;
+
+        self.patch_backend_wasm_glue()?;
+        self.copy_wasm_to_backend_dist(backend_dist_path)
     }
 
-    fn build_release(&self) -> Result<(), Error> {
-        self.prepare_dirs()?;
+    fn cargo_command(&self) -> exec::Config {
+        let profile_args: &[&str] = match &self.config.profile.env {
+            Env::Dev => &["--color", "always"],
+            Env::Release => &["--release", "--color", "always"],
+        };
 
-        exec::run(&exec::Config {
+        exec::Config {
             work_dir: ".".into(),
             cmd: "cargo".into(),
-            args: exec::to_args(&["build", "--release", "--color", "always"]),
-        })
-        .map_err(Error::CargoBuild)?;
-
-        exec::run(&exec::Config {
-            work_dir: self.config.wasm_project_path.clone(),
-            cmd: "wasm-pack".into(),
-            args: exec::to_args(&[
-                "build",
-                "--release",
-                "--target",
-                "web",
-                "--out-name",
-                &self.config.project_name,
-                "--out-dir",
-                &self
-                    .config
-                    .web_project_wasm_frontend_path()
-                    .to_string_lossy(),
-            ]),
-        })
-        .map_err(Error::WasmPack)?;
-
-        self.copy_wasm_to_frontend_dist()?;
+            args: self.config.cargo_build_args(profile_args),
+            env: self.config.cargo_env.clone(),
+            env_mode: exec::EnvMode::Inherit,
+            timeout: self.config.cargo_timeout,
+        }
+    }
 
-        exec::run(&exec::Config {
+    fn wasm_pack_command(&self, target: &str, out_dir: &Path) -> exec::Config {
+        let profile_args: &[&str] = match &self.config.profile.env {
+            Env::Dev => &["--dev", "--no-opt"],
+            Env::Release => &["--release"],
+        };
+
+        let mut args = exec::to_args(&["build"]);
+        args.extend(exec::to_args(profile_args));
+        args.extend(exec::to_args(&[
+            "--target",
+            target,
+            "--out-name",
+            &self.config.project_name,
+            "--out-dir",
+            &out_dir.to_string_lossy(),
+        ]));
+
+        exec::Config {
             work_dir: self.config.wasm_project_path.clone(),
             cmd: "wasm-pack".into(),
-            args: exec::to_args(&[
-                "build",
-                "--release",
-                "--target",
-                "nodejs",
-                "--out-name",
-                &self.config.project_name,
-                "--out-dir",
-                &self
-                    .config
-                    .web_project_wasm_backend_path()
-                    .to_string_lossy(),
-            ]),
-        })
-        .map_err(Error::WasmPack)?;
-
-        self.patch_backend_wasm_glue()?;
-        self.copy_wasm_to_backend_dist()?;
+            args,
+            env: self.config.wasm_pack_env.clone(),
+            env_mode: exec::EnvMode::Inherit,
+            timeout: self.config.wasm_pack_timeout,
+        }
+    }
 
-        Ok(())
+    // The js glue generated by wasm-pack, it only changes when the wasm bindings change
+    pub fn frontend_glue_path(&self) -> PathBuf {
+        self.config
+            .web_project_wasm_frontend_path()
+            .join(format!("{}.js", self.config.project_name))
     }
 
     fn prepare_dirs(&self) -> Result<(), Error> {
         fs::create_dir_all(&self.config.frontend_dist_path).map_err(Error::CreateDistDir)?;
-        fs::create_dir_all(&self.config.backend_dist_path).map_err(Error::CreateDistDir)?;
         fs::create_dir_all(&self.config.web_project_wasm_frontend_path())
             .map_err(Error::CreateWebWasmDir)?;
-        fs::create_dir_all(&self.config.web_project_wasm_backend_path())
-            .map_err(Error::CreateWebWasmDir)?;
+
+        if let Some(backend_dist_path) = &self.config.backend_dist_path {
+            fs::create_dir_all(backend_dist_path).map_err(Error::CreateDistDir)?;
+            fs::create_dir_all(self.config.web_project_wasm_backend_path())
+                .map_err(Error::CreateWebWasmDir)?;
+        }
 
         Ok(())
     }
 
     fn copy_wasm_to_frontend_dist(&self) -> Result<(), Error> {
-        fs_extra::dir::copy(
+        let copied = file_util::sync_dir(
             &self.config.web_project_wasm_frontend_path(),
             &self.config.frontend_dist_path,
-            &fs_extra::dir::CopyOptions {
-                overwrite: true,
-                ..fs_extra::dir::CopyOptions::default()
-            },
         )
         .map_err(Error::CopyWasmToDist)?;
 
+        print_copied_files(&copied);
+
+        // The report is informational, it doesn't fail the build
+        if let Err(err) = size_report::print(&self.config.size_report) {
+            eprintln!("Warning: {}", err);
+        }
+
         Ok(())
     }
 
-    fn copy_wasm_to_backend_dist(&self) -> Result<(), Error> {
-        fs_extra::dir::copy(
+    fn copy_wasm_to_backend_dist(&self, backend_dist_path: &Path) -> Result<(), Error> {
+        let copied = file_util::sync_dir(
             &self.config.web_project_wasm_backend_path(),
-            &self.config.backend_dist_path,
-            &fs_extra::dir::CopyOptions {
-                overwrite: true,
-                ..fs_extra::dir::CopyOptions::default()
-            },
+            backend_dist_path,
         )
         .map_err(Error::CopyWasmToDist)?;
 
+        print_copied_files(&copied);
+
         Ok(())
     }
 
@@ -262,7 +273,8 @@ impl RustBuilder {
                 ),
             );
 
-        fs::write(&file_path, new_content).map_err(Error::WriteBackendWasmGlue)?;
+        fs::write(&file_path, &new_content).map_err(Error::WriteBackendWasmGlue)?;
+        generated_files::record(&file_path, new_content.as_bytes());
 
         Ok(())
     }
@@ -270,9 +282,54 @@ impl RustBuilder {
 
 impl Runner<Error> for RustBuilder {
     fn run(&self) -> Result<(), Error> {
-        match &self.config.env {
-            Env::Dev => self.build_dev(),
-            Env::Release => self.build_release(),
+        self.build()
+    }
+}
+
+impl BuildStep for RustBuilder {
+    fn name(&self) -> String {
+        "rust".to_string()
+    }
+
+    fn inputs(&self) -> Vec<PathBuf> {
+        vec![
+            self.config.core_project_path.clone(),
+            self.config.wasm_project_path.clone(),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<PathBuf> {
+        let mut outputs = vec![self.config.frontend_dist_path.clone()];
+        outputs.extend(self.config.backend_dist_path.clone());
+        outputs
+    }
+
+    fn commands(&self) -> Vec<exec::Config> {
+        let frontend_path = self.config.web_project_wasm_frontend_path();
+        let mut commands = vec![
+            self.cargo_command(),
+            self.wasm_pack_command("web", &frontend_path),
+        ];
+
+        if self.config.backend_dist_path.is_some() {
+            let backend_path = self.config.web_project_wasm_backend_path();
+            commands.push(self.wasm_pack_command("nodejs", &backend_path));
         }
+
+        commands
+    }
+
+    fn run(&self) -> Result<(), String> {
+        Runner::run(self).map_err(|err| err.to_string())
+    }
+}
+
+fn print_copied_files(copied: &[PathBuf]) {
+    if copied.is_empty() {
+        println!("Wasm output unchanged, nothing copied");
+    }
+
+    for path in copied {
+        println!("Copied {}", path.display());
     }
 }
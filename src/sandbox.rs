@@ -0,0 +1,146 @@
+use crate::exec;
+use crate::poly_config::SandboxConfig;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+// bubblewrap, used for the filesystem and network isolation on Linux
+const HELPER: &str = "bwrap";
+
+// Everything else, like tokens in CI, is hidden from sandboxed commands
+const KEPT_ENV_VARS: [&str; 5] = ["PATH", "HOME", "LANG", "TERM", "TMPDIR"];
+
+#[derive(Debug)]
+pub enum Error {
+    HelperMissing,
+    Exec(exec::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::HelperMissing => write!(
+                f,
+                "The sandbox requires {} on PATH, install bubblewrap or set allow_env_only = true in [sandbox]",
+                HELPER
+            ),
+
+            Error::Exec(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// Runs a command with a scrubbed environment. With bubblewrap the filesystem is
+// mounted read-only except for the writable dir and /tmp, and the network is cut off.
+// The writable dir is the dist dir for build hooks and the new project for post create
+// commands
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    writable_dir: PathBuf,
+    network: bool,
+    allow_env_only: bool,
+}
+
+impl Sandbox {
+    pub fn new(writable_dir: &Path, config: &SandboxConfig) -> Self {
+        Self {
+            writable_dir: writable_dir.to_path_buf(),
+            network: config.network,
+            allow_env_only: config.allow_env_only,
+        }
+    }
+
+    pub fn run(&self, config: &exec::Config) -> Result<String, Error> {
+        exec::run(&self.sandboxed_config(config)?).map_err(Error::Exec)
+    }
+
+    pub fn run_interactive(&self, config: &exec::Config) -> Result<(), Error> {
+        exec::run_interactive(&self.sandboxed_config(config)?).map_err(Error::Exec)
+    }
+
+    // Without bubblewrap the command only runs with a scrubbed environment, which has to be
+    // allowed explicitly since it doesn't keep the command off the network
+    fn sandboxed_config(&self, config: &exec::Config) -> Result<exec::Config, Error> {
+        let mut env_vars: Vec<(String, String)> = KEPT_ENV_VARS
+            .iter()
+            .filter_map(|name| Some((name.to_string(), env::var(name).ok()?)))
+            .collect();
+        env_vars.extend(config.env.iter().cloned());
+
+        if cfg!(target_os = "linux") && exec::is_on_path(HELPER) {
+            return Ok(self.helper_config(config, &env_vars));
+        }
+
+        if !self.allow_env_only {
+            return Err(Error::HelperMissing);
+        }
+
+        eprintln!(
+            "Warning: {} not found, {} only runs with a scrubbed environment",
+            HELPER, config.cmd
+        );
+
+        Ok(exec::Config {
+            work_dir: config.work_dir.clone(),
+            cmd: config.cmd.clone(),
+            args: config.args.clone(),
+            env: env_vars,
+            env_mode: exec::EnvMode::Clear,
+            timeout: config.timeout,
+        })
+    }
+
+    fn helper_config(&self, config: &exec::Config, env_vars: &[(String, String)]) -> exec::Config {
+        // Bind mounts need an existing dir
+        let _ = fs::create_dir_all(&self.writable_dir);
+        let writable_dir = absolute_path(&self.writable_dir);
+        let work_dir = absolute_path(&config.work_dir);
+
+        let mut args = exec::to_args(&[
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--tmpfs",
+            "/tmp",
+            "--bind",
+            &writable_dir,
+            &writable_dir,
+            "--unshare-all",
+            "--die-with-parent",
+            "--clearenv",
+        ]);
+
+        if self.network {
+            args.push("--share-net".to_string());
+        }
+
+        for (name, value) in env_vars {
+            args.extend(["--setenv".to_string(), name.clone(), value.clone()]);
+        }
+
+        args.extend(exec::to_args(&["--chdir", &work_dir, "--", &config.cmd]));
+        args.extend(config.args.iter().cloned());
+
+        exec::Config {
+            work_dir: config.work_dir.clone(),
+            cmd: HELPER.into(),
+            args,
+            env: Vec::new(),
+            env_mode: exec::EnvMode::Inherit,
+            timeout: config.timeout,
+        }
+    }
+}
+
+fn absolute_path(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into()
+}
@@ -0,0 +1,178 @@
+use crate::poly_config::PinsConfig;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadExecutable(PathBuf, io::Error),
+    Changed {
+        path: PathBuf,
+        pinned: String,
+        actual: String,
+    },
+    ReadConfig(PathBuf, io::Error),
+    WriteConfig(PathBuf, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadExecutable(path, err) => {
+                write!(f, "Failed to read {}: {}", path.display(), err)
+            }
+
+            Error::Changed {
+                path,
+                pinned,
+                actual,
+            } => write!(
+                f,
+                "Refusing to run {}, its sha256 changed from {} to {}. Review the change and run again with --accept-script-changes to pin the new version",
+                path.display(),
+                pinned,
+                actual
+            ),
+
+            Error::ReadConfig(path, err) => {
+                write!(f, "Failed to read {}: {}", path.display(), err)
+            }
+
+            Error::WriteConfig(path, err) => {
+                write!(f, "Failed to write {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+// Scripts are pinned by the path given in [hooks] or to --script, relative to the project dir
+pub fn script_key(script_name: &str) -> String {
+    script_name.trim_start_matches("./").to_string()
+}
+
+pub fn sha256(path: &Path) -> Result<String, Error> {
+    let content = fs::read(path).map_err(|err| Error::ReadExecutable(path.to_path_buf(), err))?;
+
+    Ok(data_encoding::HEXLOWER.encode(&Sha256::digest(&content)))
+}
+
+// Executables without a pin are allowed, pinning is opt-in
+pub fn verify(path: &Path, pinned: Option<&str>) -> Result<(), Error> {
+    let pinned = match pinned {
+        Some(pinned) => pinned,
+        None => return Ok(()),
+    };
+
+    let actual = sha256(path)?;
+
+    if actual.eq_ignore_ascii_case(pinned) {
+        Ok(())
+    } else {
+        Err(Error::Changed {
+            path: path.to_path_buf(),
+            pinned: pinned.to_string(),
+            actual,
+        })
+    }
+}
+
+// Pins the current version of the given scripts and plugins and writes the pins to the config file
+pub fn accept(
+    config_path: &Path,
+    pins: &mut PinsConfig,
+    scripts: &[(String, PathBuf)],
+    plugins: &BTreeMap<String, PathBuf>,
+) -> Result<(), Error> {
+    let mut changed = false;
+
+    for (key, path) in scripts {
+        changed |= pin(&mut pins.scripts, key, path)?;
+    }
+
+    for (name, path) in plugins {
+        changed |= pin(&mut pins.plugins, name, path)?;
+    }
+
+    if changed {
+        write_pins(config_path, pins)?;
+    }
+
+    Ok(())
+}
+
+fn pin(pins: &mut BTreeMap<String, String>, key: &str, path: &Path) -> Result<bool, Error> {
+    let hash = sha256(path)?;
+
+    if pins.get(key) == Some(&hash) {
+        return Ok(false);
+    }
+
+    println!("[Pinned] {} sha256:{}", path.display(), hash);
+    pins.insert(key.to_string(), hash);
+
+    Ok(true)
+}
+
+// toml can't be edited in place, so the pins tables are replaced and the rest of the file is kept as is
+fn write_pins(config_path: &Path, pins: &PinsConfig) -> Result<(), Error> {
+    let content = if config_path.exists() {
+        fs::read_to_string(config_path)
+            .map_err(|err| Error::ReadConfig(config_path.to_path_buf(), err))?
+    } else {
+        String::new()
+    };
+
+    let mut lines = Vec::new();
+    let mut in_pins_table = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with('[') {
+            in_pins_table = is_pins_header(trimmed);
+        }
+
+        if !in_pins_table {
+            lines.push(line.to_string());
+        }
+    }
+
+    while lines.last().map(|line| line.trim().is_empty()) == Some(true) {
+        lines.pop();
+    }
+
+    let tables = [("scripts", &pins.scripts), ("plugins", &pins.plugins)];
+
+    for (name, table) in tables.iter().filter(|(_, table)| !table.is_empty()) {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+
+        lines.push(format!("[pins.{}]", name));
+
+        for (key, hash) in table.iter() {
+            lines.push(format!("{:?} = {:?}", key, hash));
+        }
+    }
+
+    lines.push(String::new());
+
+    fs::write(config_path, lines.join("\n"))
+        .map_err(|err| Error::WriteConfig(config_path.to_path_buf(), err))
+}
+
+fn is_pins_header(header: &str) -> bool {
+    let name = header
+        .trim_start_matches('[')
+        .split(']')
+        .next()
+        .unwrap_or("");
+    let name = name.trim();
+
+    name == "pins" || name.starts_with("pins.")
+}
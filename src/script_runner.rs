@@ -1,25 +1,84 @@
-use crate::build::Env;
+use crate::build::Profile;
 use crate::exec;
+use crate::hook_action;
+use crate::hook_action::Action;
+use crate::poly_config::HookEventsConfig;
+use crate::sandbox;
+use crate::sandbox::Sandbox;
+use crate::script_pins;
 use std::fmt;
 use std::fmt::Display;
+use std::io;
+use std::path::Path;
 use std::path::PathBuf;
 
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
 #[derive(Debug)]
 pub enum Error {
     Exec(exec::Error),
+    Action(hook_action::Error),
+    Pin(script_pins::Error),
+    Sandbox(sandbox::Error),
+    NotExecutable(PathBuf),
+    WindowsOnly(PathBuf),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Event {
+    PreClean,
+    PreBuild,
+    PostRustBuild,
+    PostWebBuild,
     BeforeAssetHash,
     AfterAssetHash,
+    PostBuild,
+    PreServe,
+    OnWatchRebuild,
+}
+
+impl Event {
+    pub const ALL: [Event; 9] = [
+        Event::PreClean,
+        Event::PreBuild,
+        Event::PostRustBuild,
+        Event::PostWebBuild,
+        Event::BeforeAssetHash,
+        Event::AfterAssetHash,
+        Event::PostBuild,
+        Event::PreServe,
+        Event::OnWatchRebuild,
+    ];
+
+    // The scripts and commands listed for the event in [hooks]
+    pub fn entries(self, config: &HookEventsConfig) -> &[exec::CommandEntry] {
+        match self {
+            Event::PreClean => &config.pre_clean,
+            Event::PreBuild => &config.pre_build,
+            Event::PostRustBuild => &config.post_rust_build,
+            Event::PostWebBuild => &config.post_web_build,
+            Event::BeforeAssetHash => &config.before_asset_hash,
+            Event::AfterAssetHash => &config.after_asset_hash,
+            Event::PostBuild => &config.post_build,
+            Event::PreServe => &config.pre_serve,
+            Event::OnWatchRebuild => &config.on_watch_rebuild,
+        }
+    }
 }
 
 impl Display for Event {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Event::PreClean => write!(f, "pre_clean"),
+            Event::PreBuild => write!(f, "pre_build"),
+            Event::PostRustBuild => write!(f, "post_rust_build"),
+            Event::PostWebBuild => write!(f, "post_web_build"),
             Event::BeforeAssetHash => write!(f, "before_asset_hash"),
             Event::AfterAssetHash => write!(f, "after_asset_hash"),
+            Event::PostBuild => write!(f, "post_build"),
+            Event::PreServe => write!(f, "pre_serve"),
+            Event::OnWatchRebuild => write!(f, "on_watch_rebuild"),
         }
     }
 }
@@ -27,33 +86,350 @@ impl Display for Event {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
-            Error::Exec(err) => write!(f, "Script failed: {}", err),
+            Error::Exec(err) => write!(f, "{}", err),
+            Error::Action(err) => write!(f, "{}", err),
+            Error::Pin(err) => write!(f, "{}", err),
+            Error::Sandbox(err) => write!(f, "Sandboxed script failed: {}", err),
+            Error::NotExecutable(path) => write!(
+                f,
+                "{} isn't executable, run `chmod +x` on it or give it a .sh extension",
+                path.display()
+            ),
+            Error::WindowsOnly(path) => write!(
+                f,
+                "{} is a Windows batch file, use a .sh script on this platform",
+                path.display()
+            ),
         }
     }
 }
 
+// What a run of the hooks is about beyond the profile, exposed as POLY_* environment variables
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    // Relative to the project dir, set for watch rebuilds
+    pub changed_files: Vec<PathBuf>,
+    // Set for after_asset_hash
+    pub asset_manifest: Option<PathBuf>,
+}
+
+impl Context {
+    fn env(&self) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+
+        // One path per line, paths with newlines aren't worth a format scripts have to parse
+        if !self.changed_files.is_empty() {
+            let files: Vec<String> = self
+                .changed_files
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            vars.push(("POLY_CHANGED_FILES".to_string(), files.join("\n")));
+        }
+
+        if let Some(path) = &self.asset_manifest {
+            vars.push((
+                "POLY_ASSET_MANIFEST".to_string(),
+                path.display().to_string(),
+            ));
+        }
+
+        vars
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Program {
+    Script(PathBuf),
+    // An inline command from [hooks], only given the context as environment variables
+    Command {
+        cmd: String,
+        args: Vec<String>,
+        interactive: bool,
+    },
+    // Built into poly, run in process with the profile
+    Action(Action),
+}
+
 #[derive(Debug, Clone)]
 pub struct ScriptRunner {
-    script_path: PathBuf,
-    env: Env,
+    program: Program,
+    profile: Profile,
+    pinned_sha256: Option<String>,
+    sandbox: Option<Sandbox>,
+    on_failure: exec::OnFailure,
+    // The event given to the program instead of the one it runs at
+    reported_event: Option<Event>,
 }
 
 impl ScriptRunner {
-    pub fn new(script_path: PathBuf, env: &Env) -> Self {
+    pub fn new(
+        script_path: PathBuf,
+        profile: &Profile,
+        pinned_sha256: Option<String>,
+        sandbox: Option<Sandbox>,
+    ) -> Self {
         Self {
-            script_path,
-            env: env.clone(),
+            program: Program::Script(script_path),
+            profile: profile.clone(),
+            pinned_sha256,
+            sandbox,
+            on_failure: exec::OnFailure::Abort,
+            reported_event: None,
         }
     }
 
-    pub fn run(&self, event: Event) -> Result<(), Error> {
-        exec::run(&exec::Config {
-            work_dir: ".".into(),
-            cmd: self.script_path.to_string_lossy().into(),
-            args: vec![self.env.to_string(), event.to_string()],
+    pub fn with_on_failure(self, on_failure: exec::OnFailure) -> Self {
+        Self { on_failure, ..self }
+    }
+
+    pub fn with_reported_event(self, event: Event) -> Self {
+        Self {
+            reported_event: Some(event),
+            ..self
+        }
+    }
+
+    // None for an empty command. Commands are part of poly.toml itself, so there is nothing
+    // to pin, they run in the sandbox when `commands` is set in [sandbox]
+    pub fn inline(
+        entry: &exec::CommandEntry,
+        profile: &Profile,
+        sandbox: Option<Sandbox>,
+    ) -> Option<Self> {
+        let (cmd, args) = entry.to_cmd()?;
+
+        Some(Self {
+            program: Program::Command {
+                cmd,
+                args,
+                interactive: entry.is_interactive(),
+            },
+            profile: profile.clone(),
+            pinned_sha256: None,
+            sandbox,
+            on_failure: entry.on_failure(),
+            reported_event: None,
         })
-        .map_err(Error::Exec)?;
+    }
+
+    pub fn action(action: Action, profile: &Profile) -> Self {
+        Self {
+            program: Program::Action(action),
+            profile: profile.clone(),
+            pinned_sha256: None,
+            sandbox: None,
+            on_failure: exec::OnFailure::Abort,
+            reported_event: None,
+        }
+    }
+
+    // The profile and event are passed as arguments for backwards compatibility,
+    // the full build context is available as POLY_* environment variables
+    pub fn run(&self, event: Event, context: &Context) -> Result<(), Error> {
+        let event = self.reported_event.unwrap_or(event);
+
+        match &self.program {
+            Program::Script(script_path) => {
+                let (cmd, args) = self.script_command(script_path, event);
+                self.run_script(script_path, &self.exec_config(cmd, args, event, context))
+            }
+
+            Program::Command {
+                cmd,
+                args,
+                interactive,
+            } => {
+                let exec_config = self.exec_config(cmd.clone(), args.clone(), event, context);
+
+                match (&self.sandbox, interactive) {
+                    (Some(sandbox), true) => sandbox
+                        .run_interactive(&exec_config)
+                        .map_err(Error::Sandbox),
+
+                    (Some(sandbox), false) => sandbox
+                        .run(&exec_config)
+                        .map(|_| ())
+                        .map_err(Error::Sandbox),
+
+                    (None, true) => exec::run_interactive(&exec_config).map_err(Error::Exec),
+
+                    (None, false) => exec::run(&exec_config).map(|_| ()).map_err(Error::Exec),
+                }
+            }
+
+            Program::Action(action) => action.run(&self.profile).map_err(Error::Action),
+        }
+    }
+
+    fn run_script(&self, script_path: &Path, exec_config: &exec::Config) -> Result<(), Error> {
+        // Checked before every run since watch keeps running while the script is edited
+        script_pins::verify(script_path, self.pinned_sha256.as_deref()).map_err(Error::Pin)?;
+
+        if is_batch_file(script_path) && !cfg!(windows) {
+            return Err(Error::WindowsOnly(script_path.to_path_buf()));
+        }
+
+        match &self.sandbox {
+            Some(sandbox) => sandbox.run(exec_config).map_err(Error::Sandbox)?,
+            None => exec::run(exec_config).map_err(|err| match err {
+                exec::Error::FailedToExecute(err)
+                    if err.kind() == io::ErrorKind::PermissionDenied =>
+                {
+                    Error::NotExecutable(script_path.to_path_buf())
+                }
+                err => Error::Exec(err),
+            })?,
+        };
 
         Ok(())
     }
+
+    // For --dry-run
+    pub fn describe(&self, event: Event) -> String {
+        let event = self.reported_event.unwrap_or(event);
+        let context = Context::default();
+
+        match &self.program {
+            Program::Script(script_path) => {
+                let (cmd, args) = self.script_command(script_path, event);
+                exec::describe(&self.exec_config(cmd, args, event, &context))
+            }
+
+            Program::Command { cmd, args, .. } => {
+                exec::describe(&self.exec_config(cmd.clone(), args.clone(), event, &context))
+            }
+
+            Program::Action(action) => action.describe(&self.profile),
+        }
+    }
+
+    fn script_command(&self, script_path: &Path, event: Event) -> (String, Vec<String>) {
+        script_command(script_path, vec![self.profile.name(), event.to_string()])
+    }
+
+    fn exec_config(
+        &self,
+        cmd: String,
+        args: Vec<String>,
+        event: Event,
+        context: &Context,
+    ) -> exec::Config {
+        let mut env = self.profile.script_env();
+        env.push(("POLY_EVENT".to_string(), event.to_string()));
+        env.extend(context.env());
+
+        exec::Config {
+            work_dir: ".".into(),
+            cmd,
+            args,
+            env,
+            env_mode: exec::EnvMode::Inherit,
+            timeout: None,
+        }
+    }
+}
+
+fn script_command(script_path: &Path, script_args: Vec<String>) -> (String, Vec<String>) {
+    let script = script_path.to_string_lossy().to_string();
+
+    match interpreter(script_path) {
+        Some((interpreter, interpreter_args)) => {
+            let mut args = exec::to_args(&interpreter_args);
+            args.push(script);
+            args.extend(script_args);
+            (interpreter.to_string(), args)
+        }
+
+        None => (script, script_args),
+    }
+}
+
+// The scripts and commands of each event in the order they're listed in [hooks], the first
+// failing one stops the event
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    runners: Vec<(Event, ScriptRunner)>,
+}
+
+impl Hooks {
+    pub fn add(&mut self, event: Event, runner: ScriptRunner) {
+        self.runners.push((event, runner));
+    }
+
+    pub fn run(&self, event: Event) -> Result<(), Error> {
+        self.run_with(event, &Context::default())
+    }
+
+    // A changed pin or a sandbox that can't be set up always stops the event, whatever the
+    // hook's on_failure says
+    pub fn run_with(&self, event: Event, context: &Context) -> Result<(), Error> {
+        for runner in self.runners_for(event) {
+            let err = match runner.run(event, context) {
+                Ok(()) => continue,
+                Err(err @ Error::Pin(_)) => return Err(err),
+                Err(err @ Error::Sandbox(sandbox::Error::HelperMissing)) => return Err(err),
+                Err(err) => err,
+            };
+
+            match runner.on_failure {
+                exec::OnFailure::Abort => return Err(err),
+                exec::OnFailure::Warn => eprintln!("Warning: The {} hook failed: {}", event, err),
+                exec::OnFailure::Ignore => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // For --dry-run
+    pub fn print_plan(&self, event: Event) {
+        for runner in self.runners_for(event) {
+            println!("[hook {}] {}", event, runner.describe(event));
+        }
+    }
+
+    fn runners_for(&self, event: Event) -> impl Iterator<Item = &ScriptRunner> {
+        self.runners
+            .iter()
+            .filter(move |(runner_event, _)| *runner_event == event)
+            .map(|(_, runner)| runner)
+    }
+}
+
+// Scripts are run directly unless the platform can't, Windows only runs executables and
+// a shell script without the executable bit is still run through sh
+fn interpreter(script_path: &Path) -> Option<(&'static str, Vec<&'static str>)> {
+    let extension = script_path.extension()?.to_str()?.to_ascii_lowercase();
+
+    match extension.as_str() {
+        "ps1" if cfg!(windows) => Some((
+            "powershell",
+            vec!["-NoProfile", "-ExecutionPolicy", "Bypass", "-File"],
+        )),
+        "ps1" => Some(("pwsh", vec!["-NoProfile", "-File"])),
+        "bat" | "cmd" => Some(("cmd", vec!["/C"])),
+        "sh" if cfg!(windows) || !is_executable(script_path) => Some(("sh", vec![])),
+        _ => None,
+    }
+}
+
+fn is_batch_file(script_path: &Path) -> bool {
+    script_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| matches!(extension.to_ascii_lowercase().as_str(), "bat" | "cmd"))
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
 }
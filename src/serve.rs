@@ -1,22 +1,168 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use globset::{Glob, GlobMatcher};
 use http::header::HeaderName;
-use http::{request, HeaderMap, HeaderValue, Request, Response};
+use http::{request, HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Version};
 use mime_guess::Mime;
 use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::UNIX_EPOCH;
 
+use crate::access_log;
+use crate::access_log::AccessLog;
+use crate::dev_client;
+use crate::dev_console;
+use crate::dev_session;
 use crate::exec;
+use crate::live_reload;
+use crate::proxy;
+use crate::util::time_util::DateTime;
 
-const HTTP1_1: &[u8] = b"HTTP/1.1 200 OK";
+const HTTP1_1: &[u8] = b"HTTP/1.1";
 const CRNL: &[u8] = b"\r\n";
+const ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+
+// Idle keep-alive connections are closed after this long
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Clients that stop reading are disconnected instead of holding a thread forever
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// New connections wait in the accept backlog while this many are being served. Reload
+// sockets and idle keep-alive connections hold a slot too, since every connection still
+// gets a blocking thread, an async runtime would lift this limit
+const MAX_CONNECTIONS: usize = 128;
+
+// Reload sockets are pinged this often to notice closed browser tabs
+const RELOAD_PING_INTERVAL: Duration = Duration::from_secs(25);
+
+// Larger request bodies are discarded, only small dev client messages are expected
+const MAX_REQUEST_BODY: u64 = 1024 * 1024;
+
+// The `security` header preset, HSTS is added over https since browsers ignore it otherwise
+const SECURITY_HEADERS: [&str; 3] = [
+    "X-Content-Type-Options: nosniff",
+    "Referrer-Policy: strict-origin-when-cross-origin",
+    "Permissions-Policy: camera=(), microphone=(), geolocation=()",
+];
+const HSTS_HEADER: &str = "Strict-Transport-Security: max-age=31536000; includeSubDomains";
+
+// File extension and content encoding of precompressed siblings, in order of preference
+const PRECOMPRESSED_EXTENSIONS: [(&str, &str); 2] = [("br", "br"), ("gz", "gzip")];
 
 pub struct Config {
     pub static_base_path: PathBuf,
     pub routes: Vec<Route>,
+    pub redirects: Vec<Redirect>,
     pub response_headers: Vec<String>,
+    pub port: Option<u32>,
+    pub spa: bool,
+    pub compress: bool,
+    pub cache_rules: Vec<CacheRule>,
+    pub tls: Option<Arc<rustls::ServerConfig>>,
+    pub dev_client: bool,
+    pub dev_session_dir: PathBuf,
+    pub proxy: proxy::Proxy,
+    pub cors: Option<Cors>,
+    pub error_pages: ErrorPages,
+    pub access_log: Option<AccessLog>,
+}
+
+// Served with the matching status when they exist, like Cloudflare Pages and Netlify do
+#[derive(Debug, Clone)]
+pub struct ErrorPages {
+    pub not_found: PathBuf,
+    pub server_error: PathBuf,
+}
+
+impl ErrorPages {
+    pub fn new(
+        static_base_path: &Path,
+        not_found: Option<PathBuf>,
+        server_error: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            not_found: not_found.unwrap_or_else(|| static_base_path.join("404.html")),
+            server_error: server_error.unwrap_or_else(|| static_base_path.join("500.html")),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ResponseError {
+    NotFound(PathBuf),
+    Failed(String),
+}
+
+impl From<String> for ResponseError {
+    fn from(err: String) -> Self {
+        ResponseError::Failed(err)
+    }
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResponseError::NotFound(path) => write!(f, "Path not found: {}", path.display()),
+            ResponseError::Failed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// Origins allowed to make cross-origin requests, `*` allows any origin
+#[derive(Debug, Clone)]
+pub struct Cors {
+    pub allowed_origins: Vec<String>,
+}
+
+impl Cors {
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.allows_any_origin()
+            || self
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed.trim_end_matches('/') == origin)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheRule {
+    pub matcher: GlobMatcher,
+    pub cache_control: String,
+}
+
+// Parses rules like `*.wasm=public,max-age=31536000,immutable`
+pub fn parse_cache_rules(rules: &[String]) -> Result<Vec<CacheRule>, String> {
+    rules
+        .iter()
+        .map(|rule| {
+            let (pattern, cache_control) = rule
+                .split_once('=')
+                .ok_or(format!("Invalid cache rule: {}", rule))?;
+
+            let matcher = Glob::new(pattern.trim())
+                .map_err(|err| format!("Invalid cache rule pattern '{}': {}", pattern, err))?
+                .compile_matcher();
+
+            Ok(CacheRule {
+                matcher,
+                cache_control: cache_control.trim().to_string(),
+            })
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -45,39 +191,650 @@ pub fn read_routes(path: &PathBuf) -> Vec<Route> {
         .collect()
 }
 
+// Presets from poly.toml take precedence over built-in ones with the same name
+pub fn preset_headers(
+    names: &[String],
+    custom_presets: &BTreeMap<String, Vec<String>>,
+    tls: bool,
+) -> Result<Vec<String>, String> {
+    names.iter().try_fold(Vec::new(), |mut headers, name| {
+        match (custom_presets.get(name), name.as_str()) {
+            (Some(preset), _) => headers.extend(preset.iter().cloned()),
+
+            (None, "security") => {
+                headers.extend(SECURITY_HEADERS.iter().map(|header| header.to_string()));
+
+                if tls {
+                    headers.push(HSTS_HEADER.to_string());
+                }
+            }
+
+            (None, _) => {
+                let available: Vec<&str> = custom_presets
+                    .keys()
+                    .map(String::as_str)
+                    .chain(["security"])
+                    .collect();
+
+                return Err(format!(
+                    "Unknown headers preset '{}', available presets: [{}]",
+                    name,
+                    available.join(", ")
+                ));
+            }
+        }
+
+        Ok(headers)
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub from: String,
+    pub to: String,
+    pub status: StatusCode,
+}
+
+// Lines like `/old -> /new 301` in the routes file, the status defaults to 301 like in `_redirects`.
+// A `*` in the source matches one segment, a trailing `*` the rest of the path, available as `:splat`
+pub fn read_redirects(path: &PathBuf) -> Vec<Redirect> {
+    let content = fs::read_to_string(path).unwrap_or_default();
+
+    content
+        .lines()
+        .filter(|line| line.contains("->"))
+        .filter_map(|line| match parse_redirect(line) {
+            Ok(redirect) => Some(redirect),
+
+            Err(err) => {
+                eprintln!("Warning: Ignoring redirect '{}': {}", line.trim(), err);
+                None
+            }
+        })
+        .collect()
+}
+
+fn parse_redirect(line: &str) -> Result<Redirect, String> {
+    let (from, rest) = line.split_once("->").unwrap_or((line, ""));
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+
+    let (to, status) = match parts[..] {
+        [to] => (to, StatusCode::MOVED_PERMANENTLY),
+
+        [to, status] => {
+            let status = status
+                .parse::<u16>()
+                .ok()
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .filter(|status| matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308))
+                .ok_or(format!("{} is not a redirect status", status))?;
+
+            (to, status)
+        }
+
+        _ => return Err("expected `/from -> /to [status]`".to_string()),
+    };
+
+    if !from.trim().starts_with('/') {
+        return Err("the source must start with '/'".to_string());
+    }
+
+    Ok(Redirect {
+        from: from.trim().to_string(),
+        to: to.to_string(),
+        status,
+    })
+}
+
 #[derive(Debug)]
 pub enum Error {
     Bind(std::io::Error),
+    DevSession(dev_session::Error),
 }
 
 pub fn start(config: &Config) -> Result<(), Error> {
-    let port = listen_port_from_str(&config.static_base_path.to_string_lossy());
+    let port = config
+        .port
+        .unwrap_or_else(|| default_port(&config.static_base_path));
     let addr = format!("127.0.0.1:{}", port);
+    let scheme = if config.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
 
-    println!("Listening on {}", addr);
+    println!("Listening on {}://{}", scheme, addr);
     let listener = TcpListener::bind(&addr).map_err(Error::Bind)?;
 
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
+    let dev_token = if config.dev_client {
+        dev_session::create_token(&config.dev_session_dir, port).map_err(Error::DevSession)?
+    } else {
+        String::new()
+    };
 
-        match handle_connection(config, stream) {
-            Ok(_) => {}
-            Err(err) => eprintln!("Error: {}", err),
-        };
-    }
+    let connection_limit = ConnectionLimit::new(MAX_CONNECTIONS);
+    let dev_state = DevState::new(scheme, port, dev_token);
+
+    // Each connection gets its own thread so a slow download doesn't block other requests
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("Error: Failed to accept connection: {}", err);
+                    continue;
+                }
+            };
+
+            connection_limit.acquire();
+            let connection_limit = &connection_limit;
+            let dev_state = &dev_state;
+
+            scope.spawn(move || {
+                let _ = stream.set_read_timeout(Some(KEEP_ALIVE_TIMEOUT));
+                let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+                let peer_addr = stream.peer_addr().ok();
+
+                let result = match &config.tls {
+                    Some(tls_config) => {
+                        handle_tls_connection(config, dev_state, tls_config, peer_addr, stream)
+                    }
+
+                    None => handle_connection(config, dev_state, peer_addr, &mut stream),
+                };
+
+                match result {
+                    Ok(_) => {}
+                    Err(err) => eprintln!("Error: {}", err),
+                };
+
+                connection_limit.release();
+            });
+        }
+    });
 
     Ok(())
 }
 
-fn handle_connection(config: &Config, mut stream: TcpStream) -> Result<(), String> {
-    let req = read_request(&mut stream)?;
-    log_request(&req);
+struct ConnectionLimit {
+    max: usize,
+    active: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ConnectionLimit {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            active: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) {
+        let mut active = self.active.lock().unwrap();
+
+        while *active >= self.max {
+            active = self.released.wait(active).unwrap();
+        }
+
+        *active += 1;
+    }
+
+    fn release(&self) {
+        let mut active = self.active.lock().unwrap();
+        *active -= 1;
+        self.released.notify_one();
+    }
+}
+
+// Runtime state of the dev endpoints, shared by all connections
+#[derive(Debug)]
+struct DevState {
+    hub: live_reload::Hub,
+    evaluations: dev_console::Evaluations,
+    scheme: &'static str,
+    hosts: Vec<String>,
+    token: String,
+}
+
+impl DevState {
+    fn new(scheme: &'static str, port: u32, token: String) -> Self {
+        Self {
+            hub: live_reload::Hub::new(),
+            evaluations: dev_console::Evaluations::default(),
+            scheme,
+            hosts: vec![format!("127.0.0.1:{}", port), format!("localhost:{}", port)],
+            token,
+        }
+    }
+}
+
+// The port used when none is configured, derived from the static path so that
+// different projects don't collide
+pub fn default_port(static_base_path: &Path) -> u32 {
+    listen_port_from_str(&static_base_path.to_string_lossy())
+}
+
+fn handle_tls_connection(
+    config: &Config,
+    dev_state: &DevState,
+    tls_config: &Arc<rustls::ServerConfig>,
+    peer_addr: Option<SocketAddr>,
+    stream: TcpStream,
+) -> Result<(), String> {
+    let conn = rustls::ServerConnection::new(tls_config.clone())
+        .map_err(|err| format!("Failed to start TLS session: {}", err))?;
+    let mut tls_stream = rustls::StreamOwned::new(conn, stream);
+
+    handle_connection(config, dev_state, peer_addr, &mut tls_stream)?;
+
+    tls_stream.conn.send_close_notify();
+    tls_stream
+        .flush()
+        .map_err(|err| format!("Failed to close TLS session: {}", err))
+}
+
+// Serves requests until the client closes the connection, asks for it to be closed
+// or stays idle for longer than the keep-alive timeout
+fn handle_connection<S: Read + Write>(
+    config: &Config,
+    dev_state: &DevState,
+    peer_addr: Option<SocketAddr>,
+    stream: S,
+) -> Result<(), String> {
+    let mut reader = BufReader::new(stream);
     let headers = prepare_headers(config);
-    let res = prepare_response(config, &req, &headers)?;
-    write_response(stream, res)?;
+
+    loop {
+        let (req, req_body) = match read_request(&mut reader)? {
+            Some(req) => req,
+            None => return Ok(()),
+        };
+
+        let started_at = Instant::now();
+        let is_dev_client_request = config.dev_client && is_dev_client_path(&req);
+        let access_log_on_stdout = config
+            .access_log
+            .as_ref()
+            .map(AccessLog::is_stdout)
+            .unwrap_or(false);
+
+        let dev_request_error = if is_dev_client_request {
+            check_dev_request(dev_state, &req).err()
+        } else {
+            None
+        };
+
+        if let Some(err) = &dev_request_error {
+            eprintln!("Rejected {} {}: {}", req.method(), req.uri().path(), err);
+        }
+
+        if is_dev_client_request && dev_request_error.is_none() && is_websocket_upgrade(&req) {
+            return serve_reload_socket(&req, &dev_state.hub, reader.get_mut());
+        }
+
+        let mut res = match *req.method() {
+            Method::OPTIONS if config.cors.is_some() && is_preflight(&req) => {
+                prepare_allow_response(StatusCode::NO_CONTENT, &headers)
+            }
+
+            _ if dev_request_error.is_some() => {
+                prepare_empty_response(StatusCode::FORBIDDEN, &headers)
+            }
+
+            _ if match_redirect(config, &req).is_some() => {
+                prepare_redirect_response(config, &req, &headers)
+            }
+
+            _ if config.proxy.match_rule(&req).is_some() => {
+                prepare_proxy_response(config, &req, &req_body)
+            }
+
+            Method::GET | Method::HEAD if is_dev_client_request => {
+                prepare_dev_client_response(&headers)
+            }
+
+            Method::POST if is_dev_client_request => {
+                prepare_dev_post_response(dev_state, &req, &req_body, &headers)
+            }
+
+            Method::GET | Method::HEAD => match prepare_response(config, &req, &headers) {
+                Ok(res) => res,
+                Err(err) => prepare_error_response(config, err, &headers),
+            },
+            Method::OPTIONS => prepare_allow_response(StatusCode::NO_CONTENT, &headers),
+            _ => prepare_allow_response(StatusCode::METHOD_NOT_ALLOWED, &headers),
+        };
+
+        if let Some(cors) = &config.cors {
+            add_cors_headers(cors, &req, &mut res);
+        }
+
+        let keep_alive = is_keep_alive(&req);
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        res.headers_mut()
+            .insert("Connection", HeaderValue::from_static(connection));
+
+        let include_body = req.method() != Method::HEAD;
+        let status = res.status();
+        let bytes = if include_body { res.body().len() } else { 0 };
+        write_response(reader.get_mut(), res, include_body)?;
+
+        // Dev client posts are console traffic, only the script itself is logged
+        let is_dev_client_post = is_dev_client_request && req.method() == Method::POST;
+
+        if !is_dev_client_post && !access_log_on_stdout {
+            log_request(&req, status, bytes, started_at.elapsed());
+        }
+
+        if let Some(access_log) = &config.access_log {
+            access_log.log(&access_log::Entry {
+                req: &req,
+                peer_addr,
+                status,
+                bytes,
+                duration: started_at.elapsed(),
+            });
+        }
+
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+fn is_dev_client_path(req: &Request<()>) -> bool {
+    let path = req.uri().path();
+
+    match *req.method() {
+        Method::POST => [
+            dev_client::CONSOLE_PATH,
+            live_reload::RELOAD_PATH,
+            dev_console::EVAL_PATH,
+            dev_console::EVAL_RESULT_PATH,
+        ]
+        .contains(&path),
+        _ => path == dev_client::CLIENT_PATH || path == live_reload::RELOAD_PATH,
+    }
+}
+
+// The page can be made to evaluate code through these endpoints, so they only answer
+// requests for the served origin, which keeps other sites and rebound DNS names out.
+// The ones used by `poly watch` and `poly console` also need the token serve wrote to
+// .poly/dev, since a browser can't be made to send it
+fn check_dev_request(dev_state: &DevState, req: &Request<()>) -> Result<(), String> {
+    let host = header_str(req, "Host").ok_or("Missing Host header")?;
+
+    if !dev_state.hosts.iter().any(|allowed| allowed == host) {
+        return Err(format!("Unexpected host '{}'", host));
+    }
+
+    let serve_origin = format!("{}://{}", dev_state.scheme, host);
+
+    match header_str(req, "Origin") {
+        Some(origin) if origin != serve_origin => {
+            return Err(format!("Unexpected origin '{}'", origin));
+        }
+
+        None if is_page_dev_request(req) => return Err("Missing Origin header".to_string()),
+
+        _ => {}
+    }
+
+    if is_tool_dev_request(req) {
+        let token = header_str(req, dev_session::TOKEN_HEADER).unwrap_or_default();
+
+        if !dev_session::is_valid_token(&dev_state.token, token) {
+            return Err(format!(
+                "Missing or invalid {} header",
+                dev_session::TOKEN_HEADER
+            ));
+        }
+    }
+
     Ok(())
 }
 
+// Browsers send an Origin with posts and websocket upgrades, but not for script tags
+fn is_page_dev_request(req: &Request<()>) -> bool {
+    let path = req.uri().path();
+
+    match *req.method() {
+        Method::POST => path == dev_client::CONSOLE_PATH || path == dev_console::EVAL_RESULT_PATH,
+        _ => path == live_reload::RELOAD_PATH,
+    }
+}
+
+fn is_tool_dev_request(req: &Request<()>) -> bool {
+    let path = req.uri().path();
+    req.method() == Method::POST
+        && (path == live_reload::RELOAD_PATH || path == dev_console::EVAL_PATH)
+}
+
+fn header_str<'a>(req: &'a Request<()>, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn is_websocket_upgrade(req: &Request<()>) -> bool {
+    req.method() == Method::GET
+        && req
+            .headers()
+            .get("Upgrade")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.eq_ignore_ascii_case("websocket"))
+            .unwrap_or(false)
+}
+
+// Takes over the connection until the browser goes away, client frames are never read
+fn serve_reload_socket<S: Write>(
+    req: &Request<()>,
+    reload_hub: &live_reload::Hub,
+    stream: &mut S,
+) -> Result<(), String> {
+    let key = req
+        .headers()
+        .get("Sec-WebSocket-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or("Missing Sec-WebSocket-Key header")?;
+
+    let handshake = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        live_reload::websocket_accept_key(key)
+    );
+
+    write(stream, handshake.as_bytes())?;
+    let _ = stream.flush();
+
+    let mut seen = reload_hub.last_id();
+    reload_hub.connected();
+
+    loop {
+        let (last_id, messages) = reload_hub.wait_for_messages(seen, RELOAD_PING_INTERVAL);
+
+        let frames: Vec<u8> = if messages.is_empty() {
+            live_reload::websocket_ping_frame()
+        } else {
+            messages
+                .iter()
+                .flat_map(|message| live_reload::websocket_text_frame(message))
+                .collect()
+        };
+
+        seen = last_id;
+
+        // A failed write means the tab was closed or navigated away
+        if stream
+            .write_all(&frames)
+            .and_then(|_| stream.flush())
+            .is_err()
+        {
+            reload_hub.disconnected();
+            return Ok(());
+        }
+    }
+}
+
+fn is_preflight(req: &Request<()>) -> bool {
+    req.headers().contains_key("Origin")
+        && req.headers().contains_key("Access-Control-Request-Method")
+}
+
+// Applies to every response, including proxied ones, so the backend doesn't need its own CORS setup
+fn add_cors_headers(cors: &Cors, req: &Request<()>, res: &mut Response<Content>) {
+    let origin = match req.headers().get("Origin").and_then(|v| v.to_str().ok()) {
+        Some(origin) if cors.allows(origin) => origin.to_string(),
+        _ => return,
+    };
+
+    let res_headers = res.headers_mut();
+
+    if cors.allows_any_origin() {
+        res_headers.insert("Access-Control-Allow-Origin", HeaderValue::from_static("*"));
+    } else if let Ok(value) = HeaderValue::from_str(&origin) {
+        res_headers.insert("Access-Control-Allow-Origin", value);
+        res_headers.insert(
+            "Access-Control-Allow-Credentials",
+            HeaderValue::from_static("true"),
+        );
+        res_headers.append("Vary", HeaderValue::from_static("Origin"));
+    }
+
+    if is_preflight(req) {
+        let req_headers = req.headers();
+
+        if let Some(method) = req_headers.get("Access-Control-Request-Method") {
+            res_headers.insert("Access-Control-Allow-Methods", method.clone());
+        }
+
+        if let Some(headers) = req_headers.get("Access-Control-Request-Headers") {
+            res_headers.insert("Access-Control-Allow-Headers", headers.clone());
+        }
+
+        res_headers.insert("Access-Control-Max-Age", HeaderValue::from_static("86400"));
+    }
+}
+
+fn prepare_proxy_response(
+    config: &Config,
+    req: &Request<()>,
+    req_body: &[u8],
+) -> Response<Content> {
+    let mut res = config
+        .proxy
+        .forward(req, req_body, config.tls.is_some())
+        .map(Content::Bytes);
+
+    if !res.headers().contains_key("Content-Length") {
+        let content_length = HeaderValue::from(res.body().len());
+        res.headers_mut().insert("Content-Length", content_length);
+    }
+
+    res
+}
+
+fn prepare_dev_post_response(
+    dev_state: &DevState,
+    req: &Request<()>,
+    req_body: &[u8],
+    extra_headers: &HeaderMap<HeaderValue>,
+) -> Response<Content> {
+    match req.uri().path() {
+        live_reload::RELOAD_PATH => {
+            println!("[Live reload] Reloading browser");
+            dev_state.hub.broadcast(live_reload::RELOAD_MESSAGE);
+        }
+
+        dev_console::EVAL_PATH => {
+            return prepare_eval_response(dev_state, req_body, extra_headers);
+        }
+
+        dev_console::EVAL_RESULT_PATH => dev_state.evaluations.complete(req_body),
+
+        _ => dev_client::print_console_message(req_body),
+    }
+
+    prepare_empty_response(StatusCode::NO_CONTENT, extra_headers)
+}
+
+// Sends the code to the connected pages and waits for the first one to answer
+fn prepare_eval_response(
+    dev_state: &DevState,
+    code: &[u8],
+    extra_headers: &HeaderMap<HeaderValue>,
+) -> Response<Content> {
+    let (status, result) = if dev_state.hub.has_connections() {
+        let id = dev_state.evaluations.start();
+        let code = String::from_utf8_lossy(code);
+        dev_state
+            .hub
+            .broadcast(&dev_console::eval_message(id, &code));
+
+        match dev_state.evaluations.wait(id, dev_console::EVAL_TIMEOUT) {
+            Some(result) => (StatusCode::OK, result),
+            None => (
+                StatusCode::GATEWAY_TIMEOUT,
+                dev_console::EvalResult::failed("No answer from the page"),
+            ),
+        }
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            dev_console::EvalResult::failed("No page connected, open the app in a browser"),
+        )
+    };
+
+    let body = serde_json::to_vec(&result).unwrap_or_default();
+
+    let res_builder = Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Content-Length", body.len());
+
+    extra_headers
+        .iter()
+        .fold(res_builder, |builder, (name, value)| {
+            builder.header(name, value)
+        })
+        .body(Content::Bytes(body))
+        .unwrap()
+}
+
+fn prepare_dev_client_response(extra_headers: &HeaderMap<HeaderValue>) -> Response<Content> {
+    let script = dev_client::script().as_bytes().to_vec();
+
+    let res_builder = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/javascript; charset=utf-8")
+        .header("Content-Length", script.len())
+        .header("Cache-Control", "no-cache");
+
+    extra_headers
+        .iter()
+        .fold(res_builder, |builder, (name, value)| {
+            builder.header(name, value)
+        })
+        .body(Content::Bytes(script))
+        .unwrap()
+}
+
+// HTTP/1.1 connections are persistent by default, HTTP/1.0 ones have to opt in
+fn is_keep_alive(req: &Request<()>) -> bool {
+    let connection = req
+        .headers()
+        .get("Connection")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match req.version() {
+        Version::HTTP_11 => !connection.contains("close"),
+        _ => connection.contains("keep-alive"),
+    }
+}
+
 fn prepare_headers(config: &Config) -> HeaderMap<HeaderValue> {
     let headers: BTreeMap<&str, &str> = config
         .response_headers
@@ -110,30 +867,84 @@ fn header_from_str(key: &str, value: &str) -> Option<(HeaderName, HeaderValue)>
     Some((name, value))
 }
 
-fn log_request(req: &Request<()>) {
-    println!("[{}] {}", req.method(), req.uri().path());
+// Logged once the response is written, error statuses are colored when stdout is a terminal
+fn log_request(req: &Request<()>, status: StatusCode, bytes: u64, duration: Duration) {
+    let status_text = match status.as_u16() {
+        400..=499 if io::stdout().is_terminal() => format!("\x1b[33m{}\x1b[0m", status.as_u16()),
+        500..=599 if io::stdout().is_terminal() => format!("\x1b[31m{}\x1b[0m", status.as_u16()),
+        code => code.to_string(),
+    };
+
+    println!(
+        "[{}] {} {} {} bytes in {:.2?}",
+        req.method(),
+        req.uri().path(),
+        status_text,
+        bytes,
+        duration
+    );
 }
 
-fn write_response(mut stream: TcpStream, res: Response<Vec<u8>>) -> Result<(), String> {
-    write(&mut stream, HTTP1_1)?;
-    write(&mut stream, CRNL)?;
+fn write_response<S: Write>(
+    stream: &mut S,
+    res: Response<Content>,
+    include_body: bool,
+) -> Result<(), String> {
+    let status = res.status();
+    let reason = status.canonical_reason().unwrap_or_default();
+
+    write(stream, HTTP1_1)?;
+    write(
+        stream,
+        format!(" {} {}", status.as_str(), reason).as_bytes(),
+    )?;
+    write(stream, CRNL)?;
 
     for (name, value) in res.headers() {
-        write(&mut stream, format!("{}: ", name).as_bytes())?;
-        write(&mut stream, value.as_bytes())?;
-        write(&mut stream, CRNL)?;
+        write(stream, format!("{}: ", name).as_bytes())?;
+        write(stream, value.as_bytes())?;
+        write(stream, CRNL)?;
     }
 
-    write(&mut stream, CRNL)?;
+    write(stream, CRNL)?;
+
+    if include_body {
+        write_content(stream, res.body())
+            .map_err(|err| format!("Failed to write body: {}", err))?;
+    }
 
     stream
-        .write_all(res.body())
-        .map_err(|err| format!("Failed to write body: {}", err))?;
+        .flush()
+        .map_err(|err| format!("Failed to write response: {}", err))
+}
 
-    Ok(())
+fn write_content<S: Write>(stream: &mut S, content: &Content) -> Result<(), io::Error> {
+    match content {
+        Content::Bytes(bytes) => stream.write_all(bytes),
+
+        Content::File {
+            path,
+            start,
+            length,
+        } => {
+            let mut file = fs::File::open(path)?;
+            file.seek(SeekFrom::Start(*start))?;
+
+            let copied = io::copy(&mut file.take(*length), stream)?;
+
+            if copied == *length {
+                Ok(())
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "File was truncated while being served",
+                ))
+            }
+        }
+    }
 }
 
-fn write(stream: &mut TcpStream, data: &[u8]) -> Result<(), String> {
+fn write<S: Write>(stream: &mut S, data: &[u8]) -> Result<(), String> {
     stream
         .write_all(data)
         .map_err(|err| format!("Failed to write response: {}", err))
@@ -143,16 +954,37 @@ fn prepare_response(
     config: &Config,
     req: &Request<()>,
     extra_headers: &HeaderMap<HeaderValue>,
-) -> Result<Response<Vec<u8>>, String> {
+) -> Result<Response<Content>, ResponseError> {
     let body = prepare_response_body(config, req)?;
+    let body = if config.dev_client {
+        inject_dev_client(body)?
+    } else {
+        body
+    };
+    let body = if config.compress {
+        compress_body(req, body)?
+    } else {
+        body
+    };
 
-    let res_builder = Response::builder()
-        .status(200)
-        .header("Content-Type", body.content_type.to_string())
-        .header("Content-Length", body.content.len());
+    let mut res_builder = Response::builder()
+        .status(body.status)
+        .header("Content-Type", body.content_type.to_string());
+
+    if body.status != StatusCode::NOT_MODIFIED {
+        res_builder = res_builder.header("Content-Length", body.content.len());
+    }
 
-    let res_builder2 = extra_headers
+    if let Some(rule) = match_cache_rule(config, req) {
+        if !extra_headers.contains_key("Cache-Control") {
+            res_builder = res_builder.header("Cache-Control", &rule.cache_control);
+        }
+    }
+
+    let res_builder2 = body
+        .headers
         .iter()
+        .chain(extra_headers.iter())
         .fold(res_builder, |builder, (name, value)| {
             builder.header(name, value)
         });
@@ -162,15 +994,133 @@ fn prepare_response(
     Ok(response)
 }
 
-fn read_request(stream: &mut TcpStream) -> Result<Request<()>, String> {
-    let mut req_reader = BufReader::new(stream);
+fn prepare_error_response(
+    config: &Config,
+    err: ResponseError,
+    extra_headers: &HeaderMap<HeaderValue>,
+) -> Response<Content> {
+    eprintln!("Error: {}", err);
+
+    let (status, page_path) = match err {
+        ResponseError::NotFound(_) => (StatusCode::NOT_FOUND, &config.error_pages.not_found),
+        ResponseError::Failed(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &config.error_pages.server_error,
+        ),
+    };
+
+    let (content, content_type) = match fs::read(page_path) {
+        Ok(page) => (page, "text/html; charset=utf-8"),
+
+        Err(_) => (
+            format!(
+                "{} {}",
+                status.as_str(),
+                status.canonical_reason().unwrap_or_default()
+            )
+            .into_bytes(),
+            "text/plain; charset=utf-8",
+        ),
+    };
+
+    let res_builder = Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .header("Content-Length", content.len())
+        .header("Cache-Control", "no-cache");
+
+    extra_headers
+        .iter()
+        .fold(res_builder, |builder, (name, value)| {
+            builder.header(name, value)
+        })
+        .body(Content::Bytes(content))
+        .unwrap()
+}
+
+fn prepare_redirect_response(
+    config: &Config,
+    req: &Request<()>,
+    extra_headers: &HeaderMap<HeaderValue>,
+) -> Response<Content> {
+    match match_redirect(config, req) {
+        Some((redirect, location)) => {
+            println!("Matched redirect: {} -> {}", redirect.from, location);
+
+            let res_builder = Response::builder()
+                .status(redirect.status)
+                .header("Location", location)
+                .header("Content-Length", 0);
+
+            extra_headers
+                .iter()
+                .fold(res_builder, |builder, (name, value)| {
+                    builder.header(name, value)
+                })
+                .body(Content::Bytes(Vec::new()))
+                .unwrap()
+        }
+
+        None => prepare_empty_response(StatusCode::NOT_FOUND, extra_headers),
+    }
+}
+
+// For OPTIONS requests and methods that aren't allowed
+fn prepare_allow_response(
+    status: StatusCode,
+    extra_headers: &HeaderMap<HeaderValue>,
+) -> Response<Content> {
+    let mut res = prepare_empty_response(status, extra_headers);
+    res.headers_mut()
+        .insert("Allow", HeaderValue::from_static(ALLOWED_METHODS));
+    res
+}
+
+fn prepare_empty_response(
+    status: StatusCode,
+    extra_headers: &HeaderMap<HeaderValue>,
+) -> Response<Content> {
+    let mut res_builder = Response::builder().status(status);
+
+    if status != StatusCode::NO_CONTENT {
+        res_builder = res_builder.header("Content-Length", 0);
+    }
+
+    extra_headers
+        .iter()
+        .fold(res_builder, |builder, (name, value)| {
+            builder.header(name, value)
+        })
+        .body(Content::Bytes(Vec::new()))
+        .unwrap()
+}
+
+fn match_cache_rule<'a>(config: &'a Config, req: &Request<()>) -> Option<&'a CacheRule> {
+    let path = req.uri().path().trim_start_matches('/');
+
+    config
+        .cache_rules
+        .iter()
+        .find(|rule| rule.matcher.is_match(path))
+}
+
+// Returns None when the connection was closed or timed out before a new request arrived
+fn read_request<S: Read>(req_reader: &mut BufReader<S>) -> Result<Option<RequestWithBody>, String> {
     let mut buffer = Vec::new();
 
     // Read until start of body
     loop {
-        req_reader
-            .read_until(b'\n', &mut buffer)
-            .map_err(|err| format!("Failed to read request: {:?}", err))?;
+        let read_result = req_reader.read_until(b'\n', &mut buffer);
+
+        match read_result {
+            Ok(0) if buffer.is_empty() => return Ok(None),
+            Ok(0) => return Err("Connection closed mid request".to_string()),
+            Ok(_) => {}
+
+            Err(err) if buffer.is_empty() && is_timeout(&err) => return Ok(None),
+            Err(err) => return Err(format!("Failed to read request: {:?}", err)),
+        }
+
         if buffer.ends_with(&vec![b'\r', b'\n', b'\r', b'\n']) {
             break;
         }
@@ -178,20 +1128,105 @@ fn read_request(stream: &mut TcpStream) -> Result<Request<()>, String> {
 
     let mut headers = [httparse::EMPTY_HEADER; 64];
     let mut req = httparse::Request::new(&mut headers);
-    req.parse(&mut buffer).unwrap();
+    req.parse(&buffer)
+        .map_err(|err| format!("Invalid request: {}", err))?;
+
+    let version = match req.version {
+        Some(0) => Version::HTTP_10,
+        _ => Version::HTTP_11,
+    };
 
-    let req = request::Builder::new()
-        .method(req.method.unwrap_or_else(|| "GET"))
-        .uri(req.path.unwrap_or_else(|| "/"))
+    let req_builder = request::Builder::new()
+        .method(req.method.unwrap_or("GET"))
+        .uri(req.path.unwrap_or("/"))
+        .version(version);
+
+    let req_builder2 = req.headers.iter().fold(req_builder, |builder, header| {
+        builder.header(header.name, header.value)
+    });
+
+    let req = req_builder2
         .body(())
-        .unwrap();
+        .map_err(|err| format!("Invalid request: {}", err))?;
 
-    Ok(req)
+    let body = read_request_body(req_reader, &req)?;
+
+    Ok(Some((req, body)))
+}
+
+// The body always has to be consumed to reach the next request on the connection
+fn read_request_body<S: Read>(
+    req_reader: &mut BufReader<S>,
+    req: &Request<()>,
+) -> Result<Vec<u8>, String> {
+    let content_length: u64 = req
+        .headers()
+        .get("Content-Length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0);
+
+    let mut body_reader = req_reader.take(content_length);
+    let mut body = Vec::new();
+
+    let result = if content_length > MAX_REQUEST_BODY {
+        io::copy(&mut body_reader, &mut io::sink()).map(|_| ())
+    } else {
+        body_reader.read_to_end(&mut body).map(|_| ())
+    };
+
+    result
+        .map(|_| body)
+        .map_err(|err| format!("Failed to read request body: {}", err))
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+type RequestWithBody = (Request<()>, Vec<u8>);
+
+pub enum Content {
+    Bytes(Vec<u8>),
+
+    // Streamed from disk when the response is written
+    File {
+        path: PathBuf,
+        start: u64,
+        length: u64,
+    },
+}
+
+impl Content {
+    fn len(&self) -> u64 {
+        match self {
+            Content::Bytes(bytes) => bytes.len() as u64,
+            Content::File { length, .. } => *length,
+        }
+    }
+
+    fn into_bytes(self) -> Result<Vec<u8>, String> {
+        match self {
+            Content::Bytes(bytes) => Ok(bytes),
+
+            Content::File { .. } => {
+                let mut bytes = Vec::new();
+                write_content(&mut bytes, &self)
+                    .map_err(|err| format!("Failed to read file: {}", err))?;
+                Ok(bytes)
+            }
+        }
+    }
 }
 
 pub struct Body {
-    content: Vec<u8>,
+    status: StatusCode,
+    content: Content,
     content_type: Mime,
+    headers: HeaderMap<HeaderValue>,
 }
 
 fn match_route(config: &Config, req: &Request<()>) -> Option<Route> {
@@ -208,7 +1243,38 @@ fn match_route(config: &Config, req: &Request<()>) -> Option<Route> {
         .cloned()
 }
 
-fn compare_path_paths(req_parts: &Vec<String>, route_parts: &Vec<String>) -> bool {
+// Returns the first matching redirect and its location, the query string is passed on
+fn match_redirect<'a>(config: &'a Config, req: &Request<()>) -> Option<(&'a Redirect, String)> {
+    let req_parts = path_to_parts(req.uri().path());
+
+    config.redirects.iter().find_map(|redirect| {
+        let from_parts = path_to_parts(&redirect.from);
+
+        let splat = match from_parts.split_last() {
+            Some((last, prefix)) if last == "*" && req_parts.len() >= prefix.len() => {
+                if !compare_path_paths(&req_parts[..prefix.len()], prefix) {
+                    return None;
+                }
+
+                req_parts[prefix.len()..].join("/")
+            }
+
+            _ if compare_path_paths(&req_parts, &from_parts) => String::new(),
+
+            _ => return None,
+        };
+
+        let location = redirect.to.replace(":splat", &splat);
+        let location = match req.uri().query() {
+            Some(query) if !location.contains('?') => format!("{}?{}", location, query),
+            _ => location,
+        };
+
+        Some((redirect, location))
+    })
+}
+
+fn compare_path_paths(req_parts: &[String], route_parts: &[String]) -> bool {
     if req_parts.len() == route_parts.len() {
         req_parts
             .iter()
@@ -230,36 +1296,125 @@ fn path_to_parts(s: &str) -> Vec<String> {
         .collect()
 }
 
-fn prepare_response_body(config: &Config, req: &Request<()>) -> Result<Body, String> {
+fn prepare_response_body(config: &Config, req: &Request<()>) -> Result<Body, ResponseError> {
     let file_path = file_path_from_req(config, req)?;
 
     if let Some(route) = match_route(config, req) {
         println!("Matched route: {}", route.path);
-        body_from_route(req, &route)
+        Ok(body_from_route(req, &route)?)
     } else if file_path.exists() {
-        let content =
-            fs::read(&file_path).map_err(|err| format!("Failed to read file: {}", err))?;
-        let content_type = mime_guess::from_path(&file_path)
-            .first()
-            .unwrap_or_else(|| mime_guess::mime::APPLICATION_OCTET_STREAM);
-        Ok(Body {
-            content,
-            content_type,
-        })
+        Ok(body_from_static_file(req, &file_path, config.dev_client)?)
     } else if file_path.ends_with("favicon.ico") {
         let content_type = mime_guess::from_ext("ico")
             .first()
             .unwrap_or_else(|| mime_guess::mime::APPLICATION_OCTET_STREAM);
 
         Ok(Body {
-            content: favicon(),
+            status: StatusCode::OK,
+            content: Content::Bytes(favicon()),
             content_type,
+            headers: HeaderMap::new(),
         })
+    } else if config.spa && is_page_request(req) {
+        let index_path = config.static_base_path.join("index.html");
+        Ok(body_from_static_file(req, &index_path, config.dev_client)?)
     } else {
-        Err(format!("Path not found: {}", file_path.to_string_lossy()))
+        Err(ResponseError::NotFound(file_path))
     }
 }
 
+// Requests without a file extension are assumed to be client side routes
+fn is_page_request(req: &Request<()>) -> bool {
+    Path::new(req.uri().path()).extension().is_none()
+}
+
+// The validators of the body are set up for the injected html by body_from_file
+fn inject_dev_client(mut body: Body) -> Result<Body, String> {
+    if !is_html(&body.content_type)
+        || body.status != StatusCode::OK
+        || body.headers.contains_key("Content-Encoding")
+    {
+        return Ok(body);
+    }
+
+    let content = body.content.into_bytes()?;
+    body.content = Content::Bytes(dev_client::inject(&content));
+    body.headers.remove("Accept-Ranges");
+
+    Ok(body)
+}
+
+fn is_html(content_type: &Mime) -> bool {
+    content_type.type_() == mime_guess::mime::TEXT
+        && content_type.subtype() == mime_guess::mime::HTML
+}
+
+fn compress_body(req: &Request<()>, mut body: Body) -> Result<Body, String> {
+    if !accepts_encoding(req, "gzip")
+        || body.status != StatusCode::OK
+        || body.headers.contains_key("Content-Encoding")
+        || !is_compressible(&body.content_type)
+    {
+        return Ok(body);
+    }
+
+    let content = body.content.into_bytes()?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&content)
+        .map_err(|err| format!("Failed to compress body: {}", err))?;
+    body.content = Content::Bytes(
+        encoder
+            .finish()
+            .map_err(|err| format!("Failed to compress body: {}", err))?,
+    );
+
+    body.headers
+        .insert("Content-Encoding", HeaderValue::from_static("gzip"));
+    body.headers
+        .insert("Vary", HeaderValue::from_static("Accept-Encoding"));
+    body.headers.remove("Accept-Ranges");
+
+    // The compressed representation differs byte-wise from the file on disk
+    if let Some(etag) = body.headers.get("ETag").and_then(|etag| etag.to_str().ok()) {
+        let weak_etag = format!("W/{}", etag.trim_start_matches("W/"));
+        body.headers.insert("ETag", weak_etag.parse().unwrap());
+    }
+
+    Ok(body)
+}
+
+// Encodings with a quality of zero are explicitly not acceptable
+fn accepts_encoding(req: &Request<()>, encoding: &str) -> bool {
+    req.headers()
+        .get_all("Accept-Encoding")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let rejected = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .map(|q| q == 0.0)
+                    .unwrap_or(false)
+            });
+
+            (name.eq_ignore_ascii_case(encoding) || name == "*") && !rejected
+        })
+}
+
+fn is_compressible(content_type: &Mime) -> bool {
+    let subtype = content_type.subtype().as_str();
+
+    content_type.type_() == mime_guess::mime::TEXT
+        || ["javascript", "json", "wasm", "xml", "svg"]
+            .iter()
+            .any(|name| subtype.contains(name))
+}
+
 fn body_from_route(req: &Request<()>, route: &Route) -> Result<Body, String> {
     let (cmd, mut args) = exec::cmd_from_str(&route.cmd).ok_or("Invalid cmd")?;
     args.push(req.uri().path().to_string());
@@ -268,15 +1423,256 @@ fn body_from_route(req: &Request<()>, route: &Route) -> Result<Body, String> {
         work_dir: ".".into(),
         cmd,
         args,
+        env: Vec::new(),
+        env_mode: exec::EnvMode::Inherit,
+        timeout: None,
     })
     .map_err(|err| format!("Failed to run cmd: {}", err))?;
 
     Ok(Body {
-        content: output.into_bytes(),
+        status: StatusCode::OK,
+        content: Content::Bytes(output.into_bytes()),
         content_type: mime_guess::mime::TEXT_HTML_UTF_8,
+        headers: HeaderMap::new(),
     })
 }
 
+// Prefers a precompressed sibling (e.g. app.wasm.br) when the client accepts it
+fn body_from_static_file(
+    req: &Request<()>,
+    file_path: &Path,
+    dev_client: bool,
+) -> Result<Body, String> {
+    let siblings: Vec<(PathBuf, &str)> = PRECOMPRESSED_EXTENSIONS
+        .iter()
+        .map(|(ext, encoding)| (sibling_path(file_path, ext), *encoding))
+        .filter(|(path, _)| path.is_file())
+        .collect();
+
+    let selected = siblings
+        .iter()
+        .find(|(_, encoding)| accepts_encoding(req, encoding));
+
+    let mut body = match selected {
+        Some((path, encoding)) => {
+            let mut body = body_from_file(req, path, dev_client)?;
+            body.content_type = content_type_from_path(file_path);
+            body.headers
+                .insert("Content-Encoding", encoding.parse().unwrap());
+            body
+        }
+
+        None => body_from_file(req, file_path, dev_client)?,
+    };
+
+    if !siblings.is_empty() {
+        body.headers
+            .insert("Vary", HeaderValue::from_static("Accept-Encoding"));
+    }
+
+    Ok(body)
+}
+
+fn sibling_path(file_path: &Path, ext: &str) -> PathBuf {
+    let mut path = file_path.as_os_str().to_os_string();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
+
+fn content_type_from_path(file_path: &Path) -> Mime {
+    mime_guess::from_path(file_path)
+        .first()
+        .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM)
+}
+
+// Html gets the dev client injected when it's on, so it differs from the file on disk. It
+// gets a weak validator of its own, the same port serves the page with and without the
+// client, and ranges of the file would not match the injected body
+fn body_from_file(req: &Request<()>, file_path: &Path, dev_client: bool) -> Result<Body, String> {
+    let metadata =
+        fs::metadata(file_path).map_err(|err| format!("Failed to read metadata: {}", err))?;
+    let content_type = content_type_from_path(file_path);
+    let injected = dev_client && is_html(&content_type);
+
+    let (etag, last_modified) = if injected {
+        (dev_client_etag(&metadata), None)
+    } else {
+        (
+            etag_from_metadata(&metadata),
+            metadata.modified().ok().map(DateTime::from_system_time),
+        )
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert("ETag", etag.parse().unwrap());
+
+    if !injected {
+        headers.insert("Accept-Ranges", HeaderValue::from_static("bytes"));
+    }
+
+    if let Some(last_modified) = &last_modified {
+        headers.insert(
+            "Last-Modified",
+            last_modified.to_http_date().parse().unwrap(),
+        );
+    }
+
+    let not_modified = if req.headers().contains_key("If-None-Match") {
+        etag_matches(req, &etag)
+    } else {
+        last_modified
+            .map(|last_modified| not_modified_since(req, &last_modified))
+            .unwrap_or(false)
+    };
+
+    if not_modified {
+        return Ok(Body {
+            status: StatusCode::NOT_MODIFIED,
+            content: Content::Bytes(Vec::new()),
+            content_type,
+            headers,
+        });
+    }
+
+    let file_length = metadata.len();
+
+    let range = if injected {
+        None
+    } else {
+        requested_range(req, file_length as usize)
+    };
+
+    match range {
+        None => Ok(Body {
+            status: StatusCode::OK,
+            content: Content::File {
+                path: file_path.to_path_buf(),
+                start: 0,
+                length: file_length,
+            },
+            content_type,
+            headers,
+        }),
+
+        Some(Ok(range)) => {
+            let content_range = format!("bytes {}-{}/{}", range.start, range.end, file_length);
+            headers.insert("Content-Range", content_range.parse().unwrap());
+
+            Ok(Body {
+                status: StatusCode::PARTIAL_CONTENT,
+                content: Content::File {
+                    path: file_path.to_path_buf(),
+                    start: range.start as u64,
+                    length: (range.end - range.start + 1) as u64,
+                },
+                content_type,
+                headers,
+            })
+        }
+
+        Some(Err(())) => {
+            let content_range = format!("bytes */{}", file_length);
+            headers.insert("Content-Range", content_range.parse().unwrap());
+
+            Ok(Body {
+                status: StatusCode::RANGE_NOT_SATISFIABLE,
+                content: Content::Bytes(Vec::new()),
+                content_type,
+                headers,
+            })
+        }
+    }
+}
+
+// Based on size and modification time so the file doesn't have to be hashed
+fn etag_from_metadata(metadata: &fs::Metadata) -> String {
+    let modified_nanos = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+
+    format!("\"{:x}-{:x}\"", metadata.len(), modified_nanos)
+}
+
+fn dev_client_etag(metadata: &fs::Metadata) -> String {
+    let etag = etag_from_metadata(metadata);
+    format!("W/\"{}-dev\"", etag.trim_matches('"'))
+}
+
+// If-None-Match uses weak comparison, so W/ prefixes are ignored
+fn etag_matches(req: &Request<()>, etag: &str) -> bool {
+    let strip_weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+
+    req.headers()
+        .get_all("If-None-Match")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || strip_weak(tag) == strip_weak(etag))
+}
+
+fn not_modified_since(req: &Request<()>, last_modified: &DateTime) -> bool {
+    req.headers()
+        .get("If-Modified-Since")
+        .and_then(|value| value.to_str().ok())
+        .and_then(DateTime::from_http_date)
+        .map(|since| last_modified.unix_secs() <= since.unix_secs())
+        .unwrap_or(false)
+}
+
+struct ByteRange {
+    start: usize,
+    end: usize,
+}
+
+// Only single ranges are supported, multipart ranges are served as a full response
+fn requested_range(req: &Request<()>, content_length: usize) -> Option<Result<ByteRange, ()>> {
+    let value = req.headers().get("Range")?.to_str().ok()?;
+    let spec = value.trim().strip_prefix("bytes=")?;
+
+    if spec.contains(',') {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let last_index = content_length.checked_sub(1);
+
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix_length: usize = suffix.parse().ok()?;
+            last_index
+                .filter(|_| suffix_length > 0)
+                .map(|last| ByteRange {
+                    start: content_length.saturating_sub(suffix_length),
+                    end: last,
+                })
+        }
+
+        (start, "") => {
+            let start: usize = start.parse().ok()?;
+            last_index
+                .filter(|last| start <= *last)
+                .map(|last| ByteRange { start, end: last })
+        }
+
+        (start, end) => {
+            let start: usize = start.parse().ok()?;
+            let end: usize = end.parse().ok()?;
+            last_index
+                .filter(|last| start <= end && start <= *last)
+                .map(|last| ByteRange {
+                    start,
+                    end: end.min(last),
+                })
+        }
+    };
+
+    Some(range.ok_or(()))
+}
+
 fn file_path_from_req(config: &Config, req: &Request<()>) -> Result<PathBuf, String> {
     let req_path = req.uri().path().trim_start_matches("/");
     let abs_path = config.static_base_path.join(&req_path);
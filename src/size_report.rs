@@ -0,0 +1,159 @@
+use crate::build::Env;
+use crate::util::compress;
+use crate::ProjectInfo;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadFile(PathBuf, io::Error),
+    Compress(io::Error),
+    WriteReport(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadFile(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::Compress(err) => write!(f, "Failed to compress: {}", err),
+            Error::WriteReport(err) => write!(f, "Failed to save size report: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub env: Env,
+    pub wasm_dist_path: PathBuf,
+    pub project_name: String,
+    pub report_path: PathBuf,
+}
+
+impl Config {
+    pub fn from_project_info(env: &Env, project_info: &ProjectInfo) -> Self {
+        let project_dir = project_info
+            .dist_path
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_default();
+
+        Self {
+            env: env.clone(),
+            wasm_dist_path: project_info.dist_path.join("wasm"),
+            project_name: project_info.project_name.clone(),
+            report_path: project_dir.join(".poly").join("wasm-sizes.json"),
+        }
+    }
+
+    fn file_names(&self) -> Vec<String> {
+        vec![
+            format!("{}_bg.wasm", self.project_name),
+            format!("{}.js", self.project_name),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+struct FileSize {
+    raw: u64,
+    gzip: u64,
+    brotli: u64,
+}
+
+// Sizes of the last build per env, dev and release builds aren't comparable
+type Report = BTreeMap<String, BTreeMap<String, FileSize>>;
+
+pub fn print(config: &Config) -> Result<(), Error> {
+    let mut report: Report = fs::read_to_string(&config.report_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    let previous = report
+        .get(&config.env.to_string())
+        .cloned()
+        .unwrap_or_default();
+    let mut current = BTreeMap::new();
+
+    for name in config.file_names() {
+        let path = config.wasm_dist_path.join(&name);
+        let content = fs::read(&path).map_err(|err| Error::ReadFile(path.clone(), err))?;
+
+        let size = FileSize {
+            raw: content.len() as u64,
+            gzip: gzip_size(&content)?,
+            brotli: brotli_size(&content)?,
+        };
+
+        println!(
+            "[Size] {}: {} (gzip {}, brotli {}){}",
+            name,
+            format_size(size.raw),
+            format_size(size.gzip),
+            format_size(size.brotli),
+            previous
+                .get(&name)
+                .map(|prev: &FileSize| format_change(prev.raw, size.raw))
+                .unwrap_or_default()
+        );
+
+        current.insert(name, size);
+    }
+
+    report.insert(config.env.to_string(), current);
+
+    if let Some(dir) = config.report_path.parent() {
+        fs::create_dir_all(dir).map_err(Error::WriteReport)?;
+    }
+
+    let json = serde_json::to_string_pretty(&report).unwrap_or_default();
+    fs::write(&config.report_path, json).map_err(Error::WriteReport)
+}
+
+fn gzip_size(content: &[u8]) -> Result<u64, Error> {
+    let compressed = compress::gzip(content, 9).map_err(Error::Compress)?;
+
+    Ok(compressed.len() as u64)
+}
+
+// Quality 9 is close to what CDNs use for on the fly compression and much faster than 11
+fn brotli_size(content: &[u8]) -> Result<u64, Error> {
+    let compressed = compress::brotli(content, 9).map_err(Error::Compress)?;
+
+    Ok(compressed.len() as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+fn format_change(previous: u64, current: u64) -> String {
+    if previous == current {
+        return ", unchanged".to_string();
+    }
+
+    let (sign, diff) = if current > previous {
+        ("+", current - previous)
+    } else {
+        ("-", previous - current)
+    };
+
+    let percent = if previous > 0 {
+        format!(" ({}{:.1}%)", sign, diff as f64 / previous as f64 * 100.0)
+    } else {
+        String::new()
+    };
+
+    format!(", {}{}{}", sign, format_size(diff), percent)
+}
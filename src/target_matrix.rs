@@ -0,0 +1,379 @@
+use crate::build;
+use crate::build::BuildStep;
+use crate::build::Env;
+use crate::build::Profile;
+use crate::build::Runner;
+use crate::exec;
+use crate::ProjectInfo;
+use sha2::Digest;
+use sha2::Sha256;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+const LOADER_FILE_NAME: &str = "loader.js";
+
+// Ordered by preference, the loader picks the first variant the browser supports
+const VARIANTS: [Variant; 4] = [
+    Variant {
+        name: "simd-threads",
+        simd: true,
+        threads: true,
+    },
+    Variant {
+        name: "threads",
+        simd: false,
+        threads: true,
+    },
+    Variant {
+        name: "simd",
+        simd: true,
+        threads: false,
+    },
+    Variant {
+        name: "baseline",
+        simd: false,
+        threads: false,
+    },
+];
+
+#[derive(Debug, Clone, Copy)]
+struct Variant {
+    name: &'static str,
+    simd: bool,
+    threads: bool,
+}
+
+impl Variant {
+    fn rustflags(&self) -> String {
+        let mut features = Vec::new();
+
+        if self.simd {
+            features.push("+simd128");
+        }
+
+        if self.threads {
+            features.extend(["+atomics", "+bulk-memory", "+mutable-globals"]);
+        }
+
+        if features.is_empty() {
+            String::new()
+        } else {
+            format!("-C target-feature={}", features.join(","))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub profile: Profile,
+    pub project_name: String,
+    pub wasm_project_path: PathBuf,
+    pub build_path: PathBuf,
+    pub output_path: PathBuf,
+    // Variants built at the same time, each has its own target dir
+    pub jobs: usize,
+}
+
+impl Config {
+    pub fn from_project_info(profile: &Profile, project_info: &ProjectInfo, jobs: usize) -> Self {
+        Self {
+            profile: profile.clone(),
+            project_name: project_info.project_name.clone(),
+            wasm_project_path: project_info.wasm_project_path.clone(),
+            build_path: project_info
+                .dist_path
+                .with_file_name("target")
+                .join("poly-matrix"),
+            output_path: project_info.dist_path.join("wasm-matrix"),
+            jobs,
+        }
+    }
+
+    fn variant_build_path(&self, variant: &Variant) -> PathBuf {
+        self.build_path.join(variant.name)
+    }
+
+    fn variant_pkg_path(&self, variant: &Variant) -> PathBuf {
+        self.variant_build_path(variant).join("pkg")
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    CreateDir(PathBuf, io::Error),
+    RemoveOutputDir(io::Error),
+    WasmPack(String, exec::Error),
+    ReadFile(PathBuf, io::Error),
+    WriteFile(PathBuf, io::Error),
+    CopySnippets(fs_extra::error::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Error::CreateDir(path, err) => {
+                write!(f, "Failed to create {}: {}", path.display(), err)
+            }
+
+            Error::RemoveOutputDir(err) => {
+                write!(f, "Failed to remove old target matrix output: {}", err)
+            }
+
+            Error::WasmPack(variant, err) => {
+                write!(f, "wasm-pack failed for the {} variant: {}", variant, err)
+            }
+
+            Error::ReadFile(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+
+            Error::WriteFile(path, err) => {
+                write!(f, "Failed to write {}: {}", path.display(), err)
+            }
+
+            Error::CopySnippets(err) => write!(f, "Failed to copy wasm-bindgen snippets: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BuiltVariant {
+    variant: Variant,
+    glue_file_name: String,
+}
+
+// Builds the wasm crate once per SIMD/threads combination into dist/wasm-matrix
+// together with a loader that picks the best variant the browser supports
+#[derive(Debug, Clone)]
+pub struct TargetMatrix {
+    config: Config,
+}
+
+impl TargetMatrix {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    fn build_variant(&self, variant: &Variant) -> Result<BuiltVariant, Error> {
+        println!("[target matrix] Building the {} variant", variant.name);
+
+        exec::run_streamed(&self.variant_command(variant))
+            .map_err(|err| Error::WasmPack(variant.name.to_string(), err))?;
+
+        self.copy_variant_to_dist(variant, &self.config.variant_pkg_path(variant))
+    }
+
+    fn variant_command(&self, variant: &Variant) -> exec::Config {
+        let pkg_path = self.config.variant_pkg_path(variant);
+        let build_flag = match self.config.profile.env {
+            Env::Dev => "--dev",
+            Env::Release => "--release",
+        };
+
+        let mut args = exec::to_args(&[
+            "build",
+            build_flag,
+            "--target",
+            "web",
+            "--out-name",
+            &self.config.project_name,
+            "--out-dir",
+            &pkg_path.to_string_lossy(),
+        ]);
+
+        // The std library has to be rebuilt with atomics enabled, which requires nightly
+        if variant.threads {
+            args.extend(exec::to_args(&["--", "-Z", "build-std=panic_abort,std"]));
+        }
+
+        exec::Config {
+            work_dir: self.config.wasm_project_path.clone(),
+            cmd: "wasm-pack".into(),
+            args,
+            env: vec![
+                ("RUSTFLAGS".to_string(), variant.rustflags()),
+                (
+                    "CARGO_TARGET_DIR".to_string(),
+                    self.config
+                        .variant_build_path(variant)
+                        .join("target")
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+            ],
+            env_mode: exec::EnvMode::Inherit,
+            timeout: None,
+        }
+    }
+
+    // The wasm and glue file names get a content hash, so each variant can be cached forever
+    fn copy_variant_to_dist(
+        &self,
+        variant: &Variant,
+        pkg_path: &Path,
+    ) -> Result<BuiltVariant, Error> {
+        let name = &self.config.project_name;
+        let output_path = self.config.output_path.join(variant.name);
+        fs::create_dir_all(&output_path)
+            .map_err(|err| Error::CreateDir(output_path.clone(), err))?;
+
+        let wasm_file_name = format!("{}_bg.wasm", name);
+        let wasm_path = pkg_path.join(&wasm_file_name);
+        let wasm = fs::read(&wasm_path).map_err(|err| Error::ReadFile(wasm_path, err))?;
+        let hashed_wasm_file_name = format!("{}_bg.{}.wasm", name, short_hash(&wasm));
+
+        let glue_path = pkg_path.join(format!("{}.js", name));
+        let glue = fs::read_to_string(&glue_path).map_err(|err| Error::ReadFile(glue_path, err))?;
+        let glue = glue.replace(&wasm_file_name, &hashed_wasm_file_name);
+        let glue_file_name = format!("{}.{}.js", name, short_hash(glue.as_bytes()));
+
+        write_file(&output_path.join(&hashed_wasm_file_name), &wasm)?;
+        write_file(&output_path.join(&glue_file_name), glue.as_bytes())?;
+
+        let snippets_path = pkg_path.join("snippets");
+        if snippets_path.exists() {
+            fs_extra::dir::copy(
+                &snippets_path,
+                &output_path,
+                &fs_extra::dir::CopyOptions {
+                    overwrite: true,
+                    ..fs_extra::dir::CopyOptions::default()
+                },
+            )
+            .map_err(Error::CopySnippets)?;
+        }
+
+        Ok(BuiltVariant {
+            variant: *variant,
+            glue_file_name,
+        })
+    }
+
+    fn write_loader(&self, variants: &[BuiltVariant]) -> Result<(), Error> {
+        let variant_entries = variants
+            .iter()
+            .map(|built| {
+                format!(
+                    "  {{ name: \"{}\", simd: {}, threads: {}, url: \"./{}/{}\" }},",
+                    built.variant.name,
+                    built.variant.simd,
+                    built.variant.threads,
+                    built.variant.name,
+                    built.glue_file_name
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let loader = LOADER_TEMPLATE.replace("{{variants}}", &variant_entries);
+        let loader_path = self.config.output_path.join(LOADER_FILE_NAME);
+
+        write_file(&loader_path, loader.as_bytes())
+    }
+}
+
+impl Runner<Error> for TargetMatrix {
+    fn run(&self) -> Result<(), Error> {
+        if self.config.output_path.exists() {
+            fs::remove_dir_all(&self.config.output_path).map_err(Error::RemoveOutputDir)?;
+        }
+
+        let variants = build::run_parallel(
+            &VARIANTS,
+            self.config.jobs,
+            |variant| self.build_variant(variant),
+            |variant, result| match result {
+                Ok(_) => println!("[target matrix] The {} variant is done", variant.name),
+                Err(_) => println!("[target matrix] The {} variant failed", variant.name),
+            },
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>, Error>>()?;
+
+        self.write_loader(&variants)?;
+
+        for built in &variants {
+            println!(
+                "[target matrix] {} -> {}/{}",
+                built.variant.name, built.variant.name, built.glue_file_name
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl BuildStep for TargetMatrix {
+    fn name(&self) -> String {
+        "target-matrix".to_string()
+    }
+
+    fn inputs(&self) -> Vec<PathBuf> {
+        vec![self.config.wasm_project_path.clone()]
+    }
+
+    fn outputs(&self) -> Vec<PathBuf> {
+        vec![self.config.output_path.clone()]
+    }
+
+    fn commands(&self) -> Vec<exec::Config> {
+        VARIANTS
+            .iter()
+            .map(|variant| self.variant_command(variant))
+            .collect()
+    }
+
+    fn run(&self) -> Result<(), String> {
+        Runner::run(self).map_err(|err| err.to_string())
+    }
+}
+
+fn short_hash(data: &[u8]) -> String {
+    let digest = Sha256::digest(data);
+    data_encoding::HEXLOWER.encode(&digest)[..7].to_string()
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+    fs::write(path, data).map_err(|err| Error::WriteFile(path.to_path_buf(), err))
+}
+
+// The probe modules are the smallest valid modules using a v128 and an atomic instruction
+const LOADER_TEMPLATE: &str = r#"// Generated by poly build --target-matrix
+const variants = [
+{{variants}}
+];
+
+const simdProbe = new Uint8Array([
+  0, 97, 115, 109, 1, 0, 0, 0, 1, 5, 1, 96, 0, 1, 123, 3, 2, 1, 0, 10, 10, 1, 8, 0, 65, 0, 253, 15, 253, 98, 11,
+]);
+
+const threadsProbe = new Uint8Array([
+  0, 97, 115, 109, 1, 0, 0, 0, 1, 4, 1, 96, 0, 0, 3, 2, 1, 0, 5, 4, 1, 3, 1, 1, 10, 11, 1, 9, 0, 65, 0, 254, 16, 2, 0, 26, 11,
+]);
+
+function supportsSimd() {
+  return WebAssembly.validate(simdProbe);
+}
+
+// Shared memory is only available on cross-origin isolated pages
+function supportsThreads() {
+  return typeof SharedArrayBuffer !== "undefined" && self.crossOriginIsolated === true && WebAssembly.validate(threadsProbe);
+}
+
+export function pickVariant() {
+  const simd = supportsSimd();
+  const threads = supportsThreads();
+
+  return variants.find((variant) => (!variant.simd || simd) && (!variant.threads || threads));
+}
+
+// Imports and initializes the glue of the picked variant, returns the glue module
+export default async function load() {
+  const variant = pickVariant();
+  const module = await import(new URL(variant.url, import.meta.url).href);
+  await module.default();
+  return module;
+}
+"#;
@@ -0,0 +1,143 @@
+use crate::template_manifest::MANIFEST_FILE_NAME;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+#[derive(Debug)]
+pub enum Error {
+    OpenArchive(zip::result::ZipError),
+    ReadEntry(zip::result::ZipError),
+    WriteFile(PathBuf, io::Error),
+    SubdirNotFound(String, Vec<String>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::OpenArchive(err) => write!(f, "Failed to open template archive: {}", err),
+            Error::ReadEntry(err) => write!(f, "Failed to read template archive: {}", err),
+            Error::WriteFile(path, err) => {
+                write!(f, "Failed to extract {}: {}", path.display(), err)
+            }
+            Error::SubdirNotFound(subdir, templates) if templates.is_empty() => {
+                write!(f, "Template archive has no dir named '{}'", subdir)
+            }
+            Error::SubdirNotFound(subdir, templates) => write!(
+                f,
+                "Template archive has no dir named '{}', templates in the archive: [{}]",
+                subdir,
+                templates.join(", ")
+            ),
+        }
+    }
+}
+
+// Extracts the subdir of the archive, relative to its top-level dir when it has one
+// like the archives of git hosts, everything else in the archive is left out
+pub fn extract(bytes: Vec<u8>, subdir: &str, dest: &Path) -> Result<(), Error> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(Error::OpenArchive)?;
+    let names = entry_names(&mut archive)?;
+    let root = top_level_dir(&names);
+    let subdir_path = PathBuf::from(subdir);
+
+    let mut extracted = false;
+
+    for (index, name) in names.iter().enumerate() {
+        let rel_path = match name
+            .as_ref()
+            .and_then(|name| name.strip_prefix(&root).ok())
+            .and_then(|path| path.strip_prefix(&subdir_path).ok())
+        {
+            Some(rel_path) => rel_path,
+            None => continue,
+        };
+
+        extracted = true;
+
+        let mut file = archive.by_index(index).map_err(Error::ReadEntry)?;
+        let path = dest.join(rel_path);
+        let to_error = |err| Error::WriteFile(path.clone(), err);
+
+        if file.is_dir() {
+            fs::create_dir_all(&path).map_err(to_error)?;
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(to_error)?;
+        }
+
+        let mut out = fs::File::create(&path).map_err(to_error)?;
+        io::copy(&mut file, &mut out).map_err(to_error)?;
+
+        // Keeps scripts run by post create commands executable
+        #[cfg(unix)]
+        if let Some(mode) = file.unix_mode() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).map_err(to_error)?;
+        }
+    }
+
+    if !extracted {
+        let templates = template_dirs(&names, &root);
+        return Err(Error::SubdirNotFound(subdir.to_string(), templates));
+    }
+
+    Ok(())
+}
+
+// None for entries that would be written outside of the destination
+fn entry_names(
+    archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>,
+) -> Result<Vec<Option<PathBuf>>, Error> {
+    (0..archive.len())
+        .map(|index| {
+            let file = archive.by_index(index).map_err(Error::ReadEntry)?;
+            Ok(file.enclosed_name().map(|name| name.to_path_buf()))
+        })
+        .collect()
+}
+
+fn top_level_dir(names: &[Option<PathBuf>]) -> PathBuf {
+    let mut top_levels = names
+        .iter()
+        .flatten()
+        .filter_map(|name| name.components().next());
+
+    match top_levels.next() {
+        Some(first) if names.len() > 1 && top_levels.all(|component| component == first) => {
+            PathBuf::from(first.as_os_str())
+        }
+
+        _ => PathBuf::new(),
+    }
+}
+
+// Dirs with a template manifest, to point at the right subdir of a repo with several templates
+fn template_dirs(names: &[Option<PathBuf>], root: &Path) -> Vec<String> {
+    names
+        .iter()
+        .flatten()
+        .filter(|name| name.file_name() == Some(MANIFEST_FILE_NAME.as_ref()))
+        .filter_map(|name| name.strip_prefix(root).ok()?.parent())
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                return ".".to_string();
+            }
+
+            dir.components()
+                .filter_map(|component| match component {
+                    Component::Normal(part) => part.to_str(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .collect()
+}
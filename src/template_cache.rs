@@ -0,0 +1,300 @@
+use crate::util::progress::Progress;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+const ARCHIVE_FILE_NAME: &str = "archive.zip";
+const ETAG_FILE_NAME: &str = "etag";
+const ZIP_END_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+const ZIP_END_SIZE: usize = 22;
+const DOWNLOAD_RETRIES: u32 = 3;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Between reads, a slow download of a large template doesn't time out
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    GetUrl(Box<ureq::Error>),
+    ReadResponse(io::Error),
+    ReadCache(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::GetUrl(err) => write!(f, "Failed to download template: {}", err),
+            Error::ReadResponse(err) => write!(f, "Failed to read template download: {}", err),
+            Error::ReadCache(err) => write!(f, "Failed to read cached template: {}", err),
+        }
+    }
+}
+
+pub struct Config {
+    pub cache_dir: Option<PathBuf>,
+    pub refresh: bool,
+}
+
+pub struct TemplateCache {
+    config: Config,
+}
+
+pub struct Archive {
+    pub bytes: Vec<u8>,
+    pub etag: Option<String>,
+}
+
+impl Archive {
+    // Github stores the commit of the archive in the zip comment
+    pub fn commit(&self) -> Option<String> {
+        let comment = zip_comment(&self.bytes)?;
+        let commit = std::str::from_utf8(comment).ok()?.trim();
+
+        if commit.len() == 40 && commit.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some(commit.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+impl TemplateCache {
+    pub fn new(config: Config) -> TemplateCache {
+        TemplateCache { config }
+    }
+
+    // Revalidates the cached archive with the etag, the cached archive is used as-is when offline
+    pub fn fetch(&self, url: &str) -> Result<Archive, Error> {
+        let entry_dir = match &self.config.cache_dir {
+            Some(cache_dir) => cache_dir.join(cache_key(url)),
+            None => return download(url, None).map(Download::into_archive),
+        };
+
+        let archive_path = entry_dir.join(ARCHIVE_FILE_NAME);
+        let etag_path = entry_dir.join(ETAG_FILE_NAME);
+
+        let cached_etag = if self.config.refresh || !archive_path.exists() {
+            None
+        } else {
+            fs::read_to_string(&etag_path).ok()
+        };
+
+        match download(url, cached_etag.as_deref()) {
+            Ok(download) if download.not_modified => {
+                println!("Using cached template: {}", archive_path.display());
+                read_cached(&archive_path, download.etag)
+            }
+
+            Ok(download) => {
+                if let Err(err) = store(&entry_dir, &download) {
+                    eprintln!("Warning: Failed to cache template: {}", err);
+                }
+
+                Ok(download.into_archive())
+            }
+
+            Err(err @ (Error::GetUrl(_) | Error::ReadResponse(_)))
+                if !self.config.refresh && archive_path.exists() =>
+            {
+                eprintln!("Warning: {}", err);
+                println!("Using cached template: {}", archive_path.display());
+                read_cached(&archive_path, fs::read_to_string(&etag_path).ok())
+            }
+
+            Err(err) => Err(err),
+        }
+    }
+}
+
+struct Download {
+    bytes: Vec<u8>,
+    etag: Option<String>,
+    not_modified: bool,
+}
+
+impl Download {
+    fn into_archive(self) -> Archive {
+        Archive {
+            bytes: self.bytes,
+            etag: self.etag,
+        }
+    }
+}
+
+fn read_cached(archive_path: &PathBuf, etag: Option<String>) -> Result<Archive, Error> {
+    let bytes = fs::read(archive_path).map_err(Error::ReadCache)?;
+    Ok(Archive { bytes, etag })
+}
+
+// Retries with backoff, an interrupted download is resumed with a range request
+// when the server supports it
+fn download(url: &str, etag: Option<&str>) -> Result<Download, Error> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build();
+
+    let mut partial = Partial::default();
+    let mut attempt = 0;
+
+    loop {
+        match download_attempt(&agent, url, etag, &mut partial) {
+            Ok(download) => return Ok(download),
+
+            Err(err) if attempt < DOWNLOAD_RETRIES && is_retryable(&err) => {
+                let delay = Duration::from_millis(500 * 2u64.pow(attempt));
+                eprintln!("Template download failed, retrying in {:?}: {}", delay, err);
+                thread::sleep(delay);
+                attempt += 1;
+            }
+
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// What was received before a download was interrupted
+#[derive(Default)]
+struct Partial {
+    bytes: Vec<u8>,
+    etag: Option<String>,
+    total: Option<u64>,
+    accepts_ranges: bool,
+}
+
+impl Partial {
+    fn can_resume(&self) -> bool {
+        self.accepts_ranges && !self.bytes.is_empty()
+    }
+}
+
+fn download_attempt(
+    agent: &ureq::Agent,
+    url: &str,
+    etag: Option<&str>,
+    partial: &mut Partial,
+) -> Result<Download, Error> {
+    let mut request = agent.get(url);
+
+    if let Some(etag) = etag {
+        request = request.set("If-None-Match", etag);
+    }
+
+    if partial.can_resume() {
+        request = request.set("Range", &format!("bytes={}-", partial.bytes.len()));
+
+        // Without a validator the rest could come from a newer archive
+        if let Some(partial_etag) = &partial.etag {
+            request = request.set("If-Range", partial_etag);
+        }
+    }
+
+    let response = request.call().map_err(|err| Error::GetUrl(Box::new(err)))?;
+
+    if response.status() == 304 {
+        return Ok(Download {
+            bytes: Vec::new(),
+            etag: etag.map(|s| s.to_string()),
+            not_modified: true,
+        });
+    }
+
+    // Anything but a partial response is the whole archive
+    if response.status() != 206 {
+        let content_length = response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok());
+
+        *partial = Partial {
+            bytes: Vec::new(),
+            etag: response.header("ETag").map(|s| s.to_string()),
+            total: content_length,
+            accepts_ranges: response.header("Accept-Ranges") == Some("bytes"),
+        };
+    }
+
+    read_body(response.into_reader(), partial)?;
+
+    Ok(Download {
+        bytes: std::mem::take(&mut partial.bytes),
+        etag: partial.etag.clone(),
+        not_modified: false,
+    })
+}
+
+fn read_body(mut reader: impl Read, partial: &mut Partial) -> Result<(), Error> {
+    let mut progress = Progress::start("Downloading template", partial.total);
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    let result = loop {
+        match reader.read(&mut chunk) {
+            // A connection closed early reads as the end of the body
+            Ok(0) if partial.total > Some(partial.bytes.len() as u64) => {
+                break Err(Error::ReadResponse(io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            Ok(0) => break Ok(()),
+
+            Ok(length) => {
+                partial.bytes.extend_from_slice(&chunk[..length]);
+                progress.set(partial.bytes.len() as u64);
+            }
+
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => break Err(Error::ReadResponse(err)),
+        }
+    };
+
+    progress.finish();
+    result
+}
+
+// Connection problems and server errors, not client errors like a 404
+fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::GetUrl(err) => match err.as_ref() {
+            ureq::Error::Status(code, _) => *code >= 500 || *code == 429,
+            ureq::Error::Transport(_) => true,
+        },
+
+        Error::ReadResponse(_) => true,
+        Error::ReadCache(_) => false,
+    }
+}
+
+fn store(entry_dir: &PathBuf, download: &Download) -> Result<(), io::Error> {
+    fs::create_dir_all(entry_dir)?;
+    fs::write(entry_dir.join(ARCHIVE_FILE_NAME), &download.bytes)?;
+
+    // Without an etag the archive is only used as an offline fallback
+    match &download.etag {
+        Some(etag) => fs::write(entry_dir.join(ETAG_FILE_NAME), etag),
+        None => match fs::remove_file(entry_dir.join(ETAG_FILE_NAME)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        },
+    }
+}
+
+// The comment is at the end of the end of central directory record, which is the last
+// record of the archive
+fn zip_comment(bytes: &[u8]) -> Option<&[u8]> {
+    let end_offset = (0..=bytes.len().checked_sub(ZIP_END_SIZE)?)
+        .rev()
+        .find(|offset| bytes[*offset..].starts_with(&ZIP_END_SIGNATURE))?;
+
+    let length_bytes = &bytes[end_offset + 20..end_offset + 22];
+    let length = u16::from_le_bytes([length_bytes[0], length_bytes[1]]) as usize;
+    let comment_offset = end_offset + ZIP_END_SIZE;
+
+    bytes.get(comment_offset..comment_offset + length)
+}
+
+fn cache_key(url: &str) -> String {
+    data_encoding::HEXLOWER.encode(&Sha256::digest(url.as_bytes()))
+}
@@ -0,0 +1,83 @@
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub const LOCK_FILE_NAME: &str = "poly.lock";
+
+#[derive(Debug)]
+pub enum Error {
+    ReadFile(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Serialize(toml::ser::Error),
+    WriteFile(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadFile(path, err) if err.kind() == io::ErrorKind::NotFound => write!(
+                f,
+                "{} not found, only projects created with this version of poly can be upgraded",
+                path.display()
+            ),
+            Error::ReadFile(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::Parse(path, err) => {
+                write!(f, "Failed to parse {}: {}", path.display(), err)
+            }
+            Error::Serialize(err) => {
+                write!(f, "Failed to serialize {}: {}", LOCK_FILE_NAME, err)
+            }
+            Error::WriteFile(err) => write!(f, "Failed to write {}: {}", LOCK_FILE_NAME, err),
+        }
+    }
+}
+
+// Where the project scaffold came from, the archive behind a branch url changes over time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateLock {
+    pub template: LockedTemplate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedTemplate {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    // Only known for archives that carry it, like github archives
+    pub commit: Option<String>,
+    pub etag: Option<String>,
+    // sha256:<hex> of the archive
+    pub checksum: String,
+}
+
+impl TemplateLock {
+    pub fn read(project_dir: &Path) -> Result<TemplateLock, Error> {
+        let path = project_dir.join(LOCK_FILE_NAME);
+        let content =
+            fs::read_to_string(&path).map_err(|err| Error::ReadFile(path.clone(), err))?;
+
+        toml::from_str(&content).map_err(|err| Error::Parse(path, err))
+    }
+
+    pub fn write(&self, project_dir: &Path) -> Result<(), Error> {
+        let content = toml::to_string(self).map_err(Error::Serialize)?;
+        let content = format!(
+            "# Generated by poly, records the template the project was created from\n{}",
+            content
+        );
+
+        fs::write(project_dir.join(LOCK_FILE_NAME), content).map_err(Error::WriteFile)
+    }
+}
+
+pub fn checksum(bytes: &[u8]) -> String {
+    format!(
+        "sha256:{}",
+        data_encoding::HEXLOWER.encode(&Sha256::digest(bytes))
+    )
+}
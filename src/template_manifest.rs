@@ -0,0 +1,290 @@
+use crate::exec;
+use convert_case::{Case, Casing};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const MANIFEST_FILE_NAME: &str = "template.toml";
+const IF_MARKER: &str = "poly:if";
+const ENDIF_MARKER: &str = "poly:endif";
+
+#[derive(Debug)]
+pub enum Error {
+    ReadManifest(io::Error),
+    ParseManifest(toml::de::Error),
+    SkipPattern(globset::Error),
+    UnknownVar(String, Vec<String>),
+    CssNotSupported,
+    UnknownCss(String, Vec<String>),
+    UnterminatedCondition(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadManifest(err) => write!(f, "Failed to read {}: {}", MANIFEST_FILE_NAME, err),
+            Error::ParseManifest(err) => {
+                write!(f, "Failed to parse {}: {}", MANIFEST_FILE_NAME, err)
+            }
+            Error::SkipPattern(err) => write!(f, "Invalid skip pattern: {}", err),
+            Error::UnknownVar(name, available) => write!(
+                f,
+                "Unknown template var '{}', available vars: [{}]",
+                name,
+                available.join(", ")
+            ),
+            Error::CssNotSupported => write!(
+                f,
+                "The template has no css variants, use --template minimal for a project without css tooling"
+            ),
+            Error::UnknownCss(name, available) => write!(
+                f,
+                "The template doesn't support --css {}, supported: [{}]",
+                name,
+                available.join(", ")
+            ),
+            Error::UnterminatedCondition(line) => {
+                write!(f, "Missing {} for the {} on line {}", ENDIF_MARKER, IF_MARKER, line)
+            }
+        }
+    }
+}
+
+// The template.toml in the root of a template, templates without one follow the
+// counter-tailwind conventions
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TemplateManifest {
+    // Replaced with the project name in file contents and dir names, in each casing of its
+    // words, i.e. `my_app` also replaces `my-app`, `MyApp`, `myApp` and `MY_APP`
+    pub name_placeholder: String,
+    // Page copied by `poly add page`
+    pub default_page: String,
+    // Globs relative to the template root of files that are copied without replacing placeholders
+    pub skip: Vec<String>,
+    // Printed after the project is created, placeholders are replaced here too
+    pub instructions: Option<String>,
+    // Commands run in the new project dir after confirmation, like `npm install`,
+    // args are split on whitespace without quoting, use `{ run = "...", shell = true }`
+    // to run a command through the shell for pipes, `&&` and env vars, `interactive = true`
+    // for commands that prompt and `on_failure = "warn"` or `"ignore"` for optional ones
+    pub post_create: Vec<exec::CommandEntry>,
+    // Extra placeholders keyed by var name, set with `poly new --var <name>=<value>`
+    pub placeholders: BTreeMap<String, Placeholder>,
+    // Css frameworks the template can be created with, chosen with `poly new --css`
+    pub css: Option<CssConfig>,
+}
+
+// Besides removing paths, lines between `poly:if css=<name>[,<name>]` and `poly:endif`
+// marker lines are only kept for the chosen css framework
+#[derive(Debug, Clone, Deserialize)]
+pub struct CssConfig {
+    pub default: String,
+    // Paths relative to the template root that are removed for a css framework
+    #[serde(default)]
+    pub remove: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Placeholder {
+    pub placeholder: String,
+    // Used when the var isn't set, the placeholder is left as is without a default
+    pub default: Option<String>,
+}
+
+impl Default for TemplateManifest {
+    fn default() -> Self {
+        Self {
+            name_placeholder: "myapp".to_string(),
+            default_page: "home_page".to_string(),
+            skip: vec![],
+            instructions: None,
+            post_create: vec![],
+            placeholders: BTreeMap::new(),
+            css: None,
+        }
+    }
+}
+
+impl TemplateManifest {
+    pub fn from_dir(template_dir: &Path) -> Result<TemplateManifest, Error> {
+        let path = template_dir.join(MANIFEST_FILE_NAME);
+
+        if !path.exists() {
+            return Ok(TemplateManifest::default());
+        }
+
+        let content = fs::read_to_string(&path).map_err(Error::ReadManifest)?;
+        toml::from_str(&content).map_err(Error::ParseManifest)
+    }
+
+    pub fn skip_set(&self) -> Result<GlobSet, Error> {
+        let mut builder = GlobSetBuilder::new();
+
+        for pattern in &self.skip {
+            builder.add(Glob::new(pattern).map_err(Error::SkipPattern)?);
+        }
+
+        builder.build().map_err(Error::SkipPattern)
+    }
+
+    // Placeholder -> value pairs, the longest placeholders first so that
+    // a placeholder containing another one is replaced as a whole
+    pub fn replacements(
+        &self,
+        project_name: &str,
+        vars: &[(String, String)],
+    ) -> Result<Vec<(String, String)>, Error> {
+        if let Some((name, _)) = vars
+            .iter()
+            .find(|(name, _)| !self.placeholders.contains_key(name))
+        {
+            let available = self.placeholders.keys().cloned().collect();
+            return Err(Error::UnknownVar(name.clone(), available));
+        }
+
+        let mut replacements: Vec<(String, String)> = self
+            .placeholders
+            .iter()
+            .filter_map(|(name, placeholder)| {
+                let value = vars
+                    .iter()
+                    .rev()
+                    .find(|(var_name, _)| var_name == name)
+                    .map(|(_, value)| value.clone())
+                    .or_else(|| placeholder.default.clone())?;
+
+                Some((placeholder.placeholder.clone(), value))
+            })
+            .collect();
+
+        replacements.extend(name_variants(&self.name_placeholder, project_name));
+        replacements.sort_by_key(|(placeholder, _)| std::cmp::Reverse(placeholder.len()));
+
+        Ok(replacements)
+    }
+
+    // The requested css framework, or the template default when none is requested
+    pub fn css_choice(&self, requested: Option<&str>) -> Result<Option<String>, Error> {
+        match (&self.css, requested) {
+            (None, None) => Ok(None),
+            (None, Some(_)) => Err(Error::CssNotSupported),
+            (Some(css), None) => Ok(Some(css.default.clone())),
+
+            (Some(css), Some(name)) => {
+                let mut available: Vec<String> = css.remove.keys().cloned().collect();
+                if !available.contains(&css.default) {
+                    available.push(css.default.clone());
+                }
+
+                if available.iter().any(|css_name| css_name == name) {
+                    Ok(Some(name.to_string()))
+                } else {
+                    Err(Error::UnknownCss(name.to_string(), available))
+                }
+            }
+        }
+    }
+
+    pub fn css_removals(&self, css_name: &str) -> Vec<String> {
+        self.css
+            .as_ref()
+            .and_then(|css| css.remove.get(css_name))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+// Keeps the lines of poly:if sections whose condition matches one of the choices,
+// i.e. `css=tailwind`, the marker lines themselves are removed
+pub fn apply_conditions(content: &str, choices: &[(String, String)]) -> Result<String, Error> {
+    if !content.contains(IF_MARKER) {
+        return Ok(content.to_string());
+    }
+
+    let mut lines: Vec<&str> = Vec::new();
+    let mut open_condition: Option<(usize, bool)> = None;
+
+    for (index, line) in content.lines().enumerate() {
+        if let Some((_, condition)) = line.split_once(IF_MARKER) {
+            open_condition = Some((index + 1, condition_matches(condition.trim(), choices)));
+        } else if line.contains(ENDIF_MARKER) {
+            open_condition = None;
+        } else if open_condition.map(|(_, keep)| keep).unwrap_or(true) {
+            lines.push(line);
+        }
+    }
+
+    if let Some((line, _)) = open_condition {
+        return Err(Error::UnterminatedCondition(line));
+    }
+
+    let mut new_content = lines.join("\n");
+    if content.ends_with('\n') {
+        new_content.push('\n');
+    }
+
+    Ok(new_content)
+}
+
+// A condition on a choice that wasn't made never matches
+fn condition_matches(condition: &str, choices: &[(String, String)]) -> bool {
+    let condition = condition.split_whitespace().next().unwrap_or_default();
+
+    match condition.split_once('=') {
+        Some((key, values)) => choices
+            .iter()
+            .any(|(name, value)| name == key && values.split(',').any(|v| v == value)),
+
+        None => false,
+    }
+}
+
+const NAME_CASES: [Case; 5] = [
+    Case::Snake,
+    Case::Kebab,
+    Case::Pascal,
+    Case::Camel,
+    Case::UpperSnake,
+];
+
+// Same casing of the placeholder and the project name, single word placeholders
+// like `myapp` only have the snake, pascal and upper variants
+fn name_variants(placeholder: &str, project_name: &str) -> Vec<(String, String)> {
+    // Lowercase placeholders are used as is, converting would split digits into words
+    let placeholder = if placeholder
+        .chars()
+        .any(|c| c.is_ascii_uppercase() || c == '-')
+    {
+        placeholder.to_case(Case::Snake)
+    } else {
+        placeholder.to_string()
+    };
+    let mut variants: Vec<(String, String)> = Vec::new();
+
+    for case in NAME_CASES {
+        let placeholder_variant = placeholder.from_case(Case::Snake).to_case(case);
+
+        if !variants
+            .iter()
+            .any(|(existing, _)| *existing == placeholder_variant)
+        {
+            let name_variant = project_name.from_case(Case::Snake).to_case(case);
+            variants.push((placeholder_variant, name_variant));
+        }
+    }
+
+    variants
+}
+
+pub fn replace_all(content: &str, replacements: &[(String, String)]) -> String {
+    replacements
+        .iter()
+        .fold(content.to_string(), |content, (placeholder, value)| {
+            content.replace(placeholder, value)
+        })
+}
@@ -0,0 +1,89 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Committed with the project so that everyone on the team can run `poly upgrade`
+pub const RECORD_DIR_NAME: &str = ".poly-template";
+const RECORD_FILE_NAME: &str = "template.json";
+const BASE_DIR_NAME: &str = "base";
+
+#[derive(Debug)]
+pub enum Error {
+    ReadRecord(PathBuf, io::Error),
+    ParseRecord(serde_json::Error),
+    SerializeRecord(serde_json::Error),
+    WriteRecord(io::Error),
+    CopyBase(fs_extra::error::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadRecord(path, err) if err.kind() == io::ErrorKind::NotFound => write!(
+                f,
+                "{} not found, only projects created with this version of poly can be upgraded",
+                path.display()
+            ),
+            Error::ReadRecord(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::ParseRecord(err) => write!(f, "Failed to parse template record: {}", err),
+            Error::SerializeRecord(err) => {
+                write!(f, "Failed to serialize template record: {}", err)
+            }
+            Error::WriteRecord(err) => write!(f, "Failed to write template record: {}", err),
+            Error::CopyBase(err) => write!(f, "Failed to copy template base: {}", err),
+        }
+    }
+}
+
+// Which template the project was rendered from and with which choices, the archive
+// itself is recorded in poly.lock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateRecord {
+    pub name: String,
+    pub vars: Vec<(String, String)>,
+    pub css: Option<String>,
+    pub poly_version: String,
+}
+
+impl TemplateRecord {
+    pub fn read(project_dir: &Path) -> Result<TemplateRecord, Error> {
+        let path = record_dir(project_dir).join(RECORD_FILE_NAME);
+        let content = fs::read_to_string(&path).map_err(|err| Error::ReadRecord(path, err))?;
+
+        serde_json::from_str(&content).map_err(Error::ParseRecord)
+    }
+
+    // The rendered template is kept as the base of the three-way merge on upgrade
+    pub fn write(&self, project_dir: &Path, rendered_dir: &Path) -> Result<(), Error> {
+        let dir = record_dir(project_dir);
+        let base_dir = dir.join(BASE_DIR_NAME);
+
+        if base_dir.exists() {
+            fs::remove_dir_all(&base_dir).map_err(Error::WriteRecord)?;
+        }
+
+        fs::create_dir_all(&base_dir).map_err(Error::WriteRecord)?;
+
+        let options = fs_extra::dir::CopyOptions {
+            content_only: true,
+            ..fs_extra::dir::CopyOptions::new()
+        };
+
+        fs_extra::dir::copy(rendered_dir, &base_dir, &options).map_err(Error::CopyBase)?;
+
+        let content = serde_json::to_string_pretty(self).map_err(Error::SerializeRecord)?;
+        fs::write(dir.join(RECORD_FILE_NAME), content).map_err(Error::WriteRecord)
+    }
+}
+
+pub fn base_dir(project_dir: &Path) -> PathBuf {
+    record_dir(project_dir).join(BASE_DIR_NAME)
+}
+
+fn record_dir(project_dir: &Path) -> PathBuf {
+    project_dir.join(RECORD_DIR_NAME)
+}
@@ -0,0 +1,116 @@
+use rcgen::CertificateParams;
+use rcgen::SanType;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::BufReader;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum Error {
+    CreateCacheDir(io::Error),
+    GenerateCert(rcgen::RcgenError),
+    WriteFile(PathBuf, io::Error),
+    ReadFile(PathBuf, io::Error),
+    NoCertificate(PathBuf),
+    NoPrivateKey(PathBuf),
+    InvalidCert(rustls::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::CreateCacheDir(err) => write!(f, "Failed to create cert dir: {}", err),
+            Error::GenerateCert(err) => write!(f, "Failed to generate certificate: {}", err),
+            Error::WriteFile(path, err) => write!(f, "Failed to write {}: {}", path.display(), err),
+            Error::ReadFile(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::NoCertificate(path) => write!(f, "No certificate found in {}", path.display()),
+            Error::NoPrivateKey(path) => write!(f, "No private key found in {}", path.display()),
+            Error::InvalidCert(err) => write!(f, "Invalid certificate: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CertFiles {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+// The certificate is cached so the browser exception only has to be accepted once
+pub fn self_signed(cache_dir: &Path) -> Result<CertFiles, Error> {
+    let files = CertFiles {
+        cert_path: cache_dir.join("cert.pem"),
+        key_path: cache_dir.join("key.pem"),
+    };
+
+    if files.cert_path.exists() && files.key_path.exists() {
+        return Ok(files);
+    }
+
+    fs::create_dir_all(cache_dir).map_err(Error::CreateCacheDir)?;
+
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+    params
+        .subject_alt_names
+        .push(SanType::IpAddress(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+
+    let cert = rcgen::Certificate::from_params(params).map_err(Error::GenerateCert)?;
+    let cert_pem = cert.serialize_pem().map_err(Error::GenerateCert)?;
+    let key_pem = cert.serialize_private_key_pem();
+
+    fs::write(&files.cert_path, cert_pem)
+        .map_err(|err| Error::WriteFile(files.cert_path.clone(), err))?;
+    fs::write(&files.key_path, key_pem)
+        .map_err(|err| Error::WriteFile(files.key_path.clone(), err))?;
+
+    println!(
+        "Generated self-signed certificate: {}",
+        files.cert_path.display()
+    );
+
+    Ok(files)
+}
+
+pub fn server_config(files: &CertFiles) -> Result<Arc<rustls::ServerConfig>, Error> {
+    let certs: Vec<rustls::Certificate> = read_pem_items(&files.cert_path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            rustls_pemfile::Item::X509Certificate(der) => Some(rustls::Certificate(der)),
+            _ => None,
+        })
+        .collect();
+
+    if certs.is_empty() {
+        return Err(Error::NoCertificate(files.cert_path.clone()));
+    }
+
+    let key = read_pem_items(&files.key_path)?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(der)
+            | rustls_pemfile::Item::RSAKey(der)
+            | rustls_pemfile::Item::ECKey(der) => Some(rustls::PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| Error::NoPrivateKey(files.key_path.clone()))?;
+
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(Error::InvalidCert)?;
+
+    Ok(Arc::new(config))
+}
+
+fn read_pem_items(path: &Path) -> Result<Vec<rustls_pemfile::Item>, Error> {
+    let file = fs::File::open(path).map_err(|err| Error::ReadFile(path.to_path_buf(), err))?;
+    let mut reader = BufReader::new(file);
+
+    rustls_pemfile::read_all(&mut reader).map_err(|err| Error::ReadFile(path.to_path_buf(), err))
+}
@@ -0,0 +1,78 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadConfig(PathBuf, io::Error),
+    ParseConfig(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::ReadConfig(path, err) => {
+                write!(f, "Failed to read {}: {}", path.display(), err)
+            }
+
+            Error::ParseConfig(path, err) => {
+                write!(f, "Failed to parse {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+// Per user settings shared by all projects, i.e. ~/.config/poly/config.toml
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UserConfig {
+    pub templates: BTreeMap<String, UserTemplate>,
+    // Always run template post create commands in the sandbox, like `poly new --sandbox`
+    pub sandbox_post_create: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserTemplate {
+    // Same format as the --template url: <url>[#<ref>[:<subdir>]]
+    pub url: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl UserConfig {
+    // A missing config file is not an error
+    pub fn load() -> Result<UserConfig, Error> {
+        match config_path() {
+            Some(path) if path.exists() => UserConfig::from_file(path),
+            _ => Ok(UserConfig::default()),
+        }
+    }
+
+    fn from_file(path: PathBuf) -> Result<UserConfig, Error> {
+        let content =
+            fs::read_to_string(&path).map_err(|err| Error::ReadConfig(path.clone(), err))?;
+
+        toml::from_str(&content).map_err(|err| Error::ParseConfig(path, err))
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    let config_dir = user_dir("XDG_CONFIG_HOME", ".config")?;
+    Some(config_dir.join("poly").join("config.toml"))
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache_dir = user_dir("XDG_CACHE_HOME", ".cache")?;
+    Some(cache_dir.join("poly"))
+}
+
+fn user_dir(xdg_var: &str, home_fallback: &str) -> Option<PathBuf> {
+    env::var_os(xdg_var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(home_fallback)))
+}
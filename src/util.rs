@@ -1 +1,6 @@
+pub mod compress;
 pub mod file_util;
+pub mod generated_files;
+pub mod progress;
+pub mod spinner;
+pub mod time_util;
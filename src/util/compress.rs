@@ -0,0 +1,23 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io;
+use std::io::Write;
+
+// Largest window brotli supports without the large window extension browsers don't implement
+const BROTLI_WINDOW_SIZE: u32 = 22;
+
+pub fn gzip(content: &[u8], level: u32) -> Result<Vec<u8>, io::Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level));
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+pub fn brotli(content: &[u8], quality: u32) -> Result<Vec<u8>, io::Error> {
+    let mut compressed = Vec::new();
+    let mut encoder =
+        brotli::CompressorWriter::new(&mut compressed, 4096, quality, BROTLI_WINDOW_SIZE);
+    encoder.write_all(content)?;
+    drop(encoder);
+
+    Ok(compressed)
+}
@@ -1,9 +1,12 @@
+use crate::util::generated_files;
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::Read;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
+use walkdir::WalkDir;
 
 pub struct FileData {
     pub content: String,
@@ -34,6 +37,48 @@ pub fn write(path: &PathBuf, file_data: FileData) -> Result<(), io::Error> {
     }
 
     fs::rename(&tmp_path, path)?;
+    generated_files::record(path, file_data.content.as_bytes());
 
     Ok(())
 }
+
+// Copies `src_dir` into `dest_parent` like `cp -r`, skipping files whose content is
+// already up to date so their mtime (and serve's etag) stays the same. Returns the copied files
+pub fn sync_dir(src_dir: &Path, dest_parent: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let dir_name = src_dir.file_name().unwrap_or_default();
+    sync_dir_contents(src_dir, &dest_parent.join(dir_name))
+}
+
+// Same as sync_dir, but copies the contents of `src_dir` into `dest_dir`
+pub fn sync_dir_contents(src_dir: &Path, dest_dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut copied = Vec::new();
+
+    for entry in WalkDir::new(src_dir) {
+        let entry = entry?;
+        let rel_path = entry.path().strip_prefix(src_dir).unwrap_or(entry.path());
+        let dest_path = dest_dir.join(rel_path);
+
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&dest_path)?;
+        } else if !is_same_content(entry.path(), &dest_path)? {
+            fs::copy(entry.path(), &dest_path)?;
+            copied.push(dest_path);
+        }
+    }
+
+    Ok(copied)
+}
+
+fn is_same_content(a: &Path, b: &Path) -> Result<bool, io::Error> {
+    let b_metadata = match fs::metadata(b) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    if fs::metadata(a)?.len() != b_metadata.len() {
+        return Ok(false);
+    }
+
+    Ok(fs::read(a)? == fs::read(b)?)
+}
@@ -0,0 +1,41 @@
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Content hash of every file poly has written to, keyed by canonical path
+static REGISTRY: Mutex<Option<HashMap<PathBuf, Vec<u8>>>> = Mutex::new(None);
+
+pub fn record(path: &Path, content: &[u8]) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry
+            .get_or_insert_with(HashMap::new)
+            .insert(canonical_path(path), Sha256::digest(content).to_vec());
+    }
+}
+
+// True if the file still has the content poly wrote, i.e. the change came from poly itself
+pub fn is_generated(path: &Path) -> bool {
+    let expected_hash = match REGISTRY.lock() {
+        Ok(registry) => registry
+            .as_ref()
+            .and_then(|registry| registry.get(&canonical_path(path)).cloned()),
+
+        Err(_) => None,
+    };
+
+    match expected_hash {
+        Some(expected_hash) => fs::read(path)
+            .map(|content| Sha256::digest(content).to_vec() == expected_hash)
+            .unwrap_or(false),
+
+        None => false,
+    }
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
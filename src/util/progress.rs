@@ -0,0 +1,81 @@
+use crate::size_report::format_size;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+const BAR_WIDTH: usize = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+// Progress of a transfer on stderr, a bar when the total is known and only the
+// transferred size otherwise, nothing but the message when stderr isn't a terminal
+pub struct Progress {
+    message: String,
+    total: Option<u64>,
+    done: u64,
+    is_terminal: bool,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    pub fn start(message: &str, total: Option<u64>) -> Progress {
+        let is_terminal = io::stderr().is_terminal();
+
+        if !is_terminal {
+            eprintln!("{}", message);
+        }
+
+        Progress {
+            message: message.to_string(),
+            total: total.filter(|total| *total > 0),
+            done: 0,
+            is_terminal,
+            last_draw: None,
+        }
+    }
+
+    pub fn set(&mut self, done: u64) {
+        self.done = done;
+
+        let should_draw = self
+            .last_draw
+            .map(|last_draw| last_draw.elapsed() >= REDRAW_INTERVAL)
+            .unwrap_or(true);
+
+        if self.is_terminal && should_draw {
+            self.draw();
+            self.last_draw = Some(Instant::now());
+        }
+    }
+
+    pub fn finish(self) {
+        if self.is_terminal {
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+
+    fn draw(&self) {
+        match self.total {
+            Some(total) => {
+                let ratio = (self.done as f64 / total as f64).min(1.0);
+                let filled = (ratio * BAR_WIDTH as f64) as usize;
+
+                eprint!(
+                    "\r\x1b[2K{} [{}{}] {:>3}% {}/{}",
+                    self.message,
+                    "=".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    (ratio * 100.0) as u64,
+                    format_size(self.done),
+                    format_size(total)
+                );
+            }
+
+            None => eprint!("\r\x1b[2K{} {}", self.message, format_size(self.done)),
+        }
+
+        let _ = io::stderr().flush();
+    }
+}
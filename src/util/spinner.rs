@@ -0,0 +1,61 @@
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const FRAMES: [&str; 4] = ["|", "/", "-", "\\"];
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+// Animates on stderr while a long running step runs, only the message is
+// printed when stderr isn't a terminal
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(message: &str) -> Spinner {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        if !io::stderr().is_terminal() {
+            eprintln!("{}", message);
+            return Spinner { stop, handle: None };
+        }
+
+        let thread_stop = stop.clone();
+        let message = message.to_string();
+
+        let handle = thread::spawn(move || {
+            for frame in FRAMES.iter().cycle() {
+                // Sleep first so output printed when the step starts comes before the spinner
+                thread::sleep(FRAME_INTERVAL);
+
+                if thread_stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                eprint!("\r\x1b[2K{} {}", frame, message);
+                let _ = io::stderr().flush();
+            }
+
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        });
+
+        Spinner {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    pub fn from_system_time(time: SystemTime) -> DateTime {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+
+        DateTime::from_unix_secs(secs)
+    }
+
+    pub fn from_unix_secs(secs: i64) -> DateTime {
+        let days = secs.div_euclid(86400);
+        let secs_of_day = secs.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u32,
+            minute: (secs_of_day % 3600 / 60) as u32,
+            second: (secs_of_day % 60) as u32,
+        }
+    }
+
+    // Parses the IMF-fixdate format, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+    pub fn from_http_date(s: &str) -> Option<DateTime> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+
+        match parts[..] {
+            [_weekday, day, month, year, time, "GMT"] => {
+                let month_index = MONTHS.iter().position(|name| *name == month)?;
+                let time_parts: Vec<u32> = time
+                    .split(':')
+                    .map(|part| part.parse().ok())
+                    .collect::<Option<Vec<u32>>>()?;
+
+                match time_parts[..] {
+                    [hour, minute, second] => Some(DateTime {
+                        year: year.parse().ok()?,
+                        month: month_index as u32 + 1,
+                        day: day.parse().ok()?,
+                        hour,
+                        minute,
+                        second,
+                    }),
+
+                    _ => None,
+                }
+            }
+
+            _ => None,
+        }
+    }
+
+    pub fn unix_secs(&self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        days * 86400 + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+    }
+
+    pub fn to_http_date(&self) -> String {
+        let days = self.unix_secs().div_euclid(86400);
+        let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+        let month = MONTHS[(self.month - 1) as usize];
+
+        format!(
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            weekday, self.day, month, self.year, self.hour, self.minute, self.second
+        )
+    }
+
+    // Common log format, e.g. `10/Oct/2000:13:55:36 +0000`
+    pub fn to_clf_date(&self) -> String {
+        format!(
+            "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+            self.day,
+            MONTHS[(self.month - 1) as usize],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+
+    pub fn to_iso_string(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+// Converts days since the unix epoch to a (year, month, day) triple
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+// Converts a (year, month, day) triple to days since the unix epoch
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
@@ -1,6 +1,9 @@
 use crate::backlog_builder;
 use crate::backlog_builder::BacklogBuilder;
 use crate::backlog_builder::ChangeType;
+use crate::plugin::PluginStep;
+use crate::project_info::ProjectInfo;
+use crate::util::generated_files;
 use gitignored::Gitignore;
 use notify::event::CreateKind;
 use notify::event::DataChange;
@@ -19,14 +22,16 @@ use std::path::StripPrefixError;
 #[derive(Debug, Clone)]
 pub struct Config {
     pub current_dir: PathBuf,
+    pub dist_path: PathBuf,
     pub gitignore: Option<String>,
     pub builder: BacklogBuilder,
 }
 
 impl Config {
-    pub fn new(current_dir: &Path, builder: BacklogBuilder) -> Self {
+    pub fn new(current_dir: &Path, project_info: &ProjectInfo, builder: BacklogBuilder) -> Self {
         Self {
             current_dir: current_dir.to_path_buf(),
+            dist_path: project_info.dist_path.clone(),
             gitignore: read_to_string(".gitignore").ok(),
             builder,
         }
@@ -40,6 +45,8 @@ pub enum Error {
     EventFilePath(Event),
     RelativePath(StripPrefixError),
     IgnoredFileType(PathBuf),
+    GeneratedFile(PathBuf),
+    NonFrontendFile(PathBuf),
 }
 
 pub fn watch(config: Config) {
@@ -73,6 +80,12 @@ pub fn _watch(mut config: Config) -> Result<(), Error> {
 fn on_event(config: &mut Config, event_result: Result<Event, notify::Error>) -> Result<(), Error> {
     let event = event_result.map_err(|err| Error::Notify(err))?;
     let file_path = filepath_from_event(&event)?;
+
+    // Files written by poly itself would otherwise trigger a new build
+    if generated_files::is_generated(&file_path) {
+        return Err(Error::GeneratedFile(file_path));
+    }
+
     let rel_path = file_path
         .strip_prefix(&config.current_dir)
         .map_err(|err| Error::RelativePath(err))?;
@@ -85,7 +98,7 @@ fn on_event(config: &mut Config, event_result: Result<Event, notify::Error>) ->
         rel_path.to_string_lossy().to_string()
     );
 
-    if let Err(err) = config.builder.run(change_type) {
+    if let Err(err) = config.builder.run(change_type, rel_path.to_path_buf()) {
         backlog_builder::handle_error(err)
     }
 
@@ -109,6 +122,15 @@ fn handle_error(err: Error) {
         }
 
         Error::IgnoredFileType(_) => (),
+
+        Error::GeneratedFile(_) => (),
+
+        Error::NonFrontendFile(path) => {
+            println!(
+                "Ignoring change in {}, it's not part of the frontend",
+                path.display()
+            );
+        }
     }
 }
 
@@ -117,6 +139,12 @@ fn classify_file(config: &Config, path: &Path) -> Result<ChangeType, Error> {
 
     if is_ignored(config, path) {
         Err(Error::IgnoredFileType(path.to_path_buf()))
+    } else if is_plugin_output(config, path) {
+        Err(Error::GeneratedFile(path.to_path_buf()))
+    } else if let Some(plugin) = find_plugin_by_input(config, path) {
+        Ok(ChangeType::Plugin(plugin.name().to_string()))
+    } else if extension == "rs" && !is_frontend_rust_file(config, path) {
+        Err(Error::NonFrontendFile(path.to_path_buf()))
     } else if extension == "rs" {
         Ok(ChangeType::Rust)
     } else if extension == "ts" {
@@ -128,8 +156,33 @@ fn classify_file(config: &Config, path: &Path) -> Result<ChangeType, Error> {
     }
 }
 
+fn find_plugin_by_input<'a>(config: &'a Config, path: &Path) -> Option<&'a PluginStep> {
+    config
+        .builder
+        .plugins()
+        .iter()
+        .find(|plugin| plugin.matches_input(path))
+}
+
+fn is_frontend_rust_file(config: &Config, path: &Path) -> bool {
+    config
+        .builder
+        .change_analyzer()
+        .is_frontend_rust_file(&config.current_dir.join(path))
+}
+
+fn is_plugin_output(config: &Config, path: &Path) -> bool {
+    config
+        .builder
+        .plugins()
+        .iter()
+        .any(|plugin| plugin.matches_output(path))
+}
+
 fn is_ignored(config: &Config, path: &Path) -> bool {
-    is_ignored_by_component(path) || is_ignored_by_git(config, path)
+    is_ignored_by_component(path)
+        || is_ignored_by_git(config, path)
+        || config.current_dir.join(path).starts_with(&config.dist_path)
 }
 
 fn is_ignored_by_component(path: &Path) -> bool {
@@ -1,23 +1,44 @@
+use crate::build::BuildStep;
 use crate::build::Env;
+use crate::build::Profile;
 use crate::build::Runner;
 use crate::exec;
+use crate::poly_config::PolyConfig;
+use crate::util::file_util;
 use crate::ProjectInfo;
 use std::fmt;
+use std::io;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub env: Env,
+    pub profile: Profile,
     pub web_project_path: PathBuf,
+    pub dist_path: PathBuf,
+    pub npm_env: Vec<(String, String)>,
+    pub npm_timeout: Option<Duration>,
 }
 
 impl Config {
-    pub fn from_project_info(env: &Env, project_info: &ProjectInfo) -> Self {
+    pub fn from_project_info(
+        profile: &Profile,
+        project_info: &ProjectInfo,
+        poly_config: &PolyConfig,
+    ) -> Self {
         Self {
-            env: env.clone(),
+            profile: profile.clone(),
             web_project_path: project_info.web_project_path.clone(),
+            dist_path: project_info.dist_path.clone(),
+            npm_env: poly_config.step_env("web", &profile.env),
+            npm_timeout: poly_config.step_timeout("web"),
         }
     }
+
+    // Configured variables come last so they can override the POLY_* ones
+    fn npm_env(&self) -> Vec<(String, String)> {
+        [self.profile.script_env(), self.npm_env.clone()].concat()
+    }
 }
 
 #[derive(Debug)]
@@ -25,6 +46,7 @@ pub enum Error {
     NpmInstall(exec::Error),
     NpmBuildDev(exec::Error),
     NpmBuildRelease(exec::Error),
+    CopyPublicDir(io::Error),
 }
 
 impl fmt::Display for Error {
@@ -33,6 +55,9 @@ impl fmt::Display for Error {
             Error::NpmInstall(err) => write!(f, "'npm install' failed: {}", err),
             Error::NpmBuildDev(err) => write!(f, "'npm run build-dev' failed: {}", err),
             Error::NpmBuildRelease(err) => write!(f, "'npm run build-release' failed: {}", err),
+            Error::CopyPublicDir(err) => {
+                write!(f, "Failed to copy the public dir to dist: {}", err)
+            }
         }
     }
 }
@@ -50,12 +75,7 @@ impl WebBuilder {
     fn build_dev(&self) -> Result<(), Error> {
         self.npm_install()?;
 
-        exec::run(&exec::Config {
-            work_dir: self.config.web_project_path.clone(),
-            cmd: "npm".into(),
-            args: exec::to_args(&["run", "build-dev"]),
-        })
-        .map_err(Error::NpmBuildDev)?;
+        exec::run_streamed(&self.npm_build_command()).map_err(Error::NpmBuildDev)?;
 
         Ok(())
     }
@@ -63,23 +83,54 @@ impl WebBuilder {
     fn build_release(&self) -> Result<(), Error> {
         self.npm_install()?;
 
-        exec::run(&exec::Config {
+        exec::run_streamed(&self.npm_build_command()).map_err(Error::NpmBuildRelease)?;
+
+        Ok(())
+    }
+
+    fn npm_build_command(&self) -> exec::Config {
+        match &self.config.profile.env {
+            Env::Dev => self.npm_command(&["run", "build-dev"]),
+            Env::Release => self.npm_command(&["run", "build-release"]),
+        }
+    }
+
+    fn npm_command(&self, args: &[&str]) -> exec::Config {
+        exec::Config {
             work_dir: self.config.web_project_path.clone(),
             cmd: "npm".into(),
-            args: exec::to_args(&["run", "build-release"]),
-        })
-        .map_err(Error::NpmBuildRelease)?;
+            args: exec::to_args(args),
+            env: self.config.npm_env(),
+            env_mode: exec::EnvMode::Inherit,
+            timeout: self.config.npm_timeout,
+        }
+    }
+
+    // Web projects without a package.json are plain files, the public dir is copied to
+    // dist as is so that only a rust toolchain and wasm-pack are needed
+    fn uses_npm(&self) -> bool {
+        self.config.web_project_path.join("package.json").exists()
+    }
+
+    fn copy_public_dir(&self) -> Result<(), Error> {
+        let public_dir = self.config.web_project_path.join("public");
+
+        if !public_dir.exists() {
+            return Ok(());
+        }
+
+        let copied = file_util::sync_dir_contents(&public_dir, &self.config.dist_path)
+            .map_err(Error::CopyPublicDir)?;
+
+        for path in copied {
+            println!("Copied {}", path.display());
+        }
 
         Ok(())
     }
 
     fn npm_install(&self) -> Result<(), Error> {
-        exec::run(&exec::Config {
-            work_dir: self.config.web_project_path.clone(),
-            cmd: "npm".into(),
-            args: exec::to_args(&["install"]),
-        })
-        .map_err(Error::NpmInstall)?;
+        exec::run_streamed(&self.npm_command(&["install"])).map_err(Error::NpmInstall)?;
 
         Ok(())
     }
@@ -87,9 +138,40 @@ impl WebBuilder {
 
 impl Runner<Error> for WebBuilder {
     fn run(&self) -> Result<(), Error> {
-        match &self.config.env {
+        if !self.uses_npm() {
+            return self.copy_public_dir();
+        }
+
+        match &self.config.profile.env {
             Env::Dev => self.build_dev(),
             Env::Release => self.build_release(),
         }
     }
 }
+
+impl BuildStep for WebBuilder {
+    fn name(&self) -> String {
+        "web".to_string()
+    }
+
+    fn inputs(&self) -> Vec<PathBuf> {
+        vec![self.config.web_project_path.clone()]
+    }
+
+    fn outputs(&self) -> Vec<PathBuf> {
+        vec![self.config.dist_path.clone()]
+    }
+
+    // None when the public dir is only copied
+    fn commands(&self) -> Vec<exec::Config> {
+        if self.uses_npm() {
+            vec![self.npm_command(&["install"]), self.npm_build_command()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn run(&self) -> Result<(), String> {
+        Runner::run(self).map_err(|err| err.to_string())
+    }
+}
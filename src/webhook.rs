@@ -0,0 +1,170 @@
+use crate::build::Profile;
+use crate::poly_config::WebhookConfig;
+use crate::ProjectInfo;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub project_name: String,
+    pub profile: Profile,
+    pub dist_path: PathBuf,
+    pub webhooks: Vec<WebhookConfig>,
+    pub build_log_id: Option<String>,
+}
+
+impl Config {
+    pub fn from_project_info(
+        profile: &Profile,
+        project_info: &ProjectInfo,
+        webhooks: &[WebhookConfig],
+        build_log_id: Option<&str>,
+    ) -> Self {
+        Self {
+            project_name: project_info.project_name.clone(),
+            profile: profile.clone(),
+            dist_path: project_info.dist_path.clone(),
+            webhooks: webhooks.to_vec(),
+            build_log_id: build_log_id.map(|id| id.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BuildEvent {
+    Started,
+    Succeeded { duration: Duration },
+    Failed { duration: Duration, error: String },
+}
+
+impl BuildEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            BuildEvent::Started => "started",
+            BuildEvent::Succeeded { .. } => "succeeded",
+            BuildEvent::Failed { .. } => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Payload {
+    event: String,
+    project: String,
+    env: String,
+    duration_ms: Option<u128>,
+    dist_size_bytes: Option<u64>,
+    error: Option<String>,
+    build_log_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TextPayload {
+    text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    config: Config,
+}
+
+impl Notifier {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    // Delivery failures are reported but never fail the build
+    pub fn notify(&self, event: &BuildEvent) {
+        let webhooks: Vec<&WebhookConfig> = self
+            .config
+            .webhooks
+            .iter()
+            .filter(|webhook| {
+                webhook.events.is_empty() || webhook.events.iter().any(|name| name == event.name())
+            })
+            .collect();
+
+        if webhooks.is_empty() {
+            return;
+        }
+
+        let payload = self.payload(event);
+
+        for webhook in webhooks {
+            let body = match &webhook.template {
+                Some(template) => serde_json::to_string(&TextPayload {
+                    text: render_template(template, &payload),
+                }),
+
+                None => serde_json::to_string(&payload),
+            };
+
+            let result = body.map_err(|err| err.to_string()).and_then(|body| {
+                ureq::post(&webhook.url)
+                    .timeout(Duration::from_secs(10))
+                    .set("Content-Type", "application/json")
+                    .send_string(&body)
+                    .map_err(|err| err.to_string())
+            });
+
+            if let Err(err) = result {
+                eprintln!("Warning: Webhook {} failed: {}", webhook.url, err);
+            }
+        }
+    }
+
+    fn payload(&self, event: &BuildEvent) -> Payload {
+        let (duration, error) = match event {
+            BuildEvent::Started => (None, None),
+            BuildEvent::Succeeded { duration } => (Some(duration), None),
+            BuildEvent::Failed { duration, error } => (Some(duration), Some(error.clone())),
+        };
+
+        let dist_size_bytes = match event {
+            BuildEvent::Succeeded { .. } => Some(self.dist_size()),
+            _ => None,
+        };
+
+        Payload {
+            event: event.name().to_string(),
+            project: self.config.project_name.clone(),
+            env: self.config.profile.name(),
+            duration_ms: duration.map(|duration| duration.as_millis()),
+            dist_size_bytes,
+            error,
+            build_log_id: self.config.build_log_id.clone(),
+        }
+    }
+
+    fn dist_size(&self) -> u64 {
+        WalkDir::new(&self.config.dist_path)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+}
+
+fn render_template(template: &str, payload: &Payload) -> String {
+    let duration = payload
+        .duration_ms
+        .map(|ms| format!("{:.1}s", ms as f64 / 1000.0))
+        .unwrap_or_default();
+
+    let dist_size = payload
+        .dist_size_bytes
+        .map(|bytes| format!("{:.1} KiB", bytes as f64 / 1024.0))
+        .unwrap_or_default();
+
+    template
+        .replace("{event}", &payload.event)
+        .replace("{project}", &payload.project)
+        .replace("{env}", &payload.env)
+        .replace("{duration}", &duration)
+        .replace("{dist_size}", &dist_size)
+        .replace("{error}", payload.error.as_deref().unwrap_or_default())
+}
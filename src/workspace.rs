@@ -0,0 +1,80 @@
+use crate::exec;
+use crate::poly_config::WorkspaceConfig;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Task {
+    Check,
+    Test,
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Task::Check => write!(f, "check"),
+            Task::Test => write!(f, "test"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Cargo(Task, exec::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::Cargo(task, err) => write!(f, "cargo {} failed: {}", task, err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub current_dir: PathBuf,
+    pub non_frontend_members: Vec<String>,
+}
+
+impl Config {
+    pub fn new(current_dir: &Path, workspace_config: &WorkspaceConfig) -> Self {
+        Self {
+            current_dir: current_dir.to_path_buf(),
+            non_frontend_members: workspace_config.exclude.clone(),
+        }
+    }
+}
+
+// Unlike the builds, check and test cover every workspace member so a backend
+// sharing the core crate is verified together with the frontend
+pub fn run(config: &Config, task: Task) -> Result<(), Error> {
+    if !config.non_frontend_members.is_empty() {
+        println!(
+            "Including non-frontend members: {}",
+            config.non_frontend_members.join(", ")
+        );
+    }
+
+    let args = match task {
+        Task::Check => {
+            exec::to_args(&["check", "--workspace", "--all-targets", "--color", "always"])
+        }
+        Task::Test => exec::to_args(&["test", "--workspace", "--color", "always"]),
+    };
+
+    let stdout = exec::run(&exec::Config {
+        work_dir: config.current_dir.clone(),
+        cmd: "cargo".into(),
+        args,
+        env: Vec::new(),
+        env_mode: exec::EnvMode::Inherit,
+        timeout: None,
+    })
+    .map_err(|err| Error::Cargo(task, err))?;
+
+    print!("{}", stdout);
+
+    Ok(())
+}